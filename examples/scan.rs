@@ -0,0 +1,158 @@
+//! Scans for advertisements through a software controller, with the same host code the watch
+//! runs. Controllers that support extended scanning also find extended advertisements, on the LE
+//! 1M and LE Coded PHYs. Point it at any controller serving H4 over TCP, such as a Bumble
+//! controller on the `tcp-server:_:9000` transport:
+//!
+//!     cargo +stable run-host --example scan -- 127.0.0.1:9000
+
+use std::net::TcpStream;
+
+use wable::devices::ble::{
+    command::{
+        le_set_event_mask::LeSetEventMask,
+        le_set_extended_scan_enable::LeSetExtendedScanEnable,
+        le_set_extended_scan_parameters::{
+            LeSetExtendedScanParameters, PhyScanParameters, ScanningPhys,
+        },
+        le_set_scan_enable::LeSetScanEnable,
+        le_set_scan_parameters::LeSetScanParameters,
+        reset::Reset,
+        set_event_mask::SetEventMask,
+        CommandParameters,
+    },
+    event::{
+        le_advertising_report::LeAdvertisingReport,
+        le_extended_advertising_report::{
+            ExtendedAdvertisingReassembler, LeExtendedAdvertisingReport,
+        },
+    },
+    router::EventRouter,
+    std_io::{StdDelay, StdTransport},
+    Ble, PollBehavior, QueueSlot,
+};
+
+fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:9000".into());
+    let transport = StdTransport::connect_tcp(&addr).expect("couldn't connect to the controller");
+
+    let (mut ble, qslot) = Ble::new(transport, StdDelay);
+
+    let ((), qslot) = ble
+        .run_checked(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+
+    // Commands the controller doesn't support fail with BleError::Unsupported from here on.
+    let (info, qslot) = ble.probe_controller(qslot, PollBehavior::Strict).unwrap();
+    println!("controller {:?} at {:?}", info.version, info.address);
+    let extended = info.supports(LeSetExtendedScanParameters::OPCODE);
+
+    // Only the events that are subscribed to or polled for.
+    let masks = ble
+        .router()
+        .event_masks()
+        .with::<LeAdvertisingReport>()
+        .with::<LeExtendedAdvertisingReport>();
+
+    let ((), qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            SetEventMask { mask: masks.events },
+        )
+        .unwrap();
+
+    let ((), qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            LeSetEventMask {
+                le_event_mask: masks.le_events,
+            },
+        )
+        .unwrap();
+
+    if extended {
+        scan_extended(&mut ble, qslot);
+    }
+
+    let ((), qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            LeSetScanParameters {
+                le_scan_type: 0x01,
+                le_scan_interval: 0x0100,
+                le_scan_window: 0x0010,
+                own_address_type: 0x00,
+                scanning_filter_policy: 0x00,
+            },
+        )
+        .unwrap();
+
+    let ((), _qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            LeSetScanEnable {
+                le_scan_enable: 0x01,
+                filter_duplicates: 0x00,
+            },
+        )
+        .unwrap();
+
+    loop {
+        if let Some(event) = ble.filter_poll::<LeAdvertisingReport>().unwrap() {
+            for item in event.items() {
+                println!("{:?}", item.unwrap());
+            }
+        }
+    }
+}
+
+fn scan_extended(ble: &mut Ble<StdTransport<TcpStream>, StdDelay>, qslot: QueueSlot) -> ! {
+    let phy = PhyScanParameters {
+        scan_type: 0x01,
+        scan_interval: 0x0100,
+        scan_window: 0x0010,
+    };
+    let ((), qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            LeSetExtendedScanParameters {
+                own_address_type: 0x00,
+                scanning_filter_policy: 0x00,
+                phys: ScanningPhys {
+                    le_1m: Some(phy),
+                    le_coded: Some(phy),
+                },
+            },
+        )
+        .unwrap();
+
+    let ((), _qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            LeSetExtendedScanEnable {
+                enable: 0x01,
+                filter_duplicates: 0x00,
+                duration: 0x0000,
+                period: 0x0000,
+            },
+        )
+        .unwrap();
+
+    let mut reassembler = ExtendedAdvertisingReassembler::new();
+    loop {
+        if let Some(event) = ble.filter_poll::<LeExtendedAdvertisingReport>().unwrap() {
+            for item in event.items() {
+                if let Some(advertisement) = reassembler.push(&item.unwrap()) {
+                    println!("{advertisement:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wable::devices::ble::{
+    data::MaybeDecodeRef, event::le_advertising_report::LeAdvertisingReport,
+};
+use wable_fuzz::visit_report;
+
+// The input is the LE Advertising Report parameters after the subevent code.
+fuzz_target!(|data: &[u8]| {
+    let mut parameters = [0x02; 255];
+    let Some(rest) = parameters.get_mut(1..1 + data.len()) else {
+        return;
+    };
+    rest.copy_from_slice(data);
+
+    let mut parameters = &parameters[..1 + data.len()];
+    if let Ok(Some(report)) = LeAdvertisingReport::maybe_decode_ref(&mut parameters) {
+        visit_report(report);
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wable::devices::ble::{
+    data::Buffer,
+    event::{EncodedEvent, EventCode},
+};
+use wable_fuzz::decode_every_event;
+
+// The first byte is the event code and the rest are the parameters.
+fuzz_target!(|data: &[u8]| {
+    let [code, parameters @ ..] = data else {
+        return;
+    };
+    let Ok(parameters) = Buffer::try_from(parameters) else {
+        return;
+    };
+
+    decode_every_event(&EncodedEvent {
+        code: EventCode(*code),
+        parameters,
+    });
+});
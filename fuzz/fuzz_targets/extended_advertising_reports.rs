@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wable::devices::ble::{
+    data::MaybeDecodeRef,
+    event::le_extended_advertising_report::{
+        ExtendedAdvertisingReassembler, LeExtendedAdvertisingReport,
+    },
+};
+use wable_fuzz::visit_extended_report;
+
+// The input is a sequence of LE Extended Advertising Report parameters after the subevent code,
+// each preceded by its length, so that data is reassembled across reports.
+fuzz_target!(|data: &[u8]| {
+    let mut reassembler = ExtendedAdvertisingReassembler::new();
+    let mut data = data;
+
+    while let [len, rest @ ..] = data {
+        let len = (*len as usize).min(rest.len());
+        let (report, rest) = rest.split_at(len);
+        data = rest;
+
+        let mut parameters = [0x0D; 255];
+        let Some(dest) = parameters.get_mut(1..1 + report.len()) else {
+            continue;
+        };
+        dest.copy_from_slice(report);
+
+        let mut parameters = &parameters[..1 + report.len()];
+        if let Ok(Some(report)) = LeExtendedAdvertisingReport::maybe_decode_ref(&mut parameters) {
+            visit_extended_report(report, &mut reassembler);
+        }
+    }
+});
@@ -0,0 +1,57 @@
+#![no_main]
+
+use core::convert::Infallible;
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::{ErrorType, Read, Write};
+use libfuzzer_sys::fuzz_target;
+use wable::devices::ble::{Ble, BleError};
+use wable_fuzz::decode_every_event;
+
+/// A controller that sends the fuzzer's bytes and accepts any command.
+struct Controller<'a> {
+    rx: &'a [u8],
+}
+
+impl ErrorType for Controller<'_> {
+    type Error = Infallible;
+}
+
+impl Read for Controller<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let len = buf.len().min(self.rx.len());
+        buf[..len].copy_from_slice(&self.rx[..len]);
+        self.rx = &self.rx[len..];
+        Ok(len)
+    }
+}
+
+impl Write for Controller<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (mut ble, _qslot) = Ble::new(Controller { rx: data }, NoDelay);
+
+    // Every call consumes input, so this ends once the controller has nothing left to send.
+    loop {
+        match ble.try_poll_raw() {
+            Ok(event) => decode_every_event(&event),
+            Err(BleError::WouldBlock) => break,
+            Err(_) => (),
+        }
+    }
+    while ble.take_acl_packet().is_some() {}
+});
@@ -0,0 +1,119 @@
+//! Fuzz targets for the BLE host's receive path, to show that nothing a misbehaving controller
+//! sends can make it panic. Run them with `cargo +nightly fuzz run <target>` from this directory.
+
+use wable::devices::ble::{
+    command::{
+        le_clear_advertising_sets::LeClearAdvertisingSets, le_read_buffer_size::LeReadBufferSize,
+        le_read_buffer_size_v2::LeReadBufferSizeV2,
+        le_read_local_supported_features::LeReadLocalSupportedFeatures,
+        le_read_maximum_advertising_data_length::LeReadMaximumAdvertisingDataLength,
+        le_read_number_of_supported_advertising_sets::LeReadNumberOfSupportedAdvertisingSets,
+        le_remove_advertising_set::LeRemoveAdvertisingSet,
+        le_set_advertising_data::LeSetAdvertisingData,
+        le_set_advertising_enable::LeSetAdvertisingEnable,
+        le_set_advertising_parameters::LeSetAdvertisingParameters,
+        le_set_extended_advertising_data::LeSetExtendedAdvertisingData,
+        le_set_extended_advertising_enable::LeSetExtendedAdvertisingEnable,
+        le_set_extended_advertising_parameters::LeSetExtendedAdvertisingParameters,
+        le_set_extended_scan_enable::LeSetExtendedScanEnable,
+        le_set_extended_scan_parameters::LeSetExtendedScanParameters,
+        le_set_extended_scan_response_data::LeSetExtendedScanResponseData,
+        le_set_scan_enable::LeSetScanEnable, le_set_scan_parameters::LeSetScanParameters,
+        le_set_scan_response_data::LeSetScanResponseData, read_bd_addr::ReadBdAddr,
+        read_buffer_size::ReadBufferSize,
+        read_local_supported_commands::ReadLocalSupportedCommands,
+        read_local_supported_features::ReadLocalSupportedFeatures,
+        read_local_version_information::ReadLocalVersionInformation, reset::Reset,
+        set_event_mask::SetEventMask, AnyCommand,
+    },
+    event::{
+        command_complete::CommandComplete,
+        command_status::CommandStatus,
+        disconnection_complete::DisconnectionComplete,
+        hardware_error::HardwareError,
+        le_advertising_report::{LeAdvertisingReport, OwnedLeAdvertisingReport},
+        le_advertising_set_terminated::LeAdvertisingSetTerminated,
+        le_connection_complete::LeConnectionComplete,
+        le_extended_advertising_report::{
+            ExtendedAdvertisingReassembler, LeExtendedAdvertisingReport,
+            OwnedLeExtendedAdvertisingReport,
+        },
+        number_of_completed_packets::NumberOfCompletedPackets,
+        EncodedEvent,
+    },
+};
+
+/// Decodes `event` as every event type the host knows, looking into whatever decodes.
+pub fn decode_every_event(event: &EncodedEvent) {
+    let _ = event.decode::<CommandComplete<AnyCommand>>();
+    let _ = event.decode::<CommandComplete<Reset>>();
+    let _ = event.decode::<CommandComplete<SetEventMask>>();
+    let _ = event.decode::<CommandComplete<LeSetScanParameters>>();
+    let _ = event.decode::<CommandComplete<LeSetScanEnable>>();
+    let _ = event.decode::<CommandComplete<ReadLocalVersionInformation>>();
+    let _ = event.decode::<CommandComplete<ReadBdAddr>>();
+    let _ = event.decode::<CommandComplete<ReadLocalSupportedCommands>>();
+    let _ = event.decode::<CommandComplete<ReadLocalSupportedFeatures>>();
+    let _ = event.decode::<CommandComplete<LeReadLocalSupportedFeatures>>();
+    let _ = event.decode::<CommandComplete<ReadBufferSize>>();
+    let _ = event.decode::<CommandComplete<LeReadBufferSize>>();
+    let _ = event.decode::<CommandComplete<LeReadBufferSizeV2>>();
+    let _ = event.decode::<CommandComplete<LeSetAdvertisingParameters>>();
+    let _ = event.decode::<CommandComplete<LeSetAdvertisingData>>();
+    let _ = event.decode::<CommandComplete<LeSetScanResponseData>>();
+    let _ = event.decode::<CommandComplete<LeSetAdvertisingEnable>>();
+    let _ = event.decode::<CommandComplete<LeSetExtendedAdvertisingParameters>>();
+    let _ = event.decode::<CommandComplete<LeSetExtendedAdvertisingData>>();
+    let _ = event.decode::<CommandComplete<LeSetExtendedScanResponseData>>();
+    let _ = event.decode::<CommandComplete<LeSetExtendedAdvertisingEnable>>();
+    let _ = event.decode::<CommandComplete<LeReadMaximumAdvertisingDataLength>>();
+    let _ = event.decode::<CommandComplete<LeReadNumberOfSupportedAdvertisingSets>>();
+    let _ = event.decode::<CommandComplete<LeRemoveAdvertisingSet>>();
+    let _ = event.decode::<CommandComplete<LeClearAdvertisingSets>>();
+    let _ = event.decode::<CommandComplete<LeSetExtendedScanParameters>>();
+    let _ = event.decode::<CommandComplete<LeSetExtendedScanEnable>>();
+    let _ = event.decode::<CommandStatus<AnyCommand>>();
+    let _ = event.decode::<DisconnectionComplete>();
+    let _ = event.decode::<HardwareError>();
+    let _ = event.decode::<LeConnectionComplete>();
+    let _ = event.decode::<LeAdvertisingSetTerminated>();
+
+    if let Ok(Some(completed)) = event.decode::<NumberOfCompletedPackets>() {
+        completed.entries().for_each(drop);
+    }
+    if let Ok(Some(report)) = event.decode::<OwnedLeAdvertisingReport>() {
+        visit_report(report.report());
+    }
+    if let Ok(Some(report)) = event.decode::<LeAdvertisingReport>() {
+        visit_report(report);
+    }
+    if let Ok(Some(report)) = event.decode::<OwnedLeExtendedAdvertisingReport>() {
+        visit_extended_report(report.report(), &mut ExtendedAdvertisingReassembler::new());
+    }
+    if let Ok(Some(report)) = event.decode::<LeExtendedAdvertisingReport>() {
+        visit_extended_report(report, &mut ExtendedAdvertisingReassembler::new());
+    }
+}
+
+/// Goes through every item of `report`, and copies the report and its items.
+pub fn visit_report(report: LeAdvertisingReport<'_>) {
+    for item in report.items().flatten() {
+        let _ = item.into_owned();
+    }
+    let _ = report.into_owned();
+}
+
+/// Goes through every item of `report`, putting their data together with `reassembler`, and
+/// copies the report.
+pub fn visit_extended_report(
+    report: LeExtendedAdvertisingReport<'_>,
+    reassembler: &mut ExtendedAdvertisingReassembler,
+) {
+    for item in report.items().flatten() {
+        let _ = item.data_status();
+        if let Some(advertisement) = reassembler.push(&item) {
+            let _ = advertisement.data_status();
+        }
+    }
+    let _ = report.into_owned();
+}
@@ -1,15 +1,46 @@
 use core::{fmt::Debug, marker::PhantomData};
 
-use command::{AnyCommand, CommandParameters, EncodedCommand, HasOpcode};
-use data::{Buffer, DecodeError, Encode, EncoderFull};
+use acl::{AclBufferSize, L2capFrame};
+use command::{
+    le_read_buffer_size::LeReadBufferSize, le_read_buffer_size_v2::LeReadBufferSizeV2,
+    le_read_local_supported_features::LeReadLocalSupportedFeatures, read_bd_addr::ReadBdAddr,
+    read_buffer_size::ReadBufferSize, read_local_supported_commands::ReadLocalSupportedCommands,
+    read_local_supported_features::ReadLocalSupportedFeatures,
+    read_local_version_information::ReadLocalVersionInformation, reset::Reset, CommandParameters,
+    EncodedCommand, HasOpcode,
+};
+use controller::ControllerInfo;
+use data::{
+    opcode::Opcode,
+    status_code::{StatusCode, StatusError},
+    DecodeError, Encode, EncoderFull,
+};
+use embedded_hal::delay::DelayNs;
 use embedded_io::{Read, ReadExactError, Write};
-use esp_hal::delay::Delay;
-use esp_hal::prelude::*;
-use event::{command_complete::{CommandComplete, CommandWithCompleteEvent}, command_status::CommandStatus, EncodedEvent, EventCode, EventParameters};
-
+use event::{
+    command_complete::{CommandComplete, CommandWithCompleteEvent, ReturnParametersWithStatus},
+    command_status::{CommandStatus, CommandWithCompletionEvent, CompletionEvent},
+    EncodedEvent, EventParameters,
+};
+use host::HostState;
+use packet::{AclPacket, FramingError, FramingStats, HciPacket, PacketType};
+use router::EventRouter;
+
+pub mod acl;
+pub mod advertiser;
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod capture;
 pub mod command;
+pub mod controller;
 pub mod data;
 pub mod event;
+pub mod h5;
+mod host;
+pub mod packet;
+pub mod router;
+#[cfg(feature = "std")]
+pub mod std_io;
 
 mod private {
     pub trait Internal {}
@@ -17,21 +48,77 @@ mod private {
 
 use private::Internal;
 
-pub struct Ble<H> {
-    /// The most recent num_hci_command_packets value received from the controller, decremented
-    /// whenever a command is sent. If this field is 0, no commands can be sent.
-    num_hci_command_packets: usize,
-    queued_command: Option<EncodedCommand>,
-    queued_event: Option<EncodedEvent>,
+/// The maximum number of commands that can be waiting for the controller to accept them, and so
+/// the maximum number of [QueueSlot]s a host can hand out.
+pub const COMMAND_QUEUE_LEN: usize = 4;
+
+/// The maximum number of received ACL data packets the host holds on to until they are taken.
+pub const ACL_QUEUE_LEN: usize = 4;
+
+/// The maximum number of connections the host can send and reassemble ACL data on at once.
+pub const MAX_CONNECTIONS: usize = 2;
+
+pub struct Ble<H, D, R = ()> {
+    state: HostState,
     hci: H,
-    delay: Delay,
+    /// Used to wait between polls when the controller has nothing for us.
+    delay: D,
+    /// Receives the events that the host isn't waiting for.
+    router: R,
 }
 
+/// Errors that have a `qslot` give back the queue slot of the command that failed. When any other
+/// error ends a method that took a [QueueSlot], the host keeps the slot; such errors are fatal, so
+/// start over with [Ble::reset_controller] and take the slot back with [Ble::reclaim_slot].
 #[derive(Debug)]
 pub enum BleError<E> {
     WouldBlock,
     UnexpectedEof,
-    UnexpectedEvent,
+    /// With [PollBehavior::Strict], an event other than the awaited one arrived. The event is left
+    /// for the next poll, and the command is given up on as after a [BleError::Timeout]. `qslot`
+    /// is the queue slot the command was queued with.
+    UnexpectedEvent {
+        qslot: QueueSlot,
+    },
+    /// The host's command queue is full, so the command wasn't queued. `qslot` is the queue slot
+    /// it would have been queued with.
+    QueueFull {
+        qslot: QueueSlot,
+    },
+    /// The controller didn't complete the command with `opcode` in time. `qslot` is the queue slot
+    /// the command was queued with.
+    Timeout {
+        opcode: Opcode,
+        qslot: QueueSlot,
+    },
+    /// The controller completed the command with `opcode`, but with an error `status`. `qslot` is
+    /// the queue slot the command was queued with.
+    Command {
+        opcode: Opcode,
+        status: StatusError,
+        qslot: QueueSlot,
+    },
+    /// ACL data was sent before the host found out about the controller's buffers with
+    /// [Ble::read_acl_buffer_size].
+    AclBufferSizeUnknown,
+    /// The controller doesn't support the command with `opcode`, according to the
+    /// [ControllerInfo] from [Ble::probe_controller], so it wasn't sent. `qslot` is the queue slot
+    /// it would have been queued with.
+    Unsupported {
+        opcode: Opcode,
+        qslot: QueueSlot,
+    },
+    /// While waiting for the completion of the command with `opcode`, a completion event arrived
+    /// that reports on something else. The event is left for the next poll. `qslot` is the queue
+    /// slot the command was queued with.
+    CompletionMismatch {
+        opcode: Opcode,
+        qslot: QueueSlot,
+    },
+    /// The byte stream from the controller got out of sync with packet boundaries. Polling again
+    /// resynchronizes, but packets may have been lost, so it is best to start over with
+    /// [Ble::reset_controller].
+    Framing(FramingError),
     Encode(EncoderFull),
     Decode(DecodeError),
     Io(E),
@@ -64,6 +151,12 @@ impl<E> From<EncoderFull> for BleError<E> {
     }
 }
 
+impl<E> From<FramingError> for BleError<E> {
+    fn from(value: FramingError) -> Self {
+        Self::Framing(value)
+    }
+}
+
 impl<E> From<DecodeError> for BleError<E> {
     fn from(value: DecodeError) -> Self {
         Self::Decode(value)
@@ -81,39 +174,125 @@ pub struct QueueSlot;
 pub struct QueueLock<C> {
     _phantom: PhantomData<C>,
     qslot: QueueSlot,
+    /// Tells the host which queued command to give up on when waiting for it times out.
+    id: host::CommandId,
 }
 
 pub trait CommandReceiptIndicator<C>: Internal {}
 
+/// Proof that the controller has been reset, so that no commands are in flight anymore.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ControllerReset;
+
+impl Internal for ControllerReset {}
+impl<C> CommandReceiptIndicator<C> for ControllerReset {}
+
 impl<C> QueueLock<C> {
     pub fn release_with<E: CommandReceiptIndicator<C>>(self, _event: &E) -> QueueSlot {
         self.qslot
     }
 }
 
+/// A monotonic time source in milliseconds, such as `esp_wifi::current_millis`.
+pub trait Clock {
+    fn now_millis(&mut self) -> u64;
+}
+
+impl<F: FnMut() -> u64> Clock for F {
+    fn now_millis(&mut self) -> u64 {
+        self()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PollBehavior {
     Strict,
+    /// Hands events other than the awaited one over to the [EventRouter].
     Filter,
 }
 
-impl<E, H> Ble<H>
-where
-    H: Read<Error = E> + Write<Error = E>,
-    E: embedded_io::Error,
-{
-    pub fn new(hci: H, delay: Delay) -> (Self, QueueSlot) {
+impl<H, D> Ble<H, D> {
+    pub fn new(hci: H, delay: D) -> (Self, QueueSlot) {
+        let (ble, [qslot]) = Self::with_slots(hci, delay);
+        (ble, qslot)
+    }
+
+    /// Like [Ble::new], but hands out `N` queue slots so that up to `N` commands can be in flight
+    /// at once.
+    pub fn with_slots<const N: usize>(hci: H, delay: D) -> (Self, [QueueSlot; N]) {
+        Ble::with_router(hci, delay, ())
+    }
+}
+
+impl<H, D, R> Ble<H, D, R> {
+    /// Creates a host that delivers the events it isn't waiting for to `router`, handing out `N`
+    /// queue slots.
+    pub fn with_router<const N: usize>(hci: H, delay: D, router: R) -> (Self, [QueueSlot; N]) {
+        const { assert!(N <= COMMAND_QUEUE_LEN, "too many queue slots") };
+
         (
             Self {
-                num_hci_command_packets: 1,
-                queued_command: None,
-                queued_event: None,
+                state: HostState::new(),
                 hci,
                 delay,
+                router,
             },
-            QueueSlot,
+            [(); N].map(|()| QueueSlot),
         )
     }
 
+    /// Returns the underlying HCI transport.
+    pub fn hci(&self) -> &H {
+        &self.hci
+    }
+
+    pub fn router(&self) -> &R {
+        &self.router
+    }
+
+    pub fn router_mut(&mut self) -> &mut R {
+        &mut self.router
+    }
+
+    /// The number of events that were neither awaited by the host nor taken by the router.
+    pub fn unrouted_events(&self) -> usize {
+        self.state.unrouted_events
+    }
+
+    /// The number of received packets other than events that the host had no use or no room for.
+    pub fn dropped_packets(&self) -> usize {
+        self.state.dropped_packets
+    }
+
+    pub fn framing_stats(&self) -> FramingStats {
+        self.state.framing_stats
+    }
+
+    /// What the controller supports, once [Ble::probe_controller] has found out.
+    pub fn controller_info(&self) -> Option<&ControllerInfo> {
+        self.state.controller_info.as_ref()
+    }
+
+    /// The controller's buffers for ACL data, once [Ble::read_acl_buffer_size] has found out.
+    pub fn acl_buffer_size(&self) -> Option<AclBufferSize> {
+        self.state.acl.buffer_size()
+    }
+
+    /// Takes the oldest ACL data packet received while polling for events. Packets taken this way
+    /// aren't reassembled by [Ble::poll_acl].
+    pub fn take_acl_packet(&mut self) -> Option<AclPacket> {
+        self.state.acl_packets.pop_front()
+    }
+}
+
+impl<E, H, D, R> Ble<H, D, R>
+where
+    H: Read<Error = E> + Write<Error = E>,
+    E: embedded_io::Error,
+    D: DelayNs,
+    R: EventRouter,
+{
     pub fn run_until_complete<C: CommandParameters + CommandWithCompleteEvent>(
         &mut self,
         qslot: QueueSlot,
@@ -122,15 +301,290 @@ where
     ) -> Result<(C::ReturnParameters, QueueSlot), BleError<E>> {
         let qlock = self.queue(qslot, command)?;
         
-        loop {
-            if let Some(complete) = self.maybe_poll::<CommandComplete<C>>()? {
+        match self.wait_for::<CommandComplete<C>>(poll_behavior) {
+            Ok(Some(complete)) => {
                 let slot = qlock.release_with(&complete);
-                return Ok((complete.return_parameters, slot))
-            } else {
-                match poll_behavior {
-                    PollBehavior::Filter => self.queued_event = None,
-                    PollBehavior::Strict => return Err(BleError::UnexpectedEvent),
+                Ok((complete.return_parameters, slot))
+            }
+            Ok(None) => {
+                self.state.abandon(qlock.id);
+                Err(BleError::UnexpectedEvent { qslot: qlock.qslot })
+            }
+            Err(error) => Err(self.state.keep_slot(qlock.qslot, error)),
+        }
+    }
+
+    /// Like [Ble::run_until_complete], but checks the status at the start of the return
+    /// parameters, returning [BleError::Command] if the command failed and the rest of the
+    /// return parameters if it succeeded.
+    pub fn run_checked<C>(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        command: C,
+    ) -> Result<(<C::ReturnParameters as ReturnParametersWithStatus>::Rest, QueueSlot), BleError<E>>
+    where
+        C: CommandParameters + CommandWithCompleteEvent,
+        C::ReturnParameters: ReturnParametersWithStatus,
+    {
+        let (return_parameters, qslot) = self.run_until_complete(qslot, poll_behavior, command)?;
+        match return_parameters.into_result() {
+            Ok(rest) => Ok((rest, qslot)),
+            Err(status) => Err(BleError::Command {
+                opcode: C::OPCODE,
+                status,
+                qslot,
+            }),
+        }
+    }
+
+    /// Issues a command that is acknowledged with a Command Status, then waits for the event that
+    /// reports its outcome, e.g. [LeCreateConnection](command::le_create_connection::LeCreateConnection)
+    /// followed by [LeConnectionComplete](event::le_connection_complete::LeConnectionComplete).
+    ///
+    /// A failure in either the Command Status or the completion is returned as
+    /// [BleError::Command]. `poll_behavior` applies until the Command Status; after that, the
+    /// command no longer holds a place in the queue and the wait can be long, so unrelated events
+    /// are always routed.
+    pub fn run_until_completion<C>(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        command: C,
+    ) -> Result<(C::Completion, QueueSlot), BleError<E>>
+    where
+        C: CommandParameters + CommandWithCompletionEvent,
+    {
+        let encoded = match EncodedCommand::encode_ref(&command) {
+            Ok(encoded) => encoded,
+            Err(error) => return Err(self.state.keep_slot(qslot, error.into())),
+        };
+        let qlock = self.queue_encoded::<C>(qslot, encoded)?;
+
+        let status = match self.wait_for::<CommandStatus<C>>(poll_behavior) {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                self.state.abandon(qlock.id);
+                return Err(BleError::UnexpectedEvent { qslot: qlock.qslot });
+            }
+            Err(error) => return Err(self.state.keep_slot(qlock.qslot, error)),
+        };
+        let qslot = qlock.release_with(&status);
+        if let Some(status) = status.status.error() {
+            return Err(BleError::Command {
+                opcode: C::OPCODE,
+                status,
+                qslot,
+            });
+        }
+
+        match self.wait_for_completion(&command) {
+            Ok(Some(completion)) => match completion.status().error() {
+                Some(status) => Err(BleError::Command {
+                    opcode: C::OPCODE,
+                    status,
+                    qslot,
+                }),
+                None => Ok((completion, qslot)),
+            },
+            Ok(None) => Err(BleError::CompletionMismatch {
+                opcode: C::OPCODE,
+                qslot,
+            }),
+            Err(error) => Err(self.state.keep_slot(qslot, error)),
+        }
+    }
+
+    /// Like [Ble::run_until_complete], but gives up if the command hasn't completed within
+    /// `timeout_ms` milliseconds as measured by `clock`.
+    ///
+    /// On timeout, the command is dropped if it was still waiting to be sent, or its command credit
+    /// is given back if it was sent, and `qslot` is returned in [BleError::Timeout].
+    pub fn run_until_complete_timeout<C, K>(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        command: C,
+        clock: &mut K,
+        timeout_ms: u64,
+    ) -> Result<(C::ReturnParameters, QueueSlot), BleError<E>>
+    where
+        C: CommandParameters + CommandWithCompleteEvent,
+        K: Clock,
+    {
+        let deadline = clock.now_millis().saturating_add(timeout_ms);
+        let qlock = self.queue(qslot, command)?;
+
+        loop {
+            let encoded = match self.poll_raw_before(clock, deadline) {
+                Ok(Some(encoded)) => encoded,
+                Ok(None) => {
+                    self.state.abandon(qlock.id);
+                    return Err(BleError::Timeout {
+                        opcode: C::OPCODE,
+                        qslot: qlock.qslot,
+                    });
+                }
+                Err(error) => return Err(self.state.keep_slot(qlock.qslot, error)),
+            };
+
+            match encoded.decode::<CommandComplete<C>>() {
+                Ok(Some(complete)) => {
+                    let slot = qlock.release_with(&complete);
+                    return Ok((complete.return_parameters, slot));
                 }
+                Ok(None) => {}
+                Err(error) => return Err(self.state.keep_slot(qlock.qslot, error.into())),
+            }
+
+            match poll_behavior {
+                PollBehavior::Filter => {
+                    if let Err(error) = self.state.route(&mut self.router, &encoded) {
+                        return Err(self.state.keep_slot(qlock.qslot, error.into()));
+                    }
+                }
+                PollBehavior::Strict => {
+                    self.state.requeue(encoded);
+                    self.state.abandon(qlock.id);
+                    return Err(BleError::UnexpectedEvent { qslot: qlock.qslot });
+                }
+            }
+        }
+    }
+
+    /// Starts over after a transport error: forgets all queued commands, events and data, and
+    /// resets the controller. The controller needs to be configured again afterwards.
+    ///
+    /// The returned [ControllerReset] releases the [QueueLock]s of all commands that were in
+    /// flight.
+    pub fn reset_controller(&mut self) -> Result<(StatusCode, ControllerReset), BleError<E>> {
+        self.state.reset();
+        self.try_issue(Reset {})?;
+
+        loop {
+            if let Some(complete) = self.maybe_poll::<CommandComplete<Reset>>()? {
+                return Ok((complete.return_parameters, ControllerReset));
+            }
+
+            self.dispatch()?;
+        }
+    }
+
+    /// Hands back a queue slot that the host kept when an error without a `qslot` ended a method
+    /// that took one. Call it until it returns `None` after [Ble::reset_controller].
+    pub fn reclaim_slot(&mut self, _reset: &ControllerReset) -> Option<QueueSlot> {
+        self.state.take_kept_slot()
+    }
+
+    /// Asks the controller for its version, address, supported commands and features, and keeps
+    /// the answers so that commands it doesn't support fail with [BleError::Unsupported] from then
+    /// on, except those sent with [Ble::try_issue].
+    pub fn probe_controller(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<(&ControllerInfo, QueueSlot), BleError<E>> {
+        let (version, qslot) =
+            self.run_checked(qslot, poll_behavior, ReadLocalVersionInformation {})?;
+        let (address, qslot) = self.run_checked(qslot, poll_behavior, ReadBdAddr {})?;
+        let (commands, qslot) =
+            self.run_checked(qslot, poll_behavior, ReadLocalSupportedCommands {})?;
+        let (features, qslot) =
+            self.run_checked(qslot, poll_behavior, ReadLocalSupportedFeatures {})?;
+        let (le_features, qslot) =
+            self.run_checked(qslot, poll_behavior, LeReadLocalSupportedFeatures {})?;
+
+        let info = self.state.controller_info.insert(ControllerInfo {
+            version,
+            address,
+            commands,
+            features,
+            le_features,
+        });
+        Ok((info, qslot))
+    }
+
+    /// Finds out how much ACL data the controller can take, which [Ble::send_acl] needs to know.
+    /// LE Read Buffer Size v2 is used if the controller is known to support it, and Read Buffer
+    /// Size if the controller has no separate buffers for LE.
+    pub fn read_acl_buffer_size(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<(AclBufferSize, QueueSlot), BleError<E>> {
+        let (buffer_size, qslot) = if self.state.known_to_support(LeReadBufferSizeV2::OPCODE) {
+            let (size, qslot) = self.run_checked(qslot, poll_behavior, LeReadBufferSizeV2 {})?;
+            (AclBufferSize::from(size), qslot)
+        } else {
+            let (size, qslot) = self.run_checked(qslot, poll_behavior, LeReadBufferSize {})?;
+            (AclBufferSize::from(size), qslot)
+        };
+
+        let (buffer_size, qslot) = if buffer_size.data_len == 0 {
+            let (size, qslot) = self.run_checked(qslot, poll_behavior, ReadBufferSize {})?;
+            (AclBufferSize::from(size), qslot)
+        } else {
+            (buffer_size, qslot)
+        };
+
+        self.state.acl.set_buffer_size(buffer_size);
+        Ok((buffer_size, qslot))
+    }
+
+    /// Sends `frame` in as many ACL data packets as the controller's buffers need, waiting for
+    /// the controller to have room for each of them. Events received meanwhile are handed over to
+    /// the [EventRouter].
+    pub fn send_acl(&mut self, frame: &L2capFrame) -> Result<(), BleError<E>> {
+        let buffer_size = self
+            .state
+            .acl
+            .buffer_size()
+            .ok_or(BleError::AclBufferSizeUnknown)?;
+
+        for (packet_boundary_flag, fragment) in acl::fragments(frame, buffer_size.data_len) {
+            while !self.state.acl.take_credit(frame.connection_handle) {
+                self.dispatch()?;
+            }
+
+            let header = host::acl_header(
+                frame.connection_handle,
+                packet_boundary_flag,
+                fragment.len(),
+            );
+            self.hci.write_all(&header)?;
+            self.hci.write_all(fragment)?;
+            self.hci.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Polls for ACL data, reassembling the L2CAP frames that arrive in fragments and handing any
+    /// events over to the [EventRouter]. Returns a frame once its last fragment has arrived.
+    pub fn poll_acl(&mut self) -> Result<Option<L2capFrame>, BleError<E>> {
+        if let Some(packet) = self.state.acl_packets.pop_front() {
+            return Ok(self.state.reassemble(packet));
+        }
+        if let Some(encoded) = self.state.take_pending() {
+            self.state.route(&mut self.router, &encoded)?;
+            return Ok(None);
+        }
+
+        loop {
+            match self.try_poll_packet() {
+                Ok(HciPacket::Event(encoded)) => {
+                    self.state.receive(&encoded)?;
+                    self.issue_queued()?;
+                    self.state.route(&mut self.router, &encoded)?;
+                    return Ok(None);
+                }
+                Ok(HciPacket::AclData(packet)) => return Ok(self.state.reassemble(packet)),
+                Ok(packet) => {
+                    self.state.receive_data(packet);
+                    return Ok(None);
+                }
+                Err(BleError::WouldBlock) => self.delay.delay_ms(10),
+                Err(e) => return Err(e),
             }
         }
     }
@@ -143,71 +597,144 @@ where
         qslot: QueueSlot,
         command: C,
     ) -> Result<QueueLock<C>, BleError<E>> {
-        let encoded = EncodedCommand::encode(command)?;
-
-        if self.num_hci_command_packets > 0 {
-            self.try_issue_raw(encoded)?
-        } else {
-            if self.queued_command.is_some() {
-                panic!("Invalid state: Queue is full")
-            }
+        match EncodedCommand::encode(command) {
+            Ok(encoded) => self.queue_encoded(qslot, encoded),
+            Err(error) => Err(self.state.keep_slot(qslot, error.into())),
+        }
+    }
 
-            self.queued_command = Some(encoded);
+    fn queue_encoded<C>(
+        &mut self,
+        qslot: QueueSlot,
+        encoded: EncodedCommand,
+    ) -> Result<QueueLock<C>, BleError<E>> {
+        if !self.state.supports(encoded.opcode) {
+            return Err(BleError::Unsupported {
+                opcode: encoded.opcode,
+                qslot,
+            });
+        }
+        let Ok(id) = self.state.queue(encoded) else {
+            return Err(BleError::QueueFull { qslot });
+        };
+        if let Err(error) = self.issue_queued() {
+            return Err(self.state.keep_slot(qslot, error));
         }
 
         Ok(QueueLock {
             _phantom: PhantomData,
             qslot,
+            id,
         })
     }
 
     /// Tries to issue `command`, returning `Err(BleError::WouldBlock)` if the controller currently
     /// cannot accept more commands.
+    ///
+    /// With no queue slot to give back, this isn't checked against the [ControllerInfo]: a command
+    /// the controller doesn't support is sent anyway, and answered with
+    /// [StatusError::UnknownHciCommand].
     pub fn try_issue<C: Encode + HasOpcode>(&mut self, command: C) -> Result<(), BleError<E>> {
-        if self.num_hci_command_packets == 0 {
-            return Err(BleError::WouldBlock);
-        }
-
         self.try_issue_raw(EncodedCommand::encode(command)?)
     }
 
-    /// Tries to issue `command`, returning `Err(BleError::WouldBlock)` if the controller currently
-    /// cannot accept more commands.
+    /// Like [Ble::try_issue], for an already encoded command. It isn't checked against the
+    /// [ControllerInfo] either.
     pub fn try_issue_raw(&mut self, command: EncodedCommand) -> Result<(), BleError<E>> {
-        if self.num_hci_command_packets == 0 {
+        if !self.state.take_credit() {
             return Err(BleError::WouldBlock);
         }
 
-        self.hci.write_all(&[0x01])?;
-        self.hci.write_all(&command.opcode().0.to_le_bytes())?;
-        self.hci.write_all(&[command.parameters.len() as u8])?;
+        self.write_command(&command)
+    }
+
+    /// Polls until an `Ev` arrives. Other events are routed, or with [PollBehavior::Strict], the
+    /// first one is left for the next poll and `None` is returned.
+    fn wait_for<Ev: for<'a> EventParameters<'a>>(
+        &mut self,
+        poll_behavior: PollBehavior,
+    ) -> Result<Option<Ev>, BleError<E>> {
+        loop {
+            if let Some(event) = self.maybe_poll::<Ev>()? {
+                return Ok(Some(event));
+            }
+            match poll_behavior {
+                PollBehavior::Filter => self.dispatch()?,
+                PollBehavior::Strict => return Ok(None),
+            }
+        }
+    }
+
+    /// Polls until the completion of `command` arrives, routing other events. Returns `None` if a
+    /// completion for something else arrives first, leaving it for the next poll.
+    fn wait_for_completion<C: CommandWithCompletionEvent>(
+        &mut self,
+        command: &C,
+    ) -> Result<Option<C::Completion>, BleError<E>> {
+        loop {
+            let encoded = self.poll_raw()?;
+            match encoded.decode::<C::Completion>()? {
+                Some(completion) if command.is_completed_by(&completion) => {
+                    return Ok(Some(completion))
+                }
+                Some(_) => {
+                    self.state.requeue(encoded);
+                    return Ok(None);
+                }
+                None => self.state.route(&mut self.router, &encoded)?,
+            }
+        }
+    }
+
+    /// Sends as many queued commands as the controller currently has room for.
+    fn issue_queued(&mut self) -> Result<(), BleError<E>> {
+        while let Some(command) = self.state.next_command() {
+            self.write_command(&command)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_command(&mut self, command: &EncodedCommand) -> Result<(), BleError<E>> {
+        self.hci.write_all(&host::command_header(command))?;
         self.hci.write_all(&command.parameters)?;
         self.hci.flush()?;
 
         Ok(())
     }
 
-    /// Polls for events, decoding as the event type `Ev` and ignoring any that don't match.
-    pub fn filter_poll<Ev: EventParameters>(&mut self) -> Result<Option<Ev>, BleError<E>> {
-        Ok(self.poll_raw()?.decode()?)
+    /// Polls for events, decoding as the event type `Ev` and handing any that don't match over to
+    /// the [EventRouter].
+    ///
+    /// `Ev` may borrow from the host's receive buffer, e.g.
+    /// [LeAdvertisingReport](event::le_advertising_report::LeAdvertisingReport), in which case it
+    /// has to be dropped before polling again.
+    pub fn filter_poll<'a, Ev: EventParameters<'a>>(
+        &'a mut self,
+    ) -> Result<Option<Ev>, BleError<E>> {
+        let encoded = self.poll_raw()?;
+
+        Ok(self.state.hold_or_route(&mut self.router, encoded)?)
     }
 
-    /// Polls for events, decoding as the event type `Ev` and leaving unmatched events unprocessed.
-    pub fn maybe_poll<Ev: EventParameters>(&mut self) -> Result<Option<Ev>, BleError<E>> {
+    /// Polls for an event and hands it over to the [EventRouter].
+    pub fn dispatch(&mut self) -> Result<(), BleError<E>> {
         let encoded = self.poll_raw()?;
+        self.state.route(&mut self.router, &encoded)?;
+        Ok(())
+    }
 
-        let decoded = encoded.decode::<Ev>()?;
+    /// Polls for events, decoding as the event type `Ev` and leaving unmatched events unprocessed.
+    pub fn maybe_poll<'a, Ev: EventParameters<'a>>(
+        &'a mut self,
+    ) -> Result<Option<Ev>, BleError<E>> {
+        let encoded = self.poll_raw()?;
 
-        if let Some(decoded) = decoded {
-            Ok(Some(decoded))
-        } else {
-            self.queued_event = Some(encoded);
-            Ok(None)
-        }
+        Ok(self.state.hold(encoded)?)
     }
 
     pub fn poll_raw(&mut self) -> Result<EncodedEvent, BleError<E>> {
-        if let Some(encoded) = self.queued_event.take() {
+        if let Some(encoded) = self.state.take_pending() {
             return Ok(encoded)
         }
 
@@ -215,7 +742,7 @@ where
             match self.try_poll_raw() {
                 Ok(ev) => return Ok(ev),
                 Err(BleError::WouldBlock) => {
-                    self.delay.delay(10.millis());
+                    self.delay.delay_ms(10);
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -223,43 +750,81 @@ where
         }
     }
 
-    pub fn try_poll_raw(&mut self) -> Result<EncodedEvent, BleError<E>> {
+    /// Like [Ble::poll_raw], but returns `None` once `clock` reaches `deadline`, even if events
+    /// keep arriving.
+    fn poll_raw_before<K: Clock>(
+        &mut self,
+        clock: &mut K,
+        deadline: u64,
+    ) -> Result<Option<EncodedEvent>, BleError<E>> {
         loop {
-            let mut packet_type_buf = [0; 1];
-            match self.hci.read_exact(&mut packet_type_buf) {
-                Ok(()) => (),
-                Err(ReadExactError::UnexpectedEof) => return Err(BleError::WouldBlock),
-                Err(ReadExactError::Other(e)) => return Err(BleError::Io(e)),
+            if clock.now_millis() >= deadline {
+                return Ok(None);
+            }
+            if let Some(encoded) = self.state.take_pending() {
+                return Ok(Some(encoded));
             }
-            let packet_type = packet_type_buf[0];
-            assert_eq!(packet_type, 4);
-
-            let mut header_buf = [0; 2];
-            self.hci.read_exact(&mut header_buf)?;
-            let [event_code, parameter_length] = header_buf;
 
-            let mut event_parameters_buf = [0; 255];
-            let event_parameters = &mut event_parameters_buf[..parameter_length as usize];
-            self.hci.read_exact(event_parameters)?;
+            match self.try_poll_raw() {
+                Ok(ev) => return Ok(Some(ev)),
+                Err(BleError::WouldBlock) => self.delay.delay_ms(10),
+                Err(e) => return Err(e),
+            }
+        }
+    }
 
-            let encoded = EncodedEvent {
-                code: EventCode(event_code),
-                parameters: Buffer::from(&*event_parameters),
-            };
+    /// Polls for the next event, keeping any data packets received before it for later.
+    pub fn try_poll_raw(&mut self) -> Result<EncodedEvent, BleError<E>> {
+        loop {
+            match self.try_poll_packet()? {
+                HciPacket::Event(encoded) => {
+                    self.state.receive(&encoded)?;
+                    self.issue_queued()?;
 
-            if let Some(event) = encoded.decode::<CommandComplete<AnyCommand>>()? {
-                self.num_hci_command_packets = event.num_hci_command_packets as usize;
-            } else if let Some(event) = encoded.decode::<CommandStatus<AnyCommand>>()? {
-                self.num_hci_command_packets = event.num_hci_command_packets as usize;
+                    return Ok(encoded);
+                }
+                packet => self.state.receive_data(packet),
             }
+        }
+    }
 
-            if self.num_hci_command_packets > 0 {
-                if let Some(queued_command) = self.queued_command.take() {
-                    self.try_issue_raw(queued_command)?
+    /// Reads a single packet of any type from the controller, returning `Err(BleError::WouldBlock)`
+    /// if there is none.
+    ///
+    /// After a [FramingError], bytes are skipped until something that looks like the start of a
+    /// packet is found.
+    pub fn try_poll_packet(&mut self) -> Result<HciPacket, BleError<E>> {
+        loop {
+            let mut packet_type_buf = [0; 1];
+            if self.state.take_lookahead(&mut packet_type_buf) == 0 {
+                match self.hci.read_exact(&mut packet_type_buf) {
+                    Ok(()) => (),
+                    Err(ReadExactError::UnexpectedEof) => return Err(BleError::WouldBlock),
+                    Err(ReadExactError::Other(e)) => return Err(BleError::Io(e)),
                 }
             }
+            let packet_type = PacketType(packet_type_buf[0]);
+            let Some(header_len) = self.state.check_packet_type(packet_type)? else {
+                continue;
+            };
 
-            return Ok(encoded)
+            let mut header_buf = [0; packet::MAX_HEADER_LEN];
+            let header = &mut header_buf[..header_len];
+            self.read_exact(header)?;
+            let Some(payload_len) = self.state.check_header(packet_type, header)? else {
+                continue;
+            };
+
+            let mut payload_buf = [0; packet::MAX_PAYLOAD_LEN];
+            let payload = &mut payload_buf[..payload_len];
+            self.read_exact(payload)?;
+
+            return Ok(HciPacket::decode(packet_type, header, payload)?);
         }
     }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<E>> {
+        let len = self.state.take_lookahead(buf);
+        self.hci.read_exact(&mut buf[len..])
+    }
 }
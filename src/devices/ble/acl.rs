@@ -0,0 +1,271 @@
+//! ACL data: flow control for the packets the host sends, and reassembly of the L2CAP frames the
+//! controller sends in fragments.
+
+use heapless::{LinearMap, Vec};
+
+use super::{
+    command::{
+        le_read_buffer_size::LeBufferSize, le_read_buffer_size_v2::LeBufferSizeV2,
+        read_buffer_size::BufferSize,
+    },
+    data::{Buffer, Encoder, EncoderFull},
+    packet::{AclPacket, MAX_ACL_DATA_LEN},
+    MAX_CONNECTIONS,
+};
+
+/// The longest L2CAP frame the host sends or reassembles: the basic L2CAP header followed by
+/// the largest ATT_MTU.
+pub const MAX_L2CAP_FRAME_LEN: usize = 4 + 517;
+
+/// The length of the basic L2CAP header, a payload length followed by a channel ID.
+const L2CAP_HEADER_LEN: usize = 4;
+
+/// Packet boundary flags (Core v5.4, Vol 4, Part E, 5.4.2).
+const FIRST_NON_FLUSHABLE: u8 = 0b00;
+const CONTINUING: u8 = 0b01;
+const FIRST_FLUSHABLE: u8 = 0b10;
+
+/// The controller's buffers for ACL data from the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclBufferSize {
+    /// The longest data an ACL data packet to the controller may carry.
+    pub data_len: u16,
+    /// The number of ACL data packets the controller can hold at once.
+    pub num_packets: u16,
+}
+
+impl From<LeBufferSize> for AclBufferSize {
+    fn from(size: LeBufferSize) -> AclBufferSize {
+        AclBufferSize {
+            data_len: size.le_acl_data_packet_length,
+            num_packets: size.total_num_le_acl_data_packets.into(),
+        }
+    }
+}
+
+impl From<LeBufferSizeV2> for AclBufferSize {
+    fn from(size: LeBufferSizeV2) -> AclBufferSize {
+        AclBufferSize {
+            data_len: size.le_acl_data_packet_length,
+            num_packets: size.total_num_le_acl_data_packets.into(),
+        }
+    }
+}
+
+/// The buffers that LE shares with BR/EDR.
+impl From<BufferSize> for AclBufferSize {
+    fn from(size: BufferSize) -> AclBufferSize {
+        AclBufferSize {
+            data_len: size.acl_data_packet_length,
+            num_packets: size.total_num_acl_data_packets,
+        }
+    }
+}
+
+/// A whole L2CAP frame on the connection `connection_handle`, starting with its basic header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct L2capFrame {
+    pub connection_handle: u16,
+    data: Buffer<MAX_L2CAP_FRAME_LEN>,
+}
+
+impl L2capFrame {
+    /// Puts `payload` behind a basic L2CAP header for `channel_id`, failing if it is longer than
+    /// the host can send.
+    pub fn new(
+        connection_handle: u16,
+        channel_id: u16,
+        payload: &[u8],
+    ) -> Result<L2capFrame, EncoderFull> {
+        let len = u16::try_from(payload.len()).map_err(|_| EncoderFull)?;
+        let mut data = Buffer::new();
+        data.encode(&len)?;
+        data.encode(&channel_id)?;
+        data.write(payload)?;
+
+        Ok(L2capFrame {
+            connection_handle,
+            data,
+        })
+    }
+
+    pub fn channel_id(&self) -> u16 {
+        u16::from_le_bytes([self.data[2], self.data[3]])
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.data[L2CAP_HEADER_LEN..]
+    }
+
+    /// The whole frame, header included.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Splits `frame` into fragments of at most `data_len` bytes, each with its packet boundary flag.
+pub(super) fn fragments(frame: &L2capFrame, data_len: u16) -> impl Iterator<Item = (u8, &[u8])> {
+    let data_len = (data_len as usize).clamp(1, MAX_ACL_DATA_LEN);
+    frame
+        .as_bytes()
+        .chunks(data_len)
+        .enumerate()
+        .map(|(i, fragment)| {
+            // LE doesn't allow automatically flushable packets from the host.
+            let flag = if i == 0 {
+                FIRST_NON_FLUSHABLE
+            } else {
+                CONTINUING
+            };
+            (flag, fragment)
+        })
+}
+
+/// ACL bookkeeping shared by the blocking and async hosts.
+pub(super) struct AclState {
+    buffer_size: Option<AclBufferSize>,
+    /// The number of packets the controller has room for.
+    free: u16,
+    /// The packets sent on each connection that the controller hasn't completed yet.
+    in_flight: LinearMap<u16, u16, MAX_CONNECTIONS>,
+    /// Frames whose first fragments have arrived, at most one per connection.
+    partial: Vec<L2capFrame, MAX_CONNECTIONS>,
+}
+
+impl AclState {
+    pub(super) fn new() -> AclState {
+        AclState {
+            buffer_size: None,
+            free: 0,
+            in_flight: LinearMap::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    pub(super) fn buffer_size(&self) -> Option<AclBufferSize> {
+        self.buffer_size
+    }
+
+    pub(super) fn set_buffer_size(&mut self, buffer_size: AclBufferSize) {
+        let in_flight: u16 = self.in_flight.values().sum();
+        self.free = buffer_size.num_packets.saturating_sub(in_flight);
+        self.buffer_size = Some(buffer_size);
+    }
+
+    /// Forgets all packets in flight and all partial frames, as when the controller has just been
+    /// reset. Its buffers stay the same size.
+    pub(super) fn reset(&mut self) {
+        self.in_flight.clear();
+        self.partial.clear();
+        self.free = self.buffer_size.map_or(0, |size| size.num_packets);
+    }
+
+    /// Uses up one of the controller's buffers for a packet on `connection_handle`, returning
+    /// false if there is none to spare.
+    pub(super) fn take_credit(&mut self, connection_handle: u16) -> bool {
+        if self.free == 0 {
+            return false;
+        }
+
+        match self.in_flight.get_mut(&connection_handle) {
+            Some(count) => *count += 1,
+            None => {
+                if self.in_flight.insert(connection_handle, 1).is_err() {
+                    // Packets are in flight on too many connections to keep track of another.
+                    return false;
+                }
+            }
+        }
+        self.free -= 1;
+        true
+    }
+
+    /// Gives back the buffers of `count` packets on `connection_handle`.
+    pub(super) fn complete(&mut self, connection_handle: u16, count: u16) {
+        let Some(in_flight) = self.in_flight.get_mut(&connection_handle) else {
+            return;
+        };
+
+        let count = count.min(*in_flight);
+        *in_flight -= count;
+        if *in_flight == 0 {
+            self.in_flight.remove(&connection_handle);
+        }
+        self.free += count;
+    }
+
+    /// Forgets `connection_handle`, whose packets the controller flushes without completing them
+    /// (Core v5.4, Vol 4, Part E, 4.3).
+    pub(super) fn disconnect(&mut self, connection_handle: u16) {
+        if let Some(count) = self.in_flight.remove(&connection_handle) {
+            self.free += count;
+        }
+        self.partial
+            .retain(|frame| frame.connection_handle != connection_handle);
+    }
+
+    /// Adds `packet` to the frame on its connection, returning the frame once it is whole.
+    /// `dropped` counts the packets that can't be part of a frame, including the fragments of a
+    /// frame that was cut short.
+    pub(super) fn reassemble(
+        &mut self,
+        packet: AclPacket,
+        dropped: &mut usize,
+    ) -> Option<L2capFrame> {
+        let handle = packet.connection_handle;
+        let position = self
+            .partial
+            .iter()
+            .position(|frame| frame.connection_handle == handle);
+
+        let index = match (packet.packet_boundary_flag, position) {
+            (FIRST_NON_FLUSHABLE | FIRST_FLUSHABLE, position) => {
+                let frame = L2capFrame {
+                    connection_handle: handle,
+                    data: Buffer::new(),
+                };
+                match position {
+                    Some(index) => {
+                        *dropped += 1;
+                        self.partial[index] = frame;
+                        index
+                    }
+                    None => {
+                        if self.partial.push(frame).is_err() {
+                            *dropped += 1;
+                            return None;
+                        }
+                        self.partial.len() - 1
+                    }
+                }
+            }
+            (CONTINUING, Some(index)) => index,
+            _ => {
+                *dropped += 1;
+                return None;
+            }
+        };
+
+        let frame = &mut self.partial[index];
+        if frame.data.write(&packet.data).is_err() {
+            *dropped += 1;
+            self.partial.swap_remove(index);
+            return None;
+        }
+
+        let [len_lo, len_hi, ..] = *frame.data else {
+            return None;
+        };
+        let total = L2CAP_HEADER_LEN + u16::from_le_bytes([len_lo, len_hi]) as usize;
+        if frame.data.len() < total {
+            return None;
+        }
+
+        let frame = self.partial.swap_remove(index);
+        if frame.data.len() > total {
+            *dropped += 1;
+            return None;
+        }
+        Some(frame)
+    }
+}
@@ -0,0 +1,658 @@
+//! Advertising, so that centrals such as phones can find the watch and connect to it: legacy
+//! advertising with an [Advertiser], or BLE 5 extended advertising with one or more
+//! [AdvertisingSet]s.
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::{Read, Write};
+
+use super::{
+    command::{
+        le_remove_advertising_set::LeRemoveAdvertisingSet,
+        le_set_advertising_data::LeSetAdvertisingData,
+        le_set_advertising_enable::LeSetAdvertisingEnable,
+        le_set_advertising_parameters::LeSetAdvertisingParameters,
+        le_set_extended_advertising_data::LeSetExtendedAdvertisingData,
+        le_set_extended_advertising_enable::{
+            AdvertisingSetEnable, AdvertisingSetEnables, LeSetExtendedAdvertisingEnable,
+        },
+        le_set_extended_advertising_parameters::LeSetExtendedAdvertisingParameters,
+        le_set_extended_scan_response_data::LeSetExtendedScanResponseData,
+        le_set_scan_response_data::LeSetScanResponseData,
+    },
+    data::advertising_data::{self, AdvertisingData},
+    event::{
+        le_advertising_set_terminated::LeAdvertisingSetTerminated,
+        le_connection_complete::LeConnectionComplete,
+    },
+    router::EventRouter,
+    Ble, BleError, PollBehavior, QueueSlot,
+};
+
+#[cfg(feature = "async")]
+use super::asynch::AsyncBle;
+
+/// The role of the controller in a connection it completed as the advertiser.
+const PERIPHERAL_ROLE: u8 = 0x01;
+
+/// Asks the controller not to split up data that the host already split up.
+const FRAGMENTS_FROM_HOST: u8 = 0x01;
+
+/// What an [Advertiser] or an [AdvertisingSet] does once a central has connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConnection {
+    /// Advertises again, so that more centrals can connect.
+    Restart,
+    /// Stays quiet until [Advertiser::start] is called again.
+    Stop,
+}
+
+/// Sets up legacy advertising and keeps track of whether it is going.
+///
+/// The controller stops advertising by itself once a central connects. Hand every
+/// [LeConnectionComplete] over to [Advertiser::handle_connection] so that it can restart
+/// advertising if configured to.
+#[derive(Debug, Clone)]
+pub struct Advertiser {
+    pub parameters: LeSetAdvertisingParameters,
+    pub data: AdvertisingData,
+    pub scan_response_data: AdvertisingData,
+    pub on_connection: OnConnection,
+    advertising: bool,
+}
+
+impl Advertiser {
+    pub fn new(
+        parameters: LeSetAdvertisingParameters,
+        data: AdvertisingData,
+        scan_response_data: AdvertisingData,
+        on_connection: OnConnection,
+    ) -> Advertiser {
+        Advertiser {
+            parameters,
+            data,
+            scan_response_data,
+            on_connection,
+            advertising: false,
+        }
+    }
+
+    pub fn is_advertising(&self) -> bool {
+        self.advertising
+    }
+
+    /// Sends the parameters and data to the controller and starts advertising.
+    pub fn start<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, self.parameters.clone())?;
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, self.data_command())?;
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, self.scan_response_command())?;
+
+        self.enable(ble, qslot, poll_behavior)
+    }
+
+    /// Stops advertising, if it is going.
+    pub fn stop<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        if !self.advertising {
+            return Ok(qslot);
+        }
+
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, enable_command(false))?;
+        self.advertising = false;
+        Ok(qslot)
+    }
+
+    /// Takes note of a completed connection. If a central connected to this advertiser,
+    /// advertising has stopped, and is restarted with [OnConnection::Restart].
+    pub fn handle_connection<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        event: &LeConnectionComplete,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        if self.restarts_after(event) {
+            self.enable(ble, qslot, poll_behavior)
+        } else {
+            Ok(qslot)
+        }
+    }
+
+    fn enable<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, enable_command(true))?;
+        self.advertising = true;
+        Ok(qslot)
+    }
+
+    /// Like [Advertiser::start], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn start_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        let parameters = self.parameters.clone();
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, parameters).await?;
+        let ((), qslot) = ble
+            .run_checked(qslot, poll_behavior, self.data_command())
+            .await?;
+        let ((), qslot) = ble
+            .run_checked(qslot, poll_behavior, self.scan_response_command())
+            .await?;
+
+        self.enable_async(ble, qslot, poll_behavior).await
+    }
+
+    /// Like [Advertiser::stop], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn stop_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        if !self.advertising {
+            return Ok(qslot);
+        }
+
+        let ((), qslot) = ble
+            .run_checked(qslot, poll_behavior, enable_command(false))
+            .await?;
+        self.advertising = false;
+        Ok(qslot)
+    }
+
+    /// Like [Advertiser::handle_connection], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn handle_connection_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        event: &LeConnectionComplete,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        if self.restarts_after(event) {
+            self.enable_async(ble, qslot, poll_behavior).await
+        } else {
+            Ok(qslot)
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn enable_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        let ((), qslot) = ble
+            .run_checked(qslot, poll_behavior, enable_command(true))
+            .await?;
+        self.advertising = true;
+        Ok(qslot)
+    }
+
+    /// Takes note of `event`, returning whether advertising has to be enabled again.
+    fn restarts_after(&mut self, event: &LeConnectionComplete) -> bool {
+        // Connections the watch initiated don't affect advertising.
+        if !self.advertising || event.role != PERIPHERAL_ROLE {
+            return false;
+        }
+
+        self.advertising = false;
+        self.on_connection == OnConnection::Restart && event.status.is_successful()
+    }
+
+    fn data_command(&self) -> LeSetAdvertisingData {
+        LeSetAdvertisingData {
+            advertising_data: self.data.clone(),
+        }
+    }
+
+    fn scan_response_command(&self) -> LeSetScanResponseData {
+        LeSetScanResponseData {
+            scan_response_data: self.scan_response_data.clone(),
+        }
+    }
+}
+
+fn enable_command(enable: bool) -> LeSetAdvertisingEnable {
+    LeSetAdvertisingEnable {
+        advertising_enable: enable.into(),
+    }
+}
+
+/// An extended advertising set, with data up to as long as the controller allows. Several sets can
+/// advertise at the same time, e.g. a connectable one for the phone and a non-connectable one
+/// that broadcasts sensor data.
+///
+/// The controller stops a set by itself once a central connects to it, or once its duration or
+/// number of events runs out. Hand every [LeAdvertisingSetTerminated] over to
+/// [AdvertisingSet::handle_terminated] so that it can restart if configured to.
+#[derive(Debug, Clone)]
+pub struct AdvertisingSet<'a> {
+    pub parameters: LeSetExtendedAdvertisingParameters,
+    /// In units of 10 ms, or 0 to advertise until stopped.
+    pub duration: u16,
+    /// 0 to advertise until stopped.
+    pub max_extended_advertising_events: u8,
+    pub on_connection: OnConnection,
+    data: &'a [u8],
+    scan_response_data: &'a [u8],
+    advertising: bool,
+}
+
+impl<'a> AdvertisingSet<'a> {
+    pub fn new(
+        parameters: LeSetExtendedAdvertisingParameters,
+        data: &'a [u8],
+        scan_response_data: &'a [u8],
+        on_connection: OnConnection,
+    ) -> AdvertisingSet<'a> {
+        AdvertisingSet {
+            parameters,
+            duration: 0,
+            max_extended_advertising_events: 0,
+            on_connection,
+            data,
+            scan_response_data,
+            advertising: false,
+        }
+    }
+
+    pub fn advertising_handle(&self) -> u8 {
+        self.parameters.advertising_handle
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn scan_response_data(&self) -> &'a [u8] {
+        self.scan_response_data
+    }
+
+    pub fn is_advertising(&self) -> bool {
+        self.advertising
+    }
+
+    /// Sends the parameters and data to the controller, creating the set if needed, and starts
+    /// advertising. Returns the transmit power the controller chose, in dBm.
+    pub fn start<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<(i8, QueueSlot), BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        let (tx_power, mut qslot) =
+            ble.run_checked(qslot, poll_behavior, self.parameters.clone())?;
+        // A new set has no data, and some kinds of sets must not be given any.
+        if !self.data.is_empty() {
+            for command in self.data_commands() {
+                ((), qslot) = ble.run_checked(qslot, poll_behavior, command)?;
+            }
+        }
+        if !self.scan_response_data.is_empty() {
+            for command in self.scan_response_commands() {
+                ((), qslot) = ble.run_checked(qslot, poll_behavior, command)?;
+            }
+        }
+
+        let qslot = self.enable(ble, qslot, poll_behavior)?;
+        Ok((tx_power, qslot))
+    }
+
+    /// Replaces the advertising data, e.g. for a broadcast of new sensor readings. While the set
+    /// is advertising, the controller may only accept data that fits into one command.
+    pub fn update_data<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        mut qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        data: &'a [u8],
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        self.data = data;
+        for command in self.data_commands() {
+            ((), qslot) = ble.run_checked(qslot, poll_behavior, command)?;
+        }
+        Ok(qslot)
+    }
+
+    /// Stops advertising, if the set is advertising.
+    pub fn stop<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        if !self.advertising {
+            return Ok(qslot);
+        }
+
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, self.enable_command(false))?;
+        self.advertising = false;
+        Ok(qslot)
+    }
+
+    /// Stops advertising and removes the set from the controller, freeing it for another set.
+    pub fn remove<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        let qslot = self.stop(ble, qslot, poll_behavior)?;
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, self.remove_command())?;
+        Ok(qslot)
+    }
+
+    /// Takes note of a terminated advertising set, ignoring other sets. If a central connected to
+    /// this one, it is restarted with [OnConnection::Restart].
+    pub fn handle_terminated<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        event: &LeAdvertisingSetTerminated,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        if self.restarts_after(event) {
+            self.enable(ble, qslot, poll_behavior)
+        } else {
+            Ok(qslot)
+        }
+    }
+
+    fn enable<E, H, D, R>(
+        &mut self,
+        ble: &mut Ble<H, D, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: Read<Error = E> + Write<Error = E>,
+        E: embedded_io::Error,
+        D: DelayNs,
+        R: EventRouter,
+    {
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, self.enable_command(true))?;
+        self.advertising = true;
+        Ok(qslot)
+    }
+
+    /// Like [AdvertisingSet::start], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn start_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<(i8, QueueSlot), BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        let parameters = self.parameters.clone();
+        let (tx_power, mut qslot) = ble.run_checked(qslot, poll_behavior, parameters).await?;
+        if !self.data.is_empty() {
+            for command in self.data_commands() {
+                ((), qslot) = ble.run_checked(qslot, poll_behavior, command).await?;
+            }
+        }
+        if !self.scan_response_data.is_empty() {
+            for command in self.scan_response_commands() {
+                ((), qslot) = ble.run_checked(qslot, poll_behavior, command).await?;
+            }
+        }
+
+        let qslot = self.enable_async(ble, qslot, poll_behavior).await?;
+        Ok((tx_power, qslot))
+    }
+
+    /// Like [AdvertisingSet::update_data], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn update_data_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        mut qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        data: &'a [u8],
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        self.data = data;
+        for command in self.data_commands() {
+            ((), qslot) = ble.run_checked(qslot, poll_behavior, command).await?;
+        }
+        Ok(qslot)
+    }
+
+    /// Like [AdvertisingSet::stop], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn stop_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        if !self.advertising {
+            return Ok(qslot);
+        }
+
+        let command = self.enable_command(false);
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, command).await?;
+        self.advertising = false;
+        Ok(qslot)
+    }
+
+    /// Like [AdvertisingSet::remove], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn remove_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        let qslot = self.stop_async(ble, qslot, poll_behavior).await?;
+        let command = self.remove_command();
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, command).await?;
+        Ok(qslot)
+    }
+
+    /// Like [AdvertisingSet::handle_terminated], for an [AsyncBle].
+    #[cfg(feature = "async")]
+    pub async fn handle_terminated_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        event: &LeAdvertisingSetTerminated,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        if self.restarts_after(event) {
+            self.enable_async(ble, qslot, poll_behavior).await
+        } else {
+            Ok(qslot)
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn enable_async<E, H, R>(
+        &mut self,
+        ble: &mut AsyncBle<H, R>,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<QueueSlot, BleError<E>>
+    where
+        H: embedded_io_async::Read<Error = E> + embedded_io_async::Write<Error = E>,
+        E: embedded_io::Error,
+        R: EventRouter,
+    {
+        let command = self.enable_command(true);
+        let ((), qslot) = ble.run_checked(qslot, poll_behavior, command).await?;
+        self.advertising = true;
+        Ok(qslot)
+    }
+
+    /// Takes note of `event`, returning whether the set has to be enabled again.
+    fn restarts_after(&mut self, event: &LeAdvertisingSetTerminated) -> bool {
+        if !self.advertising || event.advertising_handle != self.advertising_handle() {
+            return false;
+        }
+
+        self.advertising = false;
+        // The set only terminates successfully when a central connected.
+        self.on_connection == OnConnection::Restart && event.status.is_successful()
+    }
+
+    fn data_commands(&self) -> impl Iterator<Item = LeSetExtendedAdvertisingData> + 'a {
+        let advertising_handle = self.advertising_handle();
+        advertising_data::fragments(self.data).map(move |(operation, advertising_data)| {
+            LeSetExtendedAdvertisingData {
+                advertising_handle,
+                operation,
+                fragment_preference: FRAGMENTS_FROM_HOST,
+                advertising_data,
+            }
+        })
+    }
+
+    fn scan_response_commands(&self) -> impl Iterator<Item = LeSetExtendedScanResponseData> + 'a {
+        let advertising_handle = self.advertising_handle();
+        advertising_data::fragments(self.scan_response_data).map(
+            move |(operation, scan_response_data)| LeSetExtendedScanResponseData {
+                advertising_handle,
+                operation,
+                fragment_preference: FRAGMENTS_FROM_HOST,
+                scan_response_data,
+            },
+        )
+    }
+
+    fn enable_command(&self, enable: bool) -> LeSetExtendedAdvertisingEnable {
+        let set = AdvertisingSetEnable {
+            advertising_handle: self.advertising_handle(),
+            duration: self.duration,
+            max_extended_advertising_events: self.max_extended_advertising_events,
+        };
+        let mut sets = AdvertisingSetEnables::default();
+        // One set always fits.
+        let _ = sets.0.push(set);
+
+        LeSetExtendedAdvertisingEnable {
+            enable: enable.into(),
+            sets,
+        }
+    }
+
+    fn remove_command(&self) -> LeRemoveAdvertisingSet {
+        LeRemoveAdvertisingSet {
+            advertising_handle: self.advertising_handle(),
+        }
+    }
+}
@@ -0,0 +1,616 @@
+//! An async version of [Ble](super::Ble) for use under an executor such as embassy.
+
+use core::{future::Future, marker::PhantomData, pin::pin};
+
+use embassy_futures::select::{select, Either};
+use embedded_io_async::{Read, ReadExactError, Write};
+
+use super::{
+    acl::{self, AclBufferSize, L2capFrame},
+    command::{
+        le_read_buffer_size::LeReadBufferSize, le_read_buffer_size_v2::LeReadBufferSizeV2,
+        le_read_local_supported_features::LeReadLocalSupportedFeatures, read_bd_addr::ReadBdAddr,
+        read_buffer_size::ReadBufferSize,
+        read_local_supported_commands::ReadLocalSupportedCommands,
+        read_local_supported_features::ReadLocalSupportedFeatures,
+        read_local_version_information::ReadLocalVersionInformation, reset::Reset,
+        CommandParameters, EncodedCommand,
+    },
+    controller::ControllerInfo,
+    data::status_code::StatusCode,
+    event::{
+        command_complete::{CommandComplete, CommandWithCompleteEvent, ReturnParametersWithStatus},
+        command_status::{CommandStatus, CommandWithCompletionEvent, CompletionEvent},
+        EncodedEvent, EventParameters,
+    },
+    host::{self, HostState},
+    packet::{self, AclPacket, FramingStats, HciPacket, PacketType},
+    router::EventRouter,
+    BleError, ControllerReset, PollBehavior, QueueLock, QueueSlot, COMMAND_QUEUE_LEN,
+};
+
+pub struct AsyncBle<H, R = ()> {
+    state: HostState,
+    hci: H,
+    /// Receives the events that the host isn't waiting for.
+    router: R,
+}
+
+impl<H> AsyncBle<H> {
+    pub fn new(hci: H) -> (Self, QueueSlot) {
+        let (ble, [qslot]) = Self::with_slots(hci);
+        (ble, qslot)
+    }
+
+    /// Like [AsyncBle::new], but hands out `N` queue slots so that up to `N` commands can be in
+    /// flight at once.
+    pub fn with_slots<const N: usize>(hci: H) -> (Self, [QueueSlot; N]) {
+        AsyncBle::with_router(hci, ())
+    }
+}
+
+impl<H, R> AsyncBle<H, R> {
+    /// Creates a host that delivers the events it isn't waiting for to `router`, handing out `N`
+    /// queue slots.
+    pub fn with_router<const N: usize>(hci: H, router: R) -> (Self, [QueueSlot; N]) {
+        const { assert!(N <= COMMAND_QUEUE_LEN, "too many queue slots") };
+
+        (
+            Self {
+                state: HostState::new(),
+                hci,
+                router,
+            },
+            [(); N].map(|()| QueueSlot),
+        )
+    }
+
+    /// Returns the underlying HCI transport.
+    pub fn hci(&self) -> &H {
+        &self.hci
+    }
+
+    pub fn router(&self) -> &R {
+        &self.router
+    }
+
+    pub fn router_mut(&mut self) -> &mut R {
+        &mut self.router
+    }
+
+    /// The number of events that were neither awaited by the host nor taken by the router.
+    pub fn unrouted_events(&self) -> usize {
+        self.state.unrouted_events
+    }
+
+    /// The number of received packets other than events that the host had no use or no room for.
+    pub fn dropped_packets(&self) -> usize {
+        self.state.dropped_packets
+    }
+
+    pub fn framing_stats(&self) -> FramingStats {
+        self.state.framing_stats
+    }
+
+    /// What the controller supports, once [AsyncBle::probe_controller] has found out.
+    pub fn controller_info(&self) -> Option<&ControllerInfo> {
+        self.state.controller_info.as_ref()
+    }
+
+    /// The controller's buffers for ACL data, once [AsyncBle::read_acl_buffer_size] has found
+    /// out.
+    pub fn acl_buffer_size(&self) -> Option<AclBufferSize> {
+        self.state.acl.buffer_size()
+    }
+
+    /// Takes the oldest ACL data packet received while waiting for events. Packets taken this way
+    /// aren't reassembled by [AsyncBle::poll_acl].
+    pub fn take_acl_packet(&mut self) -> Option<AclPacket> {
+        self.state.acl_packets.pop_front()
+    }
+}
+
+impl<E, H, R> AsyncBle<H, R>
+where
+    H: Read<Error = E> + Write<Error = E>,
+    E: embedded_io::Error,
+    R: EventRouter,
+{
+    pub async fn run_until_complete<C: CommandParameters + CommandWithCompleteEvent>(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        command: C,
+    ) -> Result<(C::ReturnParameters, QueueSlot), BleError<E>> {
+        let qlock = self.queue(qslot, command).await?;
+
+        match self.wait_for::<CommandComplete<C>>(poll_behavior).await {
+            Ok(Some(complete)) => {
+                let slot = qlock.release_with(&complete);
+                Ok((complete.return_parameters, slot))
+            }
+            Ok(None) => {
+                self.state.abandon(qlock.id);
+                Err(BleError::UnexpectedEvent { qslot: qlock.qslot })
+            }
+            Err(error) => Err(self.state.keep_slot(qlock.qslot, error)),
+        }
+    }
+
+    /// Like [AsyncBle::run_until_complete], but checks the status at the start of the return
+    /// parameters. See [Ble::run_checked](super::Ble::run_checked).
+    pub async fn run_checked<C>(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        command: C,
+    ) -> Result<
+        (
+            <C::ReturnParameters as ReturnParametersWithStatus>::Rest,
+            QueueSlot,
+        ),
+        BleError<E>,
+    >
+    where
+        C: CommandParameters + CommandWithCompleteEvent,
+        C::ReturnParameters: ReturnParametersWithStatus,
+    {
+        let (return_parameters, qslot) = self
+            .run_until_complete(qslot, poll_behavior, command)
+            .await?;
+        match return_parameters.into_result() {
+            Ok(rest) => Ok((rest, qslot)),
+            Err(status) => Err(BleError::Command {
+                opcode: C::OPCODE,
+                status,
+                qslot,
+            }),
+        }
+    }
+
+    /// Issues a command that is acknowledged with a Command Status, then waits for the event that
+    /// reports its outcome. See [Ble::run_until_completion](super::Ble::run_until_completion).
+    pub async fn run_until_completion<C>(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        command: C,
+    ) -> Result<(C::Completion, QueueSlot), BleError<E>>
+    where
+        C: CommandParameters + CommandWithCompletionEvent,
+    {
+        let encoded = match EncodedCommand::encode_ref(&command) {
+            Ok(encoded) => encoded,
+            Err(error) => return Err(self.state.keep_slot(qslot, error.into())),
+        };
+        let qlock = self.queue_encoded::<C>(qslot, encoded).await?;
+
+        let status = match self.wait_for::<CommandStatus<C>>(poll_behavior).await {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                self.state.abandon(qlock.id);
+                return Err(BleError::UnexpectedEvent { qslot: qlock.qslot });
+            }
+            Err(error) => return Err(self.state.keep_slot(qlock.qslot, error)),
+        };
+        let qslot = qlock.release_with(&status);
+        if let Some(status) = status.status.error() {
+            return Err(BleError::Command {
+                opcode: C::OPCODE,
+                status,
+                qslot,
+            });
+        }
+
+        match self.wait_for_completion(&command).await {
+            Ok(Some(completion)) => match completion.status().error() {
+                Some(status) => Err(BleError::Command {
+                    opcode: C::OPCODE,
+                    status,
+                    qslot,
+                }),
+                None => Ok((completion, qslot)),
+            },
+            Ok(None) => Err(BleError::CompletionMismatch {
+                opcode: C::OPCODE,
+                qslot,
+            }),
+            Err(error) => Err(self.state.keep_slot(qslot, error)),
+        }
+    }
+
+    /// Like [AsyncBle::run_until_complete], but gives up once `timeout` completes, e.g. an
+    /// `embassy_time::Timer`. See [Ble::run_until_complete_timeout](super::Ble::run_until_complete_timeout).
+    ///
+    /// The timeout is only raced against the start of the next packet: once a packet has started
+    /// arriving it is read in full, so giving up never leaves part of a packet behind.
+    pub async fn run_until_complete_timeout<C, T>(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+        command: C,
+        timeout: T,
+    ) -> Result<(C::ReturnParameters, QueueSlot), BleError<E>>
+    where
+        C: CommandParameters + CommandWithCompleteEvent,
+        T: Future<Output = ()>,
+    {
+        let qlock = self.queue(qslot, command).await?;
+        let mut timeout = pin!(timeout);
+
+        loop {
+            if !self.state.event_pending() {
+                match select(self.wait_for_packet(), timeout.as_mut()).await {
+                    Either::First(Ok(())) => {}
+                    Either::First(Err(error)) => {
+                        return Err(self.state.keep_slot(qlock.qslot, error))
+                    }
+                    Either::Second(()) => {
+                        self.state.abandon(qlock.id);
+                        return Err(BleError::Timeout {
+                            opcode: C::OPCODE,
+                            qslot: qlock.qslot,
+                        });
+                    }
+                }
+            }
+            let encoded = match self.poll_raw().await {
+                Ok(encoded) => encoded,
+                Err(error) => return Err(self.state.keep_slot(qlock.qslot, error)),
+            };
+
+            match encoded.decode::<CommandComplete<C>>() {
+                Ok(Some(complete)) => {
+                    let slot = qlock.release_with(&complete);
+                    return Ok((complete.return_parameters, slot));
+                }
+                Ok(None) => {}
+                Err(error) => return Err(self.state.keep_slot(qlock.qslot, error.into())),
+            }
+
+            match poll_behavior {
+                PollBehavior::Filter => {
+                    if let Err(error) = self.state.route(&mut self.router, &encoded) {
+                        return Err(self.state.keep_slot(qlock.qslot, error.into()));
+                    }
+                }
+                PollBehavior::Strict => {
+                    self.state.requeue(encoded);
+                    self.state.abandon(qlock.id);
+                    return Err(BleError::UnexpectedEvent { qslot: qlock.qslot });
+                }
+            }
+        }
+    }
+
+    /// Starts over after a transport error. See [Ble::reset_controller](super::Ble::reset_controller).
+    pub async fn reset_controller(&mut self) -> Result<(StatusCode, ControllerReset), BleError<E>> {
+        self.state.reset();
+        // The queue was just emptied, so there is room.
+        let _ = self.state.queue(EncodedCommand::encode(Reset {})?);
+        self.issue_queued().await?;
+
+        loop {
+            if let Some(complete) = self.maybe_poll::<CommandComplete<Reset>>().await? {
+                return Ok((complete.return_parameters, ControllerReset));
+            }
+
+            self.dispatch().await?;
+        }
+    }
+
+    /// Hands back a queue slot kept by the host. See
+    /// [Ble::reclaim_slot](super::Ble::reclaim_slot).
+    pub fn reclaim_slot(&mut self, _reset: &ControllerReset) -> Option<QueueSlot> {
+        self.state.take_kept_slot()
+    }
+
+    /// Asks the controller what it supports. See
+    /// [Ble::probe_controller](super::Ble::probe_controller).
+    pub async fn probe_controller(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<(&ControllerInfo, QueueSlot), BleError<E>> {
+        let (version, qslot) = self
+            .run_checked(qslot, poll_behavior, ReadLocalVersionInformation {})
+            .await?;
+        let (address, qslot) = self
+            .run_checked(qslot, poll_behavior, ReadBdAddr {})
+            .await?;
+        let (commands, qslot) = self
+            .run_checked(qslot, poll_behavior, ReadLocalSupportedCommands {})
+            .await?;
+        let (features, qslot) = self
+            .run_checked(qslot, poll_behavior, ReadLocalSupportedFeatures {})
+            .await?;
+        let (le_features, qslot) = self
+            .run_checked(qslot, poll_behavior, LeReadLocalSupportedFeatures {})
+            .await?;
+
+        let info = self.state.controller_info.insert(ControllerInfo {
+            version,
+            address,
+            commands,
+            features,
+            le_features,
+        });
+        Ok((info, qslot))
+    }
+
+    /// Finds out how much ACL data the controller can take. See
+    /// [Ble::read_acl_buffer_size](super::Ble::read_acl_buffer_size).
+    pub async fn read_acl_buffer_size(
+        &mut self,
+        qslot: QueueSlot,
+        poll_behavior: PollBehavior,
+    ) -> Result<(AclBufferSize, QueueSlot), BleError<E>> {
+        let (buffer_size, qslot) = if self.state.known_to_support(LeReadBufferSizeV2::OPCODE) {
+            let (size, qslot) = self
+                .run_checked(qslot, poll_behavior, LeReadBufferSizeV2 {})
+                .await?;
+            (AclBufferSize::from(size), qslot)
+        } else {
+            let (size, qslot) = self
+                .run_checked(qslot, poll_behavior, LeReadBufferSize {})
+                .await?;
+            (AclBufferSize::from(size), qslot)
+        };
+
+        let (buffer_size, qslot) = if buffer_size.data_len == 0 {
+            let (size, qslot) = self
+                .run_checked(qslot, poll_behavior, ReadBufferSize {})
+                .await?;
+            (AclBufferSize::from(size), qslot)
+        } else {
+            (buffer_size, qslot)
+        };
+
+        self.state.acl.set_buffer_size(buffer_size);
+        Ok((buffer_size, qslot))
+    }
+
+    /// Sends `frame` in as many ACL data packets as the controller's buffers need. See
+    /// [Ble::send_acl](super::Ble::send_acl).
+    pub async fn send_acl(&mut self, frame: &L2capFrame) -> Result<(), BleError<E>> {
+        let buffer_size = self
+            .state
+            .acl
+            .buffer_size()
+            .ok_or(BleError::AclBufferSizeUnknown)?;
+
+        for (packet_boundary_flag, fragment) in acl::fragments(frame, buffer_size.data_len) {
+            while !self.state.acl.take_credit(frame.connection_handle) {
+                self.dispatch().await?;
+            }
+
+            let header = host::acl_header(
+                frame.connection_handle,
+                packet_boundary_flag,
+                fragment.len(),
+            );
+            self.hci.write_all(&header).await?;
+            self.hci.write_all(fragment).await?;
+            self.hci.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits for ACL data, reassembling the L2CAP frames that arrive in fragments. See
+    /// [Ble::poll_acl](super::Ble::poll_acl).
+    pub async fn poll_acl(&mut self) -> Result<Option<L2capFrame>, BleError<E>> {
+        if let Some(packet) = self.state.acl_packets.pop_front() {
+            return Ok(self.state.reassemble(packet));
+        }
+        if let Some(encoded) = self.state.take_pending() {
+            self.state.route(&mut self.router, &encoded)?;
+            return Ok(None);
+        }
+
+        match self.poll_packet().await? {
+            HciPacket::Event(encoded) => {
+                self.state.receive(&encoded)?;
+                self.issue_queued().await?;
+                self.state.route(&mut self.router, &encoded)?;
+                Ok(None)
+            }
+            HciPacket::AclData(packet) => Ok(self.state.reassemble(packet)),
+            packet => {
+                self.state.receive_data(packet);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Queues `command`, turning `qslot` into a [QueueLock]. See [Ble::queue](super::Ble::queue).
+    pub async fn queue<C: CommandParameters>(
+        &mut self,
+        qslot: QueueSlot,
+        command: C,
+    ) -> Result<QueueLock<C>, BleError<E>> {
+        match EncodedCommand::encode(command) {
+            Ok(encoded) => self.queue_encoded(qslot, encoded).await,
+            Err(error) => Err(self.state.keep_slot(qslot, error.into())),
+        }
+    }
+
+    async fn queue_encoded<C>(
+        &mut self,
+        qslot: QueueSlot,
+        encoded: EncodedCommand,
+    ) -> Result<QueueLock<C>, BleError<E>> {
+        if !self.state.supports(encoded.opcode) {
+            return Err(BleError::Unsupported {
+                opcode: encoded.opcode,
+                qslot,
+            });
+        }
+        let Ok(id) = self.state.queue(encoded) else {
+            return Err(BleError::QueueFull { qslot });
+        };
+        if let Err(error) = self.issue_queued().await {
+            return Err(self.state.keep_slot(qslot, error));
+        }
+
+        Ok(QueueLock {
+            _phantom: PhantomData,
+            qslot,
+            id,
+        })
+    }
+
+    /// Polls until an `Ev` arrives. See [Ble::wait_for](super::Ble::wait_for).
+    async fn wait_for<Ev: for<'a> EventParameters<'a>>(
+        &mut self,
+        poll_behavior: PollBehavior,
+    ) -> Result<Option<Ev>, BleError<E>> {
+        loop {
+            if let Some(event) = self.maybe_poll::<Ev>().await? {
+                return Ok(Some(event));
+            }
+            match poll_behavior {
+                PollBehavior::Filter => self.dispatch().await?,
+                PollBehavior::Strict => return Ok(None),
+            }
+        }
+    }
+
+    /// Polls until the completion of `command` arrives, routing other events. See
+    /// [Ble::wait_for_completion](super::Ble::wait_for_completion).
+    async fn wait_for_completion<C: CommandWithCompletionEvent>(
+        &mut self,
+        command: &C,
+    ) -> Result<Option<C::Completion>, BleError<E>> {
+        loop {
+            let encoded = self.poll_raw().await?;
+            match encoded.decode::<C::Completion>()? {
+                Some(completion) if command.is_completed_by(&completion) => {
+                    return Ok(Some(completion))
+                }
+                Some(_) => {
+                    self.state.requeue(encoded);
+                    return Ok(None);
+                }
+                None => self.state.route(&mut self.router, &encoded)?,
+            }
+        }
+    }
+
+    /// Sends as many queued commands as the controller currently has room for.
+    async fn issue_queued(&mut self) -> Result<(), BleError<E>> {
+        while let Some(command) = self.state.next_command() {
+            self.write_command(&command).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn write_command(&mut self, command: &EncodedCommand) -> Result<(), BleError<E>> {
+        self.hci.write_all(&host::command_header(command)).await?;
+        self.hci.write_all(&command.parameters).await?;
+        self.hci.flush().await?;
+
+        Ok(())
+    }
+
+    /// Waits for an event, decoding as the event type `Ev` and handing any that don't match over
+    /// to the [EventRouter].
+    ///
+    /// `Ev` may borrow from the host's receive buffer, as with
+    /// [Ble::filter_poll](super::Ble::filter_poll).
+    pub async fn filter_poll<'a, Ev: EventParameters<'a>>(
+        &'a mut self,
+    ) -> Result<Option<Ev>, BleError<E>> {
+        let encoded = self.poll_raw().await?;
+
+        Ok(self.state.hold_or_route(&mut self.router, encoded)?)
+    }
+
+    /// Waits for an event and hands it over to the [EventRouter].
+    pub async fn dispatch(&mut self) -> Result<(), BleError<E>> {
+        let encoded = self.poll_raw().await?;
+        self.state.route(&mut self.router, &encoded)?;
+        Ok(())
+    }
+
+    /// Waits for an event, decoding as the event type `Ev` and leaving unmatched events
+    /// unprocessed.
+    pub async fn maybe_poll<'a, Ev: EventParameters<'a>>(
+        &'a mut self,
+    ) -> Result<Option<Ev>, BleError<E>> {
+        let encoded = self.poll_raw().await?;
+
+        Ok(self.state.hold(encoded)?)
+    }
+
+    pub async fn poll_raw(&mut self) -> Result<EncodedEvent, BleError<E>> {
+        if let Some(encoded) = self.state.take_pending() {
+            return Ok(encoded);
+        }
+
+        loop {
+            match self.poll_packet().await? {
+                HciPacket::Event(encoded) => {
+                    self.state.receive(&encoded)?;
+                    self.issue_queued().await?;
+
+                    return Ok(encoded);
+                }
+                packet => self.state.receive_data(packet),
+            }
+        }
+    }
+
+    /// Waits for a single packet of any type from the controller.
+    ///
+    /// After a [FramingError](super::packet::FramingError), bytes are skipped until something that
+    /// looks like the start of a packet is found.
+    pub async fn poll_packet(&mut self) -> Result<HciPacket, BleError<E>> {
+        loop {
+            let mut packet_type_buf = [0; 1];
+            self.read_exact(&mut packet_type_buf).await?;
+            let packet_type = PacketType(packet_type_buf[0]);
+            let Some(header_len) = self.state.check_packet_type(packet_type)? else {
+                continue;
+            };
+
+            let mut header_buf = [0; packet::MAX_HEADER_LEN];
+            let header = &mut header_buf[..header_len];
+            self.read_exact(header).await?;
+            let Some(payload_len) = self.state.check_header(packet_type, header)? else {
+                continue;
+            };
+
+            let mut payload_buf = [0; packet::MAX_PAYLOAD_LEN];
+            let payload = &mut payload_buf[..payload_len];
+            self.read_exact(payload).await?;
+
+            return Ok(HciPacket::decode(packet_type, header, payload)?);
+        }
+    }
+
+    /// Waits for the first byte of the next packet and keeps it for [AsyncBle::poll_packet]. A
+    /// single read can be dropped before it completes without losing anything, unlike the reads
+    /// of a whole packet.
+    async fn wait_for_packet(&mut self) -> Result<(), BleError<E>> {
+        if self.state.has_lookahead() {
+            return Ok(());
+        }
+
+        let mut byte = [0; 1];
+        match self.hci.read(&mut byte).await {
+            Ok(0) => Err(BleError::UnexpectedEof),
+            Ok(_) => {
+                self.state.push_lookahead(byte[0]);
+                Ok(())
+            }
+            Err(e) => Err(BleError::Io(e)),
+        }
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<E>> {
+        let len = self.state.take_lookahead(buf);
+        self.hci.read_exact(&mut buf[len..]).await
+    }
+}
@@ -0,0 +1,277 @@
+//! Capturing HCI traffic for Wireshark.
+//!
+//! [Tap] wraps the transport given to [Ble](super::Ble) and records every packet that passes
+//! through it, in either direction, to a sink such as a serial port or, on the workstation, a
+//! `Vec<u8>` or file. The capture can be written in the [Btsnoop] or the [Pcap] format.
+
+use embedded_io::{ErrorType, Read, Write};
+
+use super::{
+    packet::{H4Assembler, PacketType, MAX_H4_PACKET_LEN},
+    Clock,
+};
+
+/// The longest packet that is captured in full. Longer packets are truncated.
+pub const MAX_CAPTURE_LEN: usize = MAX_H4_PACKET_LEN;
+
+/// Microseconds from midnight, January 1st, 0 AD to the Unix epoch.
+const BTSNOOP_EPOCH_OFFSET_US: u64 = 0x00DC_DDB3_0F2F_8000;
+
+/// pcap's LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR.
+const LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR: u32 = 201;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the host to the controller.
+    Sent,
+    /// From the controller to the host.
+    Received,
+}
+
+/// A captured packet, including its H4 packet indicator.
+pub struct Record<'a> {
+    pub direction: Direction,
+    /// When the packet was captured, in microseconds since the Unix epoch (or whatever the
+    /// [Clock] counts from).
+    pub timestamp_us: u64,
+    /// The packet, truncated to [MAX_CAPTURE_LEN] bytes.
+    pub data: &'a [u8],
+    /// The length of the packet before truncation.
+    pub original_len: usize,
+    /// The number of packets that couldn't be written to the sink so far.
+    pub drops: u32,
+}
+
+/// A capture file format.
+pub trait CaptureFormat {
+    fn write_header<W: Write + ?Sized>(&self, w: &mut W) -> Result<(), W::Error>;
+    fn write_record<W: Write + ?Sized>(&self, w: &mut W, record: &Record) -> Result<(), W::Error>;
+}
+
+/// The btsnoop format, as written by Android and BlueZ, with the H4 datalink.
+pub struct Btsnoop;
+
+impl CaptureFormat for Btsnoop {
+    fn write_header<W: Write + ?Sized>(&self, w: &mut W) -> Result<(), W::Error> {
+        w.write_all(b"btsnoop\0")?;
+        // Version 1, datalink type 1002 (HCI UART).
+        w.write_all(&1u32.to_be_bytes())?;
+        w.write_all(&1002u32.to_be_bytes())
+    }
+
+    fn write_record<W: Write + ?Sized>(&self, w: &mut W, record: &Record) -> Result<(), W::Error> {
+        let mut flags: u32 = match record.direction {
+            Direction::Sent => 0,
+            Direction::Received => 1,
+        };
+        if matches!(
+            record.data.first().map(|&b| PacketType(b)),
+            Some(PacketType::COMMAND | PacketType::EVENT)
+        ) {
+            flags |= 2;
+        }
+
+        w.write_all(&(record.original_len as u32).to_be_bytes())?;
+        w.write_all(&(record.data.len() as u32).to_be_bytes())?;
+        w.write_all(&flags.to_be_bytes())?;
+        w.write_all(&record.drops.to_be_bytes())?;
+        w.write_all(&(record.timestamp_us + BTSNOOP_EPOCH_OFFSET_US).to_be_bytes())?;
+        w.write_all(record.data)
+    }
+}
+
+/// The classic pcap format with LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR.
+pub struct Pcap;
+
+impl CaptureFormat for Pcap {
+    fn write_header<W: Write + ?Sized>(&self, w: &mut W) -> Result<(), W::Error> {
+        w.write_all(&0xA1B2_C3D4u32.to_le_bytes())?;
+        // Version 2.4.
+        w.write_all(&2u16.to_le_bytes())?;
+        w.write_all(&4u16.to_le_bytes())?;
+        // Time zone offset and timestamp accuracy.
+        w.write_all(&0u32.to_le_bytes())?;
+        w.write_all(&0u32.to_le_bytes())?;
+        // Snapshot length.
+        w.write_all(&(MAX_CAPTURE_LEN as u32 + 4).to_le_bytes())?;
+        w.write_all(&LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR.to_le_bytes())
+    }
+
+    fn write_record<W: Write + ?Sized>(&self, w: &mut W, record: &Record) -> Result<(), W::Error> {
+        let direction: u32 = match record.direction {
+            Direction::Sent => 0,
+            Direction::Received => 1,
+        };
+
+        w.write_all(&((record.timestamp_us / 1_000_000) as u32).to_le_bytes())?;
+        w.write_all(&((record.timestamp_us % 1_000_000) as u32).to_le_bytes())?;
+        w.write_all(&(record.data.len() as u32 + 4).to_le_bytes())?;
+        w.write_all(&(record.original_len as u32 + 4).to_le_bytes())?;
+        // The pseudo-header is always big-endian.
+        w.write_all(&direction.to_be_bytes())?;
+        w.write_all(record.data)
+    }
+}
+
+/// Wraps an HCI transport, recording every packet that passes through it to `sink`.
+///
+/// Errors writing to the sink don't affect the transport; the affected packets are counted as
+/// drops instead.
+pub struct Tap<T, W, F, K> {
+    inner: T,
+    sink: W,
+    format: F,
+    clock: K,
+    sent: H4Assembler,
+    received: H4Assembler,
+    header_written: bool,
+    drops: u32,
+}
+
+impl<T, W, F, K> Tap<T, W, F, K>
+where
+    W: Write,
+    F: CaptureFormat,
+    K: Clock,
+{
+    /// Creates a tap that timestamps packets with `clock`, in milliseconds.
+    pub fn new(inner: T, sink: W, format: F, clock: K) -> Tap<T, W, F, K> {
+        Tap {
+            inner,
+            sink,
+            format,
+            clock,
+            sent: H4Assembler::new(),
+            received: H4Assembler::new(),
+            header_written: false,
+            drops: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn sink(&self) -> &W {
+        &self.sink
+    }
+
+    /// The number of packets that couldn't be written to the sink.
+    pub fn drops(&self) -> u32 {
+        self.drops
+    }
+
+    pub fn into_parts(self) -> (T, W) {
+        (self.inner, self.sink)
+    }
+
+    fn capture(&mut self, direction: Direction, data: &[u8]) {
+        for &byte in data {
+            let assembler = match direction {
+                Direction::Sent => &mut self.sent,
+                Direction::Received => &mut self.received,
+            };
+
+            if !assembler.push(byte) {
+                continue;
+            }
+
+            if !self.header_written {
+                self.header_written = self.format.write_header(&mut self.sink).is_ok();
+            }
+
+            let assembler = match direction {
+                Direction::Sent => &self.sent,
+                Direction::Received => &self.received,
+            };
+            let record = Record {
+                direction,
+                timestamp_us: self.clock.now_millis().saturating_mul(1000),
+                data: assembler.packet(),
+                original_len: assembler.len(),
+                drops: self.drops,
+            };
+            if !self.header_written || self.format.write_record(&mut self.sink, &record).is_err() {
+                self.drops = self.drops.saturating_add(1);
+            }
+
+            match direction {
+                Direction::Sent => self.sent.clear(),
+                Direction::Received => self.received.clear(),
+            }
+        }
+    }
+}
+
+impl<T: ErrorType, W, F, K> ErrorType for Tap<T, W, F, K> {
+    type Error = T::Error;
+}
+
+impl<T, W, F, K> Read for Tap<T, W, F, K>
+where
+    T: Read,
+    W: Write,
+    F: CaptureFormat,
+    K: Clock,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = self.inner.read(buf)?;
+        self.capture(Direction::Received, &buf[..len]);
+        Ok(len)
+    }
+}
+
+impl<T, W, F, K> Write for Tap<T, W, F, K>
+where
+    T: Write,
+    W: Write,
+    F: CaptureFormat,
+    K: Clock,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let len = self.inner.write(buf)?;
+        self.capture(Direction::Sent, &buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // The capture is flushed along with the transport, but its errors are ignored as well.
+        let _ = self.sink.flush();
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, W, F, K> embedded_io_async::Read for Tap<T, W, F, K>
+where
+    T: embedded_io_async::Read,
+    W: Write,
+    F: CaptureFormat,
+    K: Clock,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = self.inner.read(buf).await?;
+        self.capture(Direction::Received, &buf[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, W, F, K> embedded_io_async::Write for Tap<T, W, F, K>
+where
+    T: embedded_io_async::Write,
+    W: Write,
+    F: CaptureFormat,
+    K: Clock,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let len = self.inner.write(buf).await?;
+        self.capture(Direction::Sent, &buf[..len]);
+        Ok(len)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        let _ = self.sink.flush();
+        self.inner.flush().await
+    }
+}
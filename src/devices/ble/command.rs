@@ -1,11 +1,35 @@
-use super::data::{opcode::Opcode, Buffer, Encode, Encoder, EncoderFull};
+use super::data::{opcode::Opcode, Buffer, Decode, DecodeError, Encode, EncoderFull};
 
+pub mod le_clear_advertising_sets;
 pub mod le_create_connection;
+pub mod le_read_buffer_size;
+pub mod le_read_buffer_size_v2;
+pub mod le_read_local_supported_features;
+pub mod le_read_maximum_advertising_data_length;
+pub mod le_read_number_of_supported_advertising_sets;
+pub mod le_remove_advertising_set;
+pub mod le_set_advertising_data;
+pub mod le_set_advertising_enable;
+pub mod le_set_advertising_parameters;
+pub mod le_set_event_mask;
+pub mod le_set_extended_advertising_data;
+pub mod le_set_extended_advertising_enable;
+pub mod le_set_extended_advertising_parameters;
+pub mod le_set_extended_scan_enable;
+pub mod le_set_extended_scan_parameters;
+pub mod le_set_extended_scan_response_data;
 pub mod le_set_scan_enable;
 pub mod le_set_scan_parameters;
+pub mod le_set_scan_response_data;
+pub mod read_bd_addr;
+pub mod read_buffer_size;
+pub mod read_local_supported_commands;
+pub mod read_local_supported_features;
+pub mod read_local_version_information;
 pub mod reset;
 pub mod set_event_mask;
 
+#[derive(Debug)]
 pub struct AnyCommand;
 
 pub trait CommandParameters: Encode {
@@ -19,11 +43,27 @@ pub struct EncodedCommand {
 
 impl EncodedCommand {
     pub fn encode<C: Encode + HasOpcode>(command: C) -> Result<EncodedCommand, EncoderFull> {
+        Self::encode_ref(&command)
+    }
+
+    /// Like [EncodedCommand::encode], for when the command is still needed afterwards.
+    pub fn encode_ref<C: Encode + HasOpcode + ?Sized>(
+        command: &C,
+    ) -> Result<EncodedCommand, EncoderFull> {
         let opcode = command.opcode();
         let mut parameters = Buffer::new();
         command.encode(&mut parameters)?;
         Ok(EncodedCommand { opcode, parameters })
     }
+
+    /// Decodes the parameters as the command `C`, or returns `None` if this is another command.
+    pub fn decode<C: CommandParameters + Decode>(&self) -> Result<Option<C>, DecodeError> {
+        if self.opcode != C::OPCODE {
+            return Ok(None);
+        }
+
+        Ok(Some(C::decode(&mut &*self.parameters)?))
+    }
 }
 
 pub trait HasOpcode {
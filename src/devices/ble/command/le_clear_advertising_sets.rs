@@ -0,0 +1,13 @@
+use crate::devices::ble::{
+    data::{status_code::StatusCode, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Removes all advertising sets, none of which may be advertising.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x003D)]
+pub struct LeClearAdvertisingSets {}
+
+impl CommandWithCompleteEvent for LeClearAdvertisingSets {
+    type ReturnParameters = StatusCode;
+}
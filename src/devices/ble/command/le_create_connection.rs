@@ -1,11 +1,16 @@
 use crate::devices::ble::{
-    data::opcode::{Ogf, Opcode},
-    data::{address::Address, Encode, Encoder, EncoderFull},
-    event::command_status::CommandWithStatusEvent,
+    data::{address::Address, status_code::StatusError, Decode, Encode},
+    event::{
+        command_status::{CommandWithCompletionEvent, CommandWithStatusEvent},
+        le_connection_complete::LeConnectionComplete,
+    },
 };
 
-use super::CommandParameters;
+/// The role of the controller in a connection it completed as the initiator.
+const CENTRAL_ROLE: u8 = 0x00;
 
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x000D)]
 pub struct LeCreateConnection {
     pub le_scan_interval: u16,
     pub le_scan_window: u16,
@@ -21,30 +26,26 @@ pub struct LeCreateConnection {
     pub max_ce_length: u16,
 }
 
-impl Encode for LeCreateConnection {
-    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
-    where
-        E: Encoder + ?Sized,
-    {
-        e.encode(&self.le_scan_interval)?;
-        e.encode(&self.le_scan_window)?;
-        e.encode(&self.initiator_filter_policy)?;
-        e.encode(&self.peer_address_type)?;
-        e.encode(&self.peer_address)?;
-        e.encode(&self.own_address_type)?;
-        e.encode(&self.connection_interval_min)?;
-        e.encode(&self.connection_interval_max)?;
-        e.encode(&self.max_latency)?;
-        e.encode(&self.supervision_timeout)?;
-        e.encode(&self.min_ce_length)?;
-        e.encode(&self.max_ce_length)?;
+impl CommandWithStatusEvent for LeCreateConnection {}
 
-        Ok(())
-    }
-}
+impl CommandWithCompletionEvent for LeCreateConnection {
+    type Completion = LeConnectionComplete;
 
-impl CommandParameters for LeCreateConnection {
-    const OPCODE: Opcode = Opcode::new(Ogf::LE_CONTROLLER, 0x000D);
+    /// A failed attempt reports no valid peer or role, e.g. after LE Create Connection Cancel, so
+    /// any failure is ours except the Advertising Timeout that ends directed advertising.
+    /// Otherwise the connection has to be one we made as central: with the filter accept list,
+    /// any such connection is ours, and otherwise it has to be to the peer we asked for. The
+    /// address type isn't compared, since the controller may report a resolved identity address
+    /// type instead.
+    fn is_completed_by(&self, completion: &LeConnectionComplete) -> bool {
+        match completion.status.error() {
+            Some(StatusError::AdvertisingTimeout) => false,
+            Some(_) => true,
+            None => {
+                completion.role == CENTRAL_ROLE
+                    && (self.initiator_filter_policy != 0x00
+                        || completion.peer_address == self.peer_address)
+            }
+        }
+    }
 }
-
-impl CommandWithStatusEvent for LeCreateConnection {}
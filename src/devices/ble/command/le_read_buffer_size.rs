@@ -0,0 +1,20 @@
+use crate::devices::ble::{
+    data::{status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0002)]
+pub struct LeReadBufferSize {}
+
+impl CommandWithCompleteEvent for LeReadBufferSize {
+    type ReturnParameters = WithStatus<LeBufferSize>;
+}
+
+/// A length of 0 means that the controller has no separate buffers for LE, and that
+/// [ReadBufferSize](super::read_buffer_size::ReadBufferSize) tells about the shared ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct LeBufferSize {
+    pub le_acl_data_packet_length: u16,
+    pub total_num_le_acl_data_packets: u8,
+}
@@ -0,0 +1,22 @@
+use crate::devices::ble::{
+    data::{status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Like [LeReadBufferSize](super::le_read_buffer_size::LeReadBufferSize), but also tells about
+/// the buffers for isochronous data.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0060)]
+pub struct LeReadBufferSizeV2 {}
+
+impl CommandWithCompleteEvent for LeReadBufferSizeV2 {
+    type ReturnParameters = WithStatus<LeBufferSizeV2>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct LeBufferSizeV2 {
+    pub le_acl_data_packet_length: u16,
+    pub total_num_le_acl_data_packets: u8,
+    pub iso_data_packet_length: u16,
+    pub total_num_iso_data_packets: u8,
+}
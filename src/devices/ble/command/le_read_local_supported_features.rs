@@ -0,0 +1,12 @@
+use crate::devices::ble::{
+    data::{features::LeFeatures, status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0003)]
+pub struct LeReadLocalSupportedFeatures {}
+
+impl CommandWithCompleteEvent for LeReadLocalSupportedFeatures {
+    type ReturnParameters = WithStatus<LeFeatures>;
+}
@@ -0,0 +1,13 @@
+use crate::devices::ble::{
+    data::{status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Reads the longest advertising or scan response data an advertising set can have.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x003A)]
+pub struct LeReadMaximumAdvertisingDataLength {}
+
+impl CommandWithCompleteEvent for LeReadMaximumAdvertisingDataLength {
+    type ReturnParameters = WithStatus<u16>;
+}
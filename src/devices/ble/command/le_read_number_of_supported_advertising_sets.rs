@@ -0,0 +1,13 @@
+use crate::devices::ble::{
+    data::{status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Reads how many advertising sets the controller can have at once.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x003B)]
+pub struct LeReadNumberOfSupportedAdvertisingSets {}
+
+impl CommandWithCompleteEvent for LeReadNumberOfSupportedAdvertisingSets {
+    type ReturnParameters = WithStatus<u8>;
+}
@@ -0,0 +1,15 @@
+use crate::devices::ble::{
+    data::{status_code::StatusCode, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Removes an advertising set that isn't advertising.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x003C)]
+pub struct LeRemoveAdvertisingSet {
+    pub advertising_handle: u8,
+}
+
+impl CommandWithCompleteEvent for LeRemoveAdvertisingSet {
+    type ReturnParameters = StatusCode;
+}
@@ -0,0 +1,14 @@
+use crate::devices::ble::{
+    data::{status_code::StatusCode, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x000A)]
+pub struct LeSetAdvertisingEnable {
+    pub advertising_enable: u8,
+}
+
+impl CommandWithCompleteEvent for LeSetAdvertisingEnable {
+    type ReturnParameters = StatusCode;
+}
@@ -0,0 +1,21 @@
+use crate::devices::ble::{
+    data::{address::Address, status_code::StatusCode, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0006)]
+pub struct LeSetAdvertisingParameters {
+    pub advertising_interval_min: u16,
+    pub advertising_interval_max: u16,
+    pub advertising_type: u8,
+    pub own_address_type: u8,
+    pub peer_address_type: u8,
+    pub peer_address: Address,
+    pub advertising_channel_map: u8,
+    pub advertising_filter_policy: u8,
+}
+
+impl CommandWithCompleteEvent for LeSetAdvertisingParameters {
+    type ReturnParameters = StatusCode;
+}
@@ -0,0 +1,14 @@
+use crate::devices::ble::{
+    data::{event_mask::LeEventMask, status_code::StatusCode, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0001)]
+pub struct LeSetEventMask {
+    pub le_event_mask: LeEventMask,
+}
+
+impl CommandWithCompleteEvent for LeSetEventMask {
+    type ReturnParameters = StatusCode;
+}
@@ -0,0 +1,23 @@
+use crate::devices::ble::{
+    data::{
+        advertising_data::{AdvertisingDataFragment, AdvertisingDataOperation},
+        status_code::StatusCode,
+        Decode, Encode,
+    },
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Sets a piece of the data of an advertising set. Data longer than one command can carry is
+/// split up with [fragments](crate::devices::ble::data::advertising_data::fragments).
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0037)]
+pub struct LeSetExtendedAdvertisingData {
+    pub advertising_handle: u8,
+    pub operation: AdvertisingDataOperation,
+    pub fragment_preference: u8,
+    pub advertising_data: AdvertisingDataFragment,
+}
+
+impl CommandWithCompleteEvent for LeSetExtendedAdvertisingData {
+    type ReturnParameters = StatusCode;
+}
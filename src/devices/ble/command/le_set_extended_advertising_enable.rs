@@ -0,0 +1,86 @@
+use heapless::Vec;
+
+use crate::devices::ble::{
+    data::{status_code::StatusCode, Decode, DecodeError, Decoder, Encode, Encoder, EncoderFull},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// The most advertising sets one command can enable or disable.
+pub const MAX_ADVERTISING_SETS: usize = 0x3F;
+
+/// Enables or disables advertising sets. Disabling no sets at all disables every set.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0039)]
+pub struct LeSetExtendedAdvertisingEnable {
+    pub enable: u8,
+    pub sets: AdvertisingSetEnables,
+}
+
+impl CommandWithCompleteEvent for LeSetExtendedAdvertisingEnable {
+    type ReturnParameters = StatusCode;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdvertisingSetEnable {
+    pub advertising_handle: u8,
+    /// In units of 10 ms, or 0 to advertise until disabled.
+    pub duration: u16,
+    /// 0 to advertise until disabled.
+    pub max_extended_advertising_events: u8,
+}
+
+/// The sets of an [LeSetExtendedAdvertisingEnable], sent as one array per field after their
+/// number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdvertisingSetEnables(pub Vec<AdvertisingSetEnable, MAX_ADVERTISING_SETS>);
+
+impl Encode for AdvertisingSetEnables {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&(self.0.len() as u8))?;
+        for set in &self.0 {
+            e.encode(&set.advertising_handle)?;
+        }
+        for set in &self.0 {
+            e.encode(&set.duration)?;
+        }
+        for set in &self.0 {
+            e.encode(&set.max_extended_advertising_events)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode for AdvertisingSetEnables {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        let num_sets = d.decode::<u8>()? as usize;
+        if num_sets > MAX_ADVERTISING_SETS {
+            return Err(DecodeError::Malformed(
+                "more advertising sets than one command can enable",
+            ));
+        }
+
+        let mut sets = Vec::new();
+        for _ in 0..num_sets {
+            let set = AdvertisingSetEnable {
+                advertising_handle: d.decode()?,
+                duration: 0,
+                max_extended_advertising_events: 0,
+            };
+            // There is room for as many as were checked for above.
+            let _ = sets.push(set);
+        }
+        for set in &mut sets {
+            set.duration = d.decode()?;
+        }
+        for set in &mut sets {
+            set.max_extended_advertising_events = d.decode()?;
+        }
+        Ok(AdvertisingSetEnables(sets))
+    }
+}
@@ -0,0 +1,44 @@
+use crate::devices::ble::{
+    data::{address::Address, status_code::WithStatus, Decode, Encode, U24},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Bits of [LeSetExtendedAdvertisingParameters::advertising_event_properties].
+pub const CONNECTABLE: u16 = 0x0001;
+pub const SCANNABLE: u16 = 0x0002;
+pub const DIRECTED: u16 = 0x0004;
+pub const HIGH_DUTY_CYCLE_DIRECTED: u16 = 0x0008;
+/// Advertises with legacy PDUs, which carry at most 31 bytes of data.
+pub const LEGACY: u16 = 0x0010;
+pub const ANONYMOUS: u16 = 0x0020;
+pub const INCLUDE_TX_POWER: u16 = 0x0040;
+
+/// An [LeSetExtendedAdvertisingParameters::advertising_tx_power] that lets the controller choose.
+pub const NO_TX_POWER_PREFERENCE: i8 = 0x7F;
+
+/// Sets up the advertising set `advertising_handle`, creating it if it doesn't exist yet.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0036)]
+pub struct LeSetExtendedAdvertisingParameters {
+    pub advertising_handle: u8,
+    pub advertising_event_properties: u16,
+    pub primary_advertising_interval_min: U24,
+    pub primary_advertising_interval_max: U24,
+    pub primary_advertising_channel_map: u8,
+    pub own_address_type: u8,
+    pub peer_address_type: u8,
+    pub peer_address: Address,
+    pub advertising_filter_policy: u8,
+    /// In dBm, or [NO_TX_POWER_PREFERENCE].
+    pub advertising_tx_power: i8,
+    pub primary_advertising_phy: u8,
+    pub secondary_advertising_max_skip: u8,
+    pub secondary_advertising_phy: u8,
+    pub advertising_sid: u8,
+    pub scan_request_notification_enable: u8,
+}
+
+impl CommandWithCompleteEvent for LeSetExtendedAdvertisingParameters {
+    /// The transmit power the controller chose, in dBm.
+    type ReturnParameters = WithStatus<i8>;
+}
@@ -0,0 +1,21 @@
+use crate::devices::ble::{
+    data::{status_code::StatusCode, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Starts or stops scanning with the parameters from
+/// [LeSetExtendedScanParameters](super::le_set_extended_scan_parameters::LeSetExtendedScanParameters).
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0042)]
+pub struct LeSetExtendedScanEnable {
+    pub enable: u8,
+    pub filter_duplicates: u8,
+    /// In units of 10 ms, or 0 to scan until disabled.
+    pub duration: u16,
+    /// In units of 1.28 s, or 0 to scan only once for `duration`.
+    pub period: u16,
+}
+
+impl CommandWithCompleteEvent for LeSetExtendedScanEnable {
+    type ReturnParameters = StatusCode;
+}
@@ -0,0 +1,112 @@
+use crate::devices::ble::{
+    data::{status_code::StatusCode, Decode, DecodeError, Decoder, Encode, Encoder, EncoderFull},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Bits of the Scanning_PHYs parameter.
+const LE_1M: u8 = 0x01;
+const LE_CODED: u8 = 0x04;
+
+/// Sets how to scan on each primary advertising PHY. Unlike legacy scanning, this finds
+/// advertisements on the LE Coded PHY and extended advertisements with long data.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0041)]
+pub struct LeSetExtendedScanParameters {
+    pub own_address_type: u8,
+    pub scanning_filter_policy: u8,
+    pub phys: ScanningPhys,
+}
+
+impl CommandWithCompleteEvent for LeSetExtendedScanParameters {
+    type ReturnParameters = StatusCode;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct PhyScanParameters {
+    pub scan_type: u8,
+    pub scan_interval: u16,
+    pub scan_window: u16,
+}
+
+/// The PHYs to scan on, each with its own parameters. At least one has to be given.
+///
+/// They are sent as a bit per PHY, followed by one array per field with an entry per PHY.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanningPhys {
+    pub le_1m: Option<PhyScanParameters>,
+    pub le_coded: Option<PhyScanParameters>,
+}
+
+impl ScanningPhys {
+    fn parameters(&self) -> impl Iterator<Item = &PhyScanParameters> {
+        self.le_1m.iter().chain(self.le_coded.iter())
+    }
+}
+
+impl Encode for ScanningPhys {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        let mut scanning_phys = 0;
+        if self.le_1m.is_some() {
+            scanning_phys |= LE_1M;
+        }
+        if self.le_coded.is_some() {
+            scanning_phys |= LE_CODED;
+        }
+
+        e.encode(&scanning_phys)?;
+        for phy in self.parameters() {
+            e.encode(&phy.scan_type)?;
+        }
+        for phy in self.parameters() {
+            e.encode(&phy.scan_interval)?;
+        }
+        for phy in self.parameters() {
+            e.encode(&phy.scan_window)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode for ScanningPhys {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        let scanning_phys: u8 = d.decode()?;
+        if scanning_phys & !(LE_1M | LE_CODED) != 0 {
+            return Err(DecodeError::Malformed(
+                "scanning on a PHY that can't be scanned",
+            ));
+        }
+
+        let mut parameters = [PhyScanParameters {
+            scan_type: 0,
+            scan_interval: 0,
+            scan_window: 0,
+        }; 2];
+        let parameters = &mut parameters[..scanning_phys.count_ones() as usize];
+        for phy in parameters.iter_mut() {
+            phy.scan_type = d.decode()?;
+        }
+        for phy in parameters.iter_mut() {
+            phy.scan_interval = d.decode()?;
+        }
+        for phy in parameters.iter_mut() {
+            phy.scan_window = d.decode()?;
+        }
+
+        // The entries are in the order of the bits.
+        let mut parameters = parameters.iter().copied();
+        let mut next_if = |bit| match scanning_phys & bit {
+            0 => None,
+            _ => parameters.next(),
+        };
+        Ok(ScanningPhys {
+            le_1m: next_if(LE_1M),
+            le_coded: next_if(LE_CODED),
+        })
+    }
+}
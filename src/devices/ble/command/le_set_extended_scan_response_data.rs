@@ -0,0 +1,23 @@
+use crate::devices::ble::{
+    data::{
+        advertising_data::{AdvertisingDataFragment, AdvertisingDataOperation},
+        status_code::StatusCode,
+        Decode, Encode,
+    },
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Like [LeSetExtendedAdvertisingData](super::le_set_extended_advertising_data::LeSetExtendedAdvertisingData),
+/// for the data sent in response to scan requests.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0038)]
+pub struct LeSetExtendedScanResponseData {
+    pub advertising_handle: u8,
+    pub operation: AdvertisingDataOperation,
+    pub fragment_preference: u8,
+    pub scan_response_data: AdvertisingDataFragment,
+}
+
+impl CommandWithCompleteEvent for LeSetExtendedScanResponseData {
+    type ReturnParameters = StatusCode;
+}
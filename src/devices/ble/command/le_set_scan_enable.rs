@@ -1,36 +1,15 @@
 use crate::devices::ble::{
-    data::{
-        opcode::{Ogf, Opcode},
-        status_code::StatusCode,
-        Encode, Encoder, EncoderFull,
-    },
+    data::{status_code::StatusCode, Decode, Encode},
     event::command_complete::CommandWithCompleteEvent,
 };
 
-use super::CommandParameters;
-
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x000C)]
 pub struct LeSetScanEnable {
     pub le_scan_enable: u8,
     pub filter_duplicates: u8,
 }
 
-impl Encode for LeSetScanEnable {
-    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
-    where
-        E: Encoder + ?Sized,
-    {
-        e.encode(&self.le_scan_enable)?;
-        e.encode(&self.filter_duplicates)?;
-
-        Ok(())
-    }
-}
-
-impl CommandParameters for LeSetScanEnable {
-    const OPCODE: Opcode = Opcode::new(Ogf::LE_CONTROLLER, 0x000C);
-}
-
 impl CommandWithCompleteEvent for LeSetScanEnable {
     type ReturnParameters = StatusCode;
 }
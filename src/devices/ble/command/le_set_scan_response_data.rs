@@ -0,0 +1,14 @@
+use crate::devices::ble::{
+    data::{advertising_data::AdvertisingData, status_code::StatusCode, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = LE_CONTROLLER, ocf = 0x0009)]
+pub struct LeSetScanResponseData {
+    pub scan_response_data: AdvertisingData,
+}
+
+impl CommandWithCompleteEvent for LeSetScanResponseData {
+    type ReturnParameters = StatusCode;
+}
@@ -0,0 +1,13 @@
+use crate::devices::ble::{
+    data::{address::Address, status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Reads the controller's public device address.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = INFORMATIONAL_PARAMETERS, ocf = 0x0009)]
+pub struct ReadBdAddr {}
+
+impl CommandWithCompleteEvent for ReadBdAddr {
+    type ReturnParameters = WithStatus<Address>;
+}
@@ -0,0 +1,22 @@
+use crate::devices::ble::{
+    data::{status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+/// Reads the sizes of the buffers that LE shares with BR/EDR, for controllers without separate
+/// LE buffers.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = INFORMATIONAL_PARAMETERS, ocf = 0x0005)]
+pub struct ReadBufferSize {}
+
+impl CommandWithCompleteEvent for ReadBufferSize {
+    type ReturnParameters = WithStatus<BufferSize>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct BufferSize {
+    pub acl_data_packet_length: u16,
+    pub synchronous_data_packet_length: u8,
+    pub total_num_acl_data_packets: u16,
+    pub total_num_synchronous_data_packets: u16,
+}
@@ -0,0 +1,12 @@
+use crate::devices::ble::{
+    data::{status_code::WithStatus, supported_commands::SupportedCommands, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = INFORMATIONAL_PARAMETERS, ocf = 0x0002)]
+pub struct ReadLocalSupportedCommands {}
+
+impl CommandWithCompleteEvent for ReadLocalSupportedCommands {
+    type ReturnParameters = WithStatus<SupportedCommands>;
+}
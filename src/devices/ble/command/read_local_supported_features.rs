@@ -0,0 +1,12 @@
+use crate::devices::ble::{
+    data::{features::LmpFeatures, status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = INFORMATIONAL_PARAMETERS, ocf = 0x0003)]
+pub struct ReadLocalSupportedFeatures {}
+
+impl CommandWithCompleteEvent for ReadLocalSupportedFeatures {
+    type ReturnParameters = WithStatus<LmpFeatures>;
+}
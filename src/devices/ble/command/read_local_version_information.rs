@@ -0,0 +1,22 @@
+use crate::devices::ble::{
+    data::{status_code::WithStatus, Decode, Encode},
+    event::command_complete::CommandWithCompleteEvent,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = INFORMATIONAL_PARAMETERS, ocf = 0x0001)]
+pub struct ReadLocalVersionInformation {}
+
+impl CommandWithCompleteEvent for ReadLocalVersionInformation {
+    type ReturnParameters = WithStatus<LocalVersionInformation>;
+}
+
+/// The versions are the assigned numbers of the Core specification versions, e.g. 0x0D for 5.4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct LocalVersionInformation {
+    pub hci_version: u8,
+    pub hci_subversion: u16,
+    pub lmp_version: u8,
+    pub company_identifier: u16,
+    pub lmp_subversion: u16,
+}
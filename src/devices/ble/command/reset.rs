@@ -1,29 +1,12 @@
 use crate::devices::ble::{
-    data::{
-        opcode::{Ogf, Opcode},
-        status_code::StatusCode,
-        Encode, Encoder, EncoderFull,
-    },
+    data::{status_code::StatusCode, Decode, Encode},
     event::command_complete::CommandWithCompleteEvent,
 };
 
-use super::CommandParameters;
-
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = CONTROLLER_BASEBAND, ocf = 0x0003)]
 pub struct Reset {}
 
-impl Encode for Reset {
-    fn encode<E>(&self, _e: &mut E) -> Result<(), EncoderFull>
-    where
-        E: Encoder + ?Sized,
-    {
-        Ok(())
-    }
-}
-
-impl CommandParameters for Reset {
-    const OPCODE: Opcode = Opcode::new(Ogf::CONTROLLER_BASEBAND, 0x0003);
-}
-
 impl CommandWithCompleteEvent for Reset {
     type ReturnParameters = StatusCode;
 }
@@ -1,31 +1,12 @@
 use crate::devices::ble::{
-    data::{
-        opcode::{Ogf, Opcode},
-        status_code::StatusCode,
-        Encode, Encoder, EncoderFull,
-    },
+    data::{event_mask::EventMask, status_code::StatusCode, Decode, Encode},
     event::command_complete::CommandWithCompleteEvent,
 };
 
-use super::CommandParameters;
-
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[opcode(ogf = CONTROLLER_BASEBAND, ocf = 0x0001)]
 pub struct SetEventMask {
-    pub mask: u64,
-}
-
-impl Encode for SetEventMask {
-    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
-    where
-        E: Encoder + ?Sized,
-    {
-        e.encode(&self.mask)?;
-
-        Ok(())
-    }
-}
-
-impl CommandParameters for SetEventMask {
-    const OPCODE: Opcode = Opcode::new(Ogf::CONTROLLER_BASEBAND, 0x0001);
+    pub mask: EventMask,
 }
 
 impl CommandWithCompleteEvent for SetEventMask {
@@ -0,0 +1,31 @@
+//! What the host knows about the controller once it has been probed with
+//! [Ble::probe_controller](super::Ble::probe_controller), so that commands the controller doesn't
+//! support fail with [BleError::Unsupported](super::BleError::Unsupported) before they are sent.
+
+use super::{
+    command::read_local_version_information::LocalVersionInformation,
+    data::{
+        address::Address,
+        features::{LeFeatures, LmpFeatures},
+        opcode::Opcode,
+        supported_commands::SupportedCommands,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerInfo {
+    pub version: LocalVersionInformation,
+    /// The public device address.
+    pub address: Address,
+    pub commands: SupportedCommands,
+    pub features: LmpFeatures,
+    pub le_features: LeFeatures,
+}
+
+impl ControllerInfo {
+    /// Whether the controller supports the command with `opcode`. Commands whose bit in
+    /// [SupportedCommands] isn't known, such as vendor commands, are assumed to be supported.
+    pub fn supports(&self, opcode: Opcode) -> bool {
+        self.commands.supports(opcode).unwrap_or(true)
+    }
+}
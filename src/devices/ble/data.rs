@@ -1,8 +1,14 @@
 use core::ops::Deref;
 
 pub mod address;
+pub mod advertising_data;
+pub mod event_mask;
+pub mod features;
 pub mod opcode;
 pub mod status_code;
+pub mod supported_commands;
+
+pub use wable_macros::{Decode, Encode};
 
 pub type Buffer<const MAX: usize> = _Buffer<[u8; MAX]>;
 
@@ -10,7 +16,7 @@ pub type Buf = _Buffer<[u8]>;
 
 mod private {
     #[doc(hidden)]
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct _Buffer<D: ?Sized> {
         pub(super) len: usize,
         pub(super) data: D,
@@ -44,17 +50,23 @@ impl<const MAX: usize> Deref for Buffer<MAX> {
     }
 }
 
-impl<'a, const MAX: usize> From<&'a [u8]> for Buffer<MAX> {
-    fn from(value: &'a [u8]) -> Self {
-        let len = value.len();
-        if len > MAX {
-            panic!(
-                "cannot create buffer from oversized source slice (len was {len}, max was {MAX})"
-            )
-        }
-        let mut data = [0; MAX];
-        data[..len].copy_from_slice(value);
-        Buffer { len, data }
+/// Buffers are equal if their contents are, whatever was in them before.
+impl<const MAX: usize> PartialEq for Buffer<MAX> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<const MAX: usize> Eq for Buffer<MAX> {}
+
+/// Copies a slice into a buffer, failing with [EncoderFull] if it's longer than `MAX`.
+impl<'a, const MAX: usize> TryFrom<&'a [u8]> for Buffer<MAX> {
+    type Error = EncoderFull;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut buffer = Buffer::new();
+        buffer.write(value)?;
+        Ok(buffer)
     }
 }
 
@@ -67,30 +79,108 @@ pub trait Encode {
         E: Encoder + ?Sized;
 }
 
-impl Encode for u8 {
-    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+impl Encode for () {
+    fn encode<E>(&self, _e: &mut E) -> Result<(), EncoderFull>
     where
         E: Encoder + ?Sized,
     {
-        e.write(&[*self])
+        Ok(())
     }
 }
 
-impl Encode for u16 {
+/// Integers are little-endian, as everywhere in the HCI.
+macro_rules! impl_integers {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+                where
+                    E: Encoder + ?Sized,
+                {
+                    e.write(&self.to_le_bytes())
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+                where
+                    D: Decoder + ?Sized,
+                {
+                    Ok(<$ty>::from_le_bytes(d.decode()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_integers!(u8, i8, u16, i16, u32, u64);
+
+/// An unsigned 24-bit integer, such as a class of device or some of the advertising intervals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct U24(u32);
+
+impl U24 {
+    pub const MAX: U24 = U24(0xFF_FFFF);
+
+    /// Returns `None` if `value` doesn't fit in 24 bits.
+    pub const fn new(value: u32) -> Option<U24> {
+        if value <= Self::MAX.0 {
+            Some(U24(value))
+        } else {
+            None
+        }
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<U24> for u32 {
+    fn from(value: U24) -> u32 {
+        value.0
+    }
+}
+
+impl Encode for U24 {
     fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
     where
         E: Encoder + ?Sized,
     {
-        e.write(&self.to_le_bytes())
+        e.write(&self.0.to_le_bytes()[..3])
+    }
+}
+
+impl Decode for U24 {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        let [b0, b1, b2] = d.decode::<[u8; 3]>()?;
+        Ok(U24(u32::from_le_bytes([b0, b1, b2, 0])))
     }
 }
 
-impl Encode for u64 {
+/// Booleans are a single byte, 0x00 or 0x01.
+impl Encode for bool {
     fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
     where
         E: Encoder + ?Sized,
     {
-        e.write(&self.to_le_bytes())
+        e.encode(&u8::from(*self))
+    }
+}
+
+impl Decode for bool {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        match d.decode::<u8>()? {
+            0x00 => Ok(false),
+            0x01 => Ok(true),
+            _ => Err(DecodeError::Malformed("a boolean is neither 0x00 nor 0x01")),
+        }
     }
 }
 
@@ -159,7 +249,7 @@ pub trait Decode: Sized {
 }
 
 impl Decode for () {
-    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    fn decode<D>(_d: &mut D) -> Result<Self, DecodeError>
     where
         D: Decoder + ?Sized,
     {
@@ -167,24 +257,6 @@ impl Decode for () {
     }
 }
 
-impl Decode for u8 {
-    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
-    where
-        D: Decoder + ?Sized,
-    {
-        Ok(d.decode::<[u8; 1]>()?[0])
-    }
-}
-
-impl Decode for u16 {
-    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
-    where
-        D: Decoder + ?Sized,
-    {
-        Ok(u16::from_le_bytes(d.decode::<[u8; 2]>()?))
-    }
-}
-
 impl<const N: usize> Decode for [u8; N] {
     fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
     where
@@ -233,13 +305,28 @@ where
     }
 }
 
+/// Decoding straight from a byte slice, which lets the decoded value borrow from it instead of
+/// copying out of it. Everything that implements [MaybeDecode] can also be decoded this way.
+pub trait MaybeDecodeRef<'a>: Sized {
+    fn maybe_decode_ref(d: &mut &'a [u8]) -> Result<Option<Self>, DecodeError>;
+}
+
+impl<'a, T> MaybeDecodeRef<'a> for T
+where
+    T: MaybeDecode,
+{
+    fn maybe_decode_ref(d: &mut &'a [u8]) -> Result<Option<Self>, DecodeError> {
+        T::maybe_decode(d)
+    }
+}
+
 pub trait Decoder {
     fn read(&mut self, buf: &mut [u8]) -> Result<(), DecodeError>;
     fn available(&self) -> usize;
 
     fn decode<D>(&mut self) -> Result<D, DecodeError>
     where
-        D: Decode + ?Sized,
+        D: Decode,
     {
         D::decode(self)
     }
@@ -249,14 +336,14 @@ pub trait MaybeDecoder: Decoder {
     fn maybe_decode<D>(&mut self) -> Result<Option<D>, DecodeError>
     where
         Self: Sized,
-        D: MaybeDecode + ?Sized;
+        D: MaybeDecode;
 }
 
 impl<T: Decoder + Copy> MaybeDecoder for T {
     fn maybe_decode<D>(&mut self) -> Result<Option<D>, DecodeError>
     where
         Self: Sized,
-        D: MaybeDecode + ?Sized,
+        D: MaybeDecode,
     {
         let mut copy = *self;
         if let Some(result) = D::maybe_decode(&mut copy)? {
@@ -268,7 +355,7 @@ impl<T: Decoder + Copy> MaybeDecoder for T {
     }
 }
 
-impl<'a> Decoder for &'a [u8] {
+impl Decoder for &[u8] {
     fn read(&mut self, buf: &mut [u8]) -> Result<(), DecodeError> {
         if self.len() < buf.len() {
             return Err(DecodeError::Empty);
@@ -1,5 +1,6 @@
 use super::{Decode, Encode};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Address(pub [u8; 6]);
 
 impl Encode for Address {
@@ -0,0 +1,212 @@
+use super::{Buffer, Decode, DecodeError, Decoder, Encode, Encoder, EncoderFull};
+
+/// The longest advertising or scan response data a legacy advertisement can carry.
+pub const MAX_ADVERTISING_DATA_LEN: usize = 31;
+
+/// Bits of the data of an [AdType::FLAGS] structure.
+pub const LE_LIMITED_DISCOVERABLE_MODE: u8 = 0x01;
+pub const LE_GENERAL_DISCOVERABLE_MODE: u8 = 0x02;
+pub const BR_EDR_NOT_SUPPORTED: u8 = 0x04;
+
+/// The type of an AD structure, from the Assigned Numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdType(pub u8);
+
+impl AdType {
+    pub const FLAGS: AdType = AdType(0x01);
+    pub const INCOMPLETE_LIST_OF_16_BIT_UUIDS: AdType = AdType(0x02);
+    pub const COMPLETE_LIST_OF_16_BIT_UUIDS: AdType = AdType(0x03);
+    pub const SHORTENED_LOCAL_NAME: AdType = AdType(0x08);
+    pub const COMPLETE_LOCAL_NAME: AdType = AdType(0x09);
+    pub const TX_POWER_LEVEL: AdType = AdType(0x0A);
+    pub const APPEARANCE: AdType = AdType(0x19);
+    pub const MANUFACTURER_SPECIFIC_DATA: AdType = AdType(0xFF);
+}
+
+/// Advertising or scan response data for legacy advertising: a sequence of AD structures, each
+/// a length, an [AdType] and its data (Core v5.4, Vol 3, Part C, 11).
+///
+/// It is sent padded to [MAX_ADVERTISING_DATA_LEN] bytes, as the commands that carry it require.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdvertisingData {
+    data: Buffer<MAX_ADVERTISING_DATA_LEN>,
+}
+
+impl AdvertisingData {
+    pub fn new() -> AdvertisingData {
+        AdvertisingData {
+            data: Buffer::new(),
+        }
+    }
+
+    /// Adds an AD structure, failing if it doesn't fit.
+    pub fn push(&mut self, ad_type: AdType, data: &[u8]) -> Result<(), EncoderFull> {
+        if self.data.len() + 2 + data.len() > MAX_ADVERTISING_DATA_LEN {
+            return Err(EncoderFull);
+        }
+
+        self.data.encode(&(1 + data.len() as u8))?;
+        self.data.encode(&ad_type.0)?;
+        self.data.write(data)
+    }
+
+    /// Like [AdvertisingData::push], for chaining.
+    pub fn with(mut self, ad_type: AdType, data: &[u8]) -> Result<AdvertisingData, EncoderFull> {
+        self.push(ad_type, data)?;
+        Ok(self)
+    }
+
+    /// The AD structures, without padding.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Default for AdvertisingData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Takes the data as is, whether or not it is made of well-formed AD structures.
+impl TryFrom<&[u8]> for AdvertisingData {
+    type Error = EncoderFull;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Ok(AdvertisingData {
+            data: Buffer::try_from(data)?,
+        })
+    }
+}
+
+impl Encode for AdvertisingData {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&(self.data.len() as u8))?;
+        e.write(&self.data)?;
+        e.write(&[0; MAX_ADVERTISING_DATA_LEN][self.data.len()..])
+    }
+}
+
+impl Decode for AdvertisingData {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        let len: u8 = d.decode()?;
+        let padded: [u8; MAX_ADVERTISING_DATA_LEN] = d.decode()?;
+        let data = padded.get(..len as usize).ok_or(DecodeError::Malformed(
+            "advertising data is longer than 31 bytes",
+        ))?;
+
+        let mut advertising_data = AdvertisingData::new();
+        // It's no longer than the padded data, so it fits.
+        let _ = advertising_data.data.write(data);
+        Ok(advertising_data)
+    }
+}
+
+/// The longest extended advertising or scan response data, whether sent or received.
+pub const MAX_EXTENDED_ADVERTISING_DATA_LEN: usize = 1650;
+
+/// The longest piece of extended advertising or scan response data that one command carries.
+pub const MAX_ADVERTISING_DATA_FRAGMENT_LEN: usize = 251;
+
+/// Which part of an advertising set's data a command carries (Core v5.4, Vol 4, Part E, 7.8.54).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum AdvertisingDataOperation {
+    Intermediate = 0x00,
+    First = 0x01,
+    Last = 0x02,
+    Complete = 0x03,
+    /// Keeps the data but gives it a new Advertising DID, for periodic broadcasts whose content
+    /// changed in a way the data doesn't show.
+    Unchanged = 0x04,
+}
+
+/// Splits `data` into pieces that each fit into one command, with the operation that tells the
+/// controller where each belongs. Empty data is a single, empty, complete piece.
+pub fn fragments(
+    data: &[u8],
+) -> impl Iterator<Item = (AdvertisingDataOperation, AdvertisingDataFragment)> + '_ {
+    let count = data
+        .len()
+        .div_ceil(MAX_ADVERTISING_DATA_FRAGMENT_LEN)
+        .max(1);
+    (0..count).map(move |i| {
+        let operation = match (i == 0, i == count - 1) {
+            (true, true) => AdvertisingDataOperation::Complete,
+            (true, false) => AdvertisingDataOperation::First,
+            (false, true) => AdvertisingDataOperation::Last,
+            (false, false) => AdvertisingDataOperation::Intermediate,
+        };
+
+        let start = i * MAX_ADVERTISING_DATA_FRAGMENT_LEN;
+        let end = data.len().min(start + MAX_ADVERTISING_DATA_FRAGMENT_LEN);
+        let mut fragment = AdvertisingDataFragment {
+            data: Buffer::new(),
+        };
+        // The pieces are cut to fit.
+        let _ = fragment.data.write(&data[start..end]);
+        (operation, fragment)
+    })
+}
+
+/// A piece of extended advertising or scan response data, sent with its length and without
+/// padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdvertisingDataFragment {
+    data: Buffer<MAX_ADVERTISING_DATA_FRAGMENT_LEN>,
+}
+
+impl AdvertisingDataFragment {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl TryFrom<&[u8]> for AdvertisingDataFragment {
+    type Error = EncoderFull;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Ok(AdvertisingDataFragment {
+            data: Buffer::try_from(data)?,
+        })
+    }
+}
+
+impl Encode for AdvertisingDataFragment {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&(self.data.len() as u8))?;
+        e.write(&self.data)
+    }
+}
+
+impl Decode for AdvertisingDataFragment {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        let len = d.decode::<u8>()? as usize;
+        if len > MAX_ADVERTISING_DATA_FRAGMENT_LEN {
+            return Err(DecodeError::Malformed(
+                "advertising data fragment is longer than 251 bytes",
+            ));
+        }
+
+        let mut data = [0; MAX_ADVERTISING_DATA_FRAGMENT_LEN];
+        d.read(&mut data[..len])?;
+
+        let mut fragment = AdvertisingDataFragment {
+            data: Buffer::new(),
+        };
+        // It's no longer than the buffer, so it fits.
+        let _ = fragment.data.write(&data[..len]);
+        Ok(fragment)
+    }
+}
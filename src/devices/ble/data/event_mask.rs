@@ -0,0 +1,218 @@
+use crate::devices::ble::event::{EventCode, EventParameters};
+
+/// Defines a bitflag type over a `u64` with a constant for each named bit.
+macro_rules! mask {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $($(#[$bit_meta:meta])* $bit:literal => $flag:ident,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        pub struct $name(pub u64);
+
+        impl $name {
+            pub const NONE: $name = $name(0);
+            $($(#[$bit_meta])* pub const $flag: $name = $name(1 << $bit);)*
+
+            pub const fn union(self, other: $name) -> $name {
+                $name(self.0 | other.0)
+            }
+
+            pub const fn contains(self, other: $name) -> bool {
+                self.0 & other.0 == other.0
+            }
+        }
+
+        impl core::ops::BitOr for $name {
+            type Output = $name;
+
+            fn bitor(self, other: $name) -> $name {
+                self.union(other)
+            }
+        }
+
+        impl core::ops::BitOrAssign for $name {
+            fn bitor_assign(&mut self, other: $name) {
+                *self = self.union(other);
+            }
+        }
+
+        impl $crate::devices::ble::data::Encode for $name {
+            fn encode<E>(&self, e: &mut E) -> Result<(), $crate::devices::ble::data::EncoderFull>
+            where
+                E: $crate::devices::ble::data::Encoder + ?Sized,
+            {
+                e.encode(&self.0)
+            }
+        }
+
+        impl $crate::devices::ble::data::Decode for $name {
+            fn decode<D>(d: &mut D) -> Result<Self, $crate::devices::ble::data::DecodeError>
+            where
+                D: $crate::devices::ble::data::Decoder + ?Sized,
+            {
+                Ok($name(d.decode()?))
+            }
+        }
+    };
+}
+
+pub(super) use mask;
+
+mask! {
+    /// The events the controller may send, for [SetEventMask](crate::devices::ble::command::set_event_mask::SetEventMask)
+    /// (Core v5.4, Vol 4, Part E, 7.3.1). Command Complete, Command Status and Number Of Completed
+    /// Packets are always sent.
+    EventMask {
+        0 => INQUIRY_COMPLETE,
+        1 => INQUIRY_RESULT,
+        2 => CONNECTION_COMPLETE,
+        3 => CONNECTION_REQUEST,
+        4 => DISCONNECTION_COMPLETE,
+        5 => AUTHENTICATION_COMPLETE,
+        6 => REMOTE_NAME_REQUEST_COMPLETE,
+        7 => ENCRYPTION_CHANGE,
+        8 => CHANGE_CONNECTION_LINK_KEY_COMPLETE,
+        9 => LINK_KEY_TYPE_CHANGED,
+        10 => READ_REMOTE_SUPPORTED_FEATURES_COMPLETE,
+        11 => READ_REMOTE_VERSION_INFORMATION_COMPLETE,
+        12 => QOS_SETUP_COMPLETE,
+        15 => HARDWARE_ERROR,
+        16 => FLUSH_OCCURRED,
+        17 => ROLE_CHANGE,
+        19 => MODE_CHANGE,
+        20 => RETURN_LINK_KEYS,
+        21 => PIN_CODE_REQUEST,
+        22 => LINK_KEY_REQUEST,
+        23 => LINK_KEY_NOTIFICATION,
+        24 => LOOPBACK_COMMAND,
+        25 => DATA_BUFFER_OVERFLOW,
+        26 => MAX_SLOTS_CHANGE,
+        27 => READ_CLOCK_OFFSET_COMPLETE,
+        28 => CONNECTION_PACKET_TYPE_CHANGED,
+        29 => QOS_VIOLATION,
+        31 => PAGE_SCAN_REPETITION_MODE_CHANGE,
+        32 => FLOW_SPECIFICATION_COMPLETE,
+        33 => INQUIRY_RESULT_WITH_RSSI,
+        34 => READ_REMOTE_EXTENDED_FEATURES_COMPLETE,
+        43 => SYNCHRONOUS_CONNECTION_COMPLETE,
+        44 => SYNCHRONOUS_CONNECTION_CHANGED,
+        45 => SNIFF_SUBRATING,
+        46 => EXTENDED_INQUIRY_RESULT,
+        47 => ENCRYPTION_KEY_REFRESH_COMPLETE,
+        48 => IO_CAPABILITY_REQUEST,
+        49 => IO_CAPABILITY_RESPONSE,
+        50 => USER_CONFIRMATION_REQUEST,
+        51 => USER_PASSKEY_REQUEST,
+        52 => REMOTE_OOB_DATA_REQUEST,
+        53 => SIMPLE_PAIRING_COMPLETE,
+        55 => LINK_SUPERVISION_TIMEOUT_CHANGED,
+        56 => ENHANCED_FLUSH_COMPLETE,
+        58 => USER_PASSKEY_NOTIFICATION,
+        59 => KEYPRESS_NOTIFICATION,
+        60 => REMOTE_HOST_SUPPORTED_FEATURES_NOTIFICATION,
+        /// Needed for any of the events in the [LeEventMask].
+        61 => LE_META,
+    }
+}
+
+mask! {
+    /// The LE Meta subevents the controller may send, for
+    /// [LeSetEventMask](crate::devices::ble::command::le_set_event_mask::LeSetEventMask)
+    /// (Core v5.4, Vol 4, Part E, 7.8.1). They are only sent if [EventMask::LE_META] is set too.
+    LeEventMask {
+        0 => CONNECTION_COMPLETE,
+        1 => ADVERTISING_REPORT,
+        2 => CONNECTION_UPDATE_COMPLETE,
+        3 => READ_REMOTE_FEATURES_COMPLETE,
+        4 => LONG_TERM_KEY_REQUEST,
+        5 => REMOTE_CONNECTION_PARAMETER_REQUEST,
+        6 => DATA_LENGTH_CHANGE,
+        7 => READ_LOCAL_P256_PUBLIC_KEY_COMPLETE,
+        8 => GENERATE_DHKEY_COMPLETE,
+        9 => ENHANCED_CONNECTION_COMPLETE,
+        10 => DIRECTED_ADVERTISING_REPORT,
+        11 => PHY_UPDATE_COMPLETE,
+        12 => EXTENDED_ADVERTISING_REPORT,
+        13 => PERIODIC_ADVERTISING_SYNC_ESTABLISHED,
+        14 => PERIODIC_ADVERTISING_REPORT,
+        15 => PERIODIC_ADVERTISING_SYNC_LOST,
+        16 => SCAN_TIMEOUT,
+        17 => ADVERTISING_SET_TERMINATED,
+        18 => SCAN_REQUEST_RECEIVED,
+        19 => CHANNEL_SELECTION_ALGORITHM,
+    }
+}
+
+/// The LE Meta event, which carries all LE subevents.
+const LE_META_EVENT_CODE: EventCode = EventCode(0x3E);
+
+impl EventMask {
+    /// The bit that enables events with `code`, or `None` if there isn't one on the first page of
+    /// the mask, as for the events that are always sent.
+    pub const fn for_code(code: EventCode) -> Option<EventMask> {
+        match code.0 {
+            // Command Complete, Command Status and Number Of Completed Packets.
+            0x0E | 0x0F | 0x13 => None,
+            // Each event's bit comes right before its code.
+            code @ 0x01..=0x3F => Some(EventMask(1 << (code - 1))),
+            _ => None,
+        }
+    }
+}
+
+impl LeEventMask {
+    /// The bit that enables the LE Meta subevent `code`, or `None` if there isn't one.
+    pub const fn for_subevent(code: u8) -> Option<LeEventMask> {
+        match code {
+            // Each subevent's bit comes right before its code.
+            0x01..=0x40 => Some(LeEventMask(1 << (code - 1))),
+            _ => None,
+        }
+    }
+}
+
+/// The masks for [SetEventMask](crate::devices::ble::command::set_event_mask::SetEventMask) and
+/// [LeSetEventMask](crate::devices::ble::command::le_set_event_mask::LeSetEventMask) that enable a
+/// set of event types, e.g. the ones an [EventRouter](crate::devices::ble::router::EventRouter) has
+/// subscribers for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EventMasks {
+    pub events: EventMask,
+    pub le_events: LeEventMask,
+}
+
+impl EventMasks {
+    pub const NONE: EventMasks = EventMasks {
+        events: EventMask::NONE,
+        le_events: LeEventMask::NONE,
+    };
+
+    /// The events the host handles itself: Disconnection Complete, which frees the ACL buffers of
+    /// a closed connection. Number Of Completed Packets can't be masked.
+    pub const HOST: EventMasks = EventMasks {
+        events: EventMask::DISCONNECTION_COMPLETE,
+        le_events: LeEventMask::NONE,
+    };
+
+    /// Enables the event type `Ev`.
+    pub fn add<'a, Ev: EventParameters<'a>>(&mut self) {
+        if let Some(mask) = EventMask::for_code(Ev::EVENT_CODE) {
+            self.events |= mask;
+        }
+
+        if Ev::EVENT_CODE == LE_META_EVENT_CODE {
+            if let Some(mask) = Ev::SUBEVENT_CODE.and_then(LeEventMask::for_subevent) {
+                self.le_events |= mask;
+            }
+        }
+    }
+
+    /// Like [EventMasks::add], for chaining.
+    pub fn with<'a, Ev: EventParameters<'a>>(mut self) -> EventMasks {
+        self.add::<Ev>();
+        self
+    }
+}
@@ -0,0 +1,40 @@
+use super::event_mask::mask;
+
+mask! {
+    /// The first page of the LMP features, for
+    /// [ReadLocalSupportedFeatures](crate::devices::ble::command::read_local_supported_features::ReadLocalSupportedFeatures)
+    /// (Core v5.4, Vol 2, Part C, 3.3). Only the bits that matter to an LE host are named.
+    LmpFeatures {
+        2 => ENCRYPTION,
+        37 => BR_EDR_NOT_SUPPORTED,
+        38 => LE_SUPPORTED,
+        49 => SIMULTANEOUS_LE_AND_BR_EDR,
+        51 => SECURE_SIMPLE_PAIRING,
+        63 => EXTENDED_FEATURES,
+    }
+}
+
+mask! {
+    /// The link layer features, for
+    /// [LeReadLocalSupportedFeatures](crate::devices::ble::command::le_read_local_supported_features::LeReadLocalSupportedFeatures)
+    /// (Core v5.4, Vol 6, Part B, 4.6).
+    LeFeatures {
+        0 => ENCRYPTION,
+        1 => CONNECTION_PARAMETERS_REQUEST,
+        2 => EXTENDED_REJECT_INDICATION,
+        3 => PERIPHERAL_INITIATED_FEATURES_EXCHANGE,
+        4 => PING,
+        5 => DATA_PACKET_LENGTH_EXTENSION,
+        6 => LL_PRIVACY,
+        7 => EXTENDED_SCANNER_FILTER_POLICIES,
+        8 => LE_2M_PHY,
+        9 => STABLE_MODULATION_INDEX_TRANSMITTER,
+        10 => STABLE_MODULATION_INDEX_RECEIVER,
+        11 => LE_CODED_PHY,
+        12 => EXTENDED_ADVERTISING,
+        13 => PERIODIC_ADVERTISING,
+        14 => CHANNEL_SELECTION_ALGORITHM_2,
+        15 => POWER_CLASS_1,
+        16 => MINIMUM_NUMBER_OF_USED_CHANNELS,
+    }
+}
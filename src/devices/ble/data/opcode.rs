@@ -1,10 +1,11 @@
-use super::{Decode, DecodeError, Decoder};
+use super::{Decode, DecodeError, Decoder, Encode, Encoder, EncoderFull};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Ogf(pub u8);
 
 impl Ogf {
     pub const CONTROLLER_BASEBAND: Ogf = Ogf(0x03);
+    pub const INFORMATIONAL_PARAMETERS: Ogf = Ogf(0x04);
     pub const LE_CONTROLLER: Ogf = Ogf(0x08);
 }
 
@@ -17,6 +18,15 @@ impl Opcode {
     }
 }
 
+impl Encode for Opcode {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&self.0)
+    }
+}
+
 impl Decode for Opcode {
     fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
     where
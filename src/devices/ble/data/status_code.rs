@@ -1,8 +1,10 @@
-use core::num::NonZeroU8;
+use core::{fmt, num::NonZeroU8};
 
 use super::{Decode, DecodeError, Decoder, Encode, Encoder, EncoderFull};
 
-#[derive(Debug, Clone, Copy)]
+/// A status or reason code, as the controller sent it. Use [StatusCode::error()] or
+/// [StatusCode::assert()] to get at the [StatusError].
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct StatusCode(pub u8);
 
 impl Encode for StatusCode {
@@ -23,18 +25,234 @@ impl Decode for StatusCode {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct StatusError(pub NonZeroU8);
-
 impl StatusCode {
+    pub const SUCCESS: StatusCode = StatusCode(0x00);
+
     pub fn is_successful(self) -> bool {
         self.0 == 0x00
     }
 
+    pub fn error(self) -> Option<StatusError> {
+        NonZeroU8::new(self.0).map(StatusError::from_code)
+    }
+
     pub fn assert(self) -> Result<(), StatusError> {
-        match NonZeroU8::new(self.0) {
-            Some(nz) => Err(StatusError(nz)),
+        match self.error() {
+            Some(error) => Err(error),
             None => Ok(()),
         }
     }
 }
+
+impl From<StatusError> for StatusCode {
+    fn from(error: StatusError) -> StatusCode {
+        StatusCode(error.code().get())
+    }
+}
+
+impl fmt::Debug for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error() {
+            Some(error) => fmt::Debug::fmt(&error, f),
+            None => f.write_str("Success"),
+        }
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error() {
+            Some(error) => fmt::Display::fmt(&error, f),
+            None => f.write_str("Success (0x00)"),
+        }
+    }
+}
+
+/// Return parameters made of a status followed by `T`. `T` is only decoded if the status is
+/// successful, since controllers don't always send the rest for a failed command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithStatus<T> {
+    Success(T),
+    Failure(StatusError),
+}
+
+impl<T> WithStatus<T> {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            WithStatus::Success(_) => StatusCode::SUCCESS,
+            WithStatus::Failure(error) => (*error).into(),
+        }
+    }
+
+    pub fn into_result(self) -> Result<T, StatusError> {
+        match self {
+            WithStatus::Success(rest) => Ok(rest),
+            WithStatus::Failure(error) => Err(error),
+        }
+    }
+}
+
+impl<T: Encode> Encode for WithStatus<T> {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&self.status())?;
+        match self {
+            WithStatus::Success(rest) => e.encode(rest),
+            WithStatus::Failure(_) => Ok(()),
+        }
+    }
+}
+
+impl<T: Decode> Decode for WithStatus<T> {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        let status: StatusCode = d.decode()?;
+        match status.error() {
+            Some(error) => Ok(WithStatus::Failure(error)),
+            None => Ok(WithStatus::Success(d.decode()?)),
+        }
+    }
+}
+
+/// What can be done about a [StatusError].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The controller, the peer or the radio environment was busy or slow; trying again later
+    /// may succeed.
+    Transient,
+    /// The command, its parameters or the state it was issued in don't suit the controller or
+    /// the peer; trying again unchanged won't help.
+    Configuration,
+    /// The controller failed, or the error isn't one the host knows; resetting the controller is
+    /// the way forward.
+    Fatal,
+}
+
+macro_rules! status_errors {
+    ($($code:literal => $name:ident, $description:literal, $category:ident;)*) => {
+        /// A non-zero status code, as listed in the Core specification, Vol 1, Part F.
+        ///
+        /// Codes the host doesn't know about yet are kept in [StatusError::Unknown].
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[non_exhaustive]
+        pub enum StatusError {
+            $($name,)*
+            Unknown(NonZeroU8),
+        }
+
+        impl StatusError {
+            pub fn from_code(code: NonZeroU8) -> StatusError {
+                match code.get() {
+                    $($code => StatusError::$name,)*
+                    _ => StatusError::Unknown(code),
+                }
+            }
+
+            pub fn code(self) -> NonZeroU8 {
+                match self {
+                    // The codes in the table are all non-zero.
+                    $(StatusError::$name => NonZeroU8::new($code).unwrap(),)*
+                    StatusError::Unknown(code) => code,
+                }
+            }
+
+            /// The name the specification gives the error.
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(StatusError::$name => $description,)*
+                    StatusError::Unknown(_) => "Unknown Error Code",
+                }
+            }
+
+            pub fn category(self) -> ErrorCategory {
+                match self {
+                    $(StatusError::$name => ErrorCategory::$category,)*
+                    StatusError::Unknown(_) => ErrorCategory::Fatal,
+                }
+            }
+        }
+    };
+}
+
+status_errors! {
+    0x01 => UnknownHciCommand, "Unknown HCI Command", Configuration;
+    0x02 => UnknownConnectionIdentifier, "Unknown Connection Identifier", Configuration;
+    0x03 => HardwareFailure, "Hardware Failure", Fatal;
+    0x04 => PageTimeout, "Page Timeout", Transient;
+    0x05 => AuthenticationFailure, "Authentication Failure", Configuration;
+    0x06 => PinOrKeyMissing, "PIN or Key Missing", Configuration;
+    0x07 => MemoryCapacityExceeded, "Memory Capacity Exceeded", Transient;
+    0x08 => ConnectionTimeout, "Connection Timeout", Transient;
+    0x09 => ConnectionLimitExceeded, "Connection Limit Exceeded", Transient;
+    0x0A => SynchronousConnectionLimitExceeded, "Synchronous Connection Limit To A Device Exceeded", Transient;
+    0x0B => ConnectionAlreadyExists, "Connection Already Exists", Configuration;
+    0x0C => CommandDisallowed, "Command Disallowed", Configuration;
+    0x0D => ConnectionRejectedLimitedResources, "Connection Rejected due to Limited Resources", Transient;
+    0x0E => ConnectionRejectedSecurityReasons, "Connection Rejected Due To Security Reasons", Configuration;
+    0x0F => ConnectionRejectedUnacceptableBdAddr, "Connection Rejected due to Unacceptable BD_ADDR", Configuration;
+    0x10 => ConnectionAcceptTimeoutExceeded, "Connection Accept Timeout Exceeded", Transient;
+    0x11 => UnsupportedFeatureOrParameterValue, "Unsupported Feature or Parameter Value", Configuration;
+    0x12 => InvalidHciCommandParameters, "Invalid HCI Command Parameters", Configuration;
+    0x13 => RemoteUserTerminatedConnection, "Remote User Terminated Connection", Transient;
+    0x14 => RemoteDeviceTerminatedConnectionLowResources, "Remote Device Terminated Connection due to Low Resources", Transient;
+    0x15 => RemoteDeviceTerminatedConnectionPowerOff, "Remote Device Terminated Connection due to Power Off", Transient;
+    0x16 => ConnectionTerminatedByLocalHost, "Connection Terminated By Local Host", Transient;
+    0x17 => RepeatedAttempts, "Repeated Attempts", Transient;
+    0x18 => PairingNotAllowed, "Pairing Not Allowed", Configuration;
+    0x19 => UnknownLmpPdu, "Unknown LMP PDU", Configuration;
+    0x1A => UnsupportedRemoteFeature, "Unsupported Remote Feature", Configuration;
+    0x1B => ScoOffsetRejected, "SCO Offset Rejected", Configuration;
+    0x1C => ScoIntervalRejected, "SCO Interval Rejected", Configuration;
+    0x1D => ScoAirModeRejected, "SCO Air Mode Rejected", Configuration;
+    0x1E => InvalidLlParameters, "Invalid LMP Parameters / Invalid LL Parameters", Configuration;
+    0x1F => UnspecifiedError, "Unspecified Error", Fatal;
+    0x20 => UnsupportedLlParameterValue, "Unsupported LMP Parameter Value / Unsupported LL Parameter Value", Configuration;
+    0x21 => RoleChangeNotAllowed, "Role Change Not Allowed", Configuration;
+    0x22 => LlResponseTimeout, "LMP Response Timeout / LL Response Timeout", Transient;
+    0x23 => LlProcedureCollision, "LMP Error Transaction Collision / LL Procedure Collision", Transient;
+    0x24 => LmpPduNotAllowed, "LMP PDU Not Allowed", Configuration;
+    0x25 => EncryptionModeNotAcceptable, "Encryption Mode Not Acceptable", Configuration;
+    0x26 => LinkKeyCannotBeChanged, "Link Key cannot be Changed", Configuration;
+    0x27 => RequestedQosNotSupported, "Requested QoS Not Supported", Configuration;
+    0x28 => InstantPassed, "Instant Passed", Transient;
+    0x29 => PairingWithUnitKeyNotSupported, "Pairing With Unit Key Not Supported", Configuration;
+    0x2A => DifferentTransactionCollision, "Different Transaction Collision", Transient;
+    0x2C => QosUnacceptableParameter, "QoS Unacceptable Parameter", Configuration;
+    0x2D => QosRejected, "QoS Rejected", Configuration;
+    0x2E => ChannelClassificationNotSupported, "Channel Classification Not Supported", Configuration;
+    0x2F => InsufficientSecurity, "Insufficient Security", Configuration;
+    0x30 => ParameterOutOfMandatoryRange, "Parameter Out Of Mandatory Range", Configuration;
+    0x32 => RoleSwitchPending, "Role Switch Pending", Transient;
+    0x34 => ReservedSlotViolation, "Reserved Slot Violation", Configuration;
+    0x35 => RoleSwitchFailed, "Role Switch Failed", Transient;
+    0x36 => ExtendedInquiryResponseTooLarge, "Extended Inquiry Response Too Large", Configuration;
+    0x37 => SecureSimplePairingNotSupportedByHost, "Secure Simple Pairing Not Supported By Host", Configuration;
+    0x38 => HostBusyPairing, "Host Busy - Pairing", Transient;
+    0x39 => ConnectionRejectedNoSuitableChannelFound, "Connection Rejected due to No Suitable Channel Found", Transient;
+    0x3A => ControllerBusy, "Controller Busy", Transient;
+    0x3B => UnacceptableConnectionParameters, "Unacceptable Connection Parameters", Configuration;
+    0x3C => AdvertisingTimeout, "Advertising Timeout", Transient;
+    0x3D => ConnectionTerminatedMicFailure, "Connection Terminated due to MIC Failure", Fatal;
+    0x3E => ConnectionFailedToBeEstablished, "Connection Failed to be Established / Synchronization Timeout", Transient;
+    0x40 => CoarseClockAdjustmentRejected, "Coarse Clock Adjustment Rejected but Will Try to Adjust Using Clock Dragging", Transient;
+    0x41 => Type0SubmapNotDefined, "Type0 Submap Not Defined", Configuration;
+    0x42 => UnknownAdvertisingIdentifier, "Unknown Advertising Identifier", Configuration;
+    0x43 => LimitReached, "Limit Reached", Transient;
+    0x44 => OperationCancelledByHost, "Operation Cancelled by Host", Transient;
+    0x45 => PacketTooLong, "Packet Too Long", Configuration;
+    0x46 => TooLate, "Too Late", Transient;
+    0x47 => TooEarly, "Too Early", Transient;
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (0x{:02X})", self.description(), self.code())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StatusError {}
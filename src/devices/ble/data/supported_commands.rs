@@ -0,0 +1,86 @@
+use crate::devices::ble::command::{
+    le_clear_advertising_sets::LeClearAdvertisingSets, le_create_connection::LeCreateConnection,
+    le_read_buffer_size::LeReadBufferSize, le_read_buffer_size_v2::LeReadBufferSizeV2,
+    le_read_local_supported_features::LeReadLocalSupportedFeatures,
+    le_read_maximum_advertising_data_length::LeReadMaximumAdvertisingDataLength,
+    le_read_number_of_supported_advertising_sets::LeReadNumberOfSupportedAdvertisingSets,
+    le_remove_advertising_set::LeRemoveAdvertisingSet,
+    le_set_advertising_data::LeSetAdvertisingData,
+    le_set_advertising_enable::LeSetAdvertisingEnable,
+    le_set_advertising_parameters::LeSetAdvertisingParameters, le_set_event_mask::LeSetEventMask,
+    le_set_extended_advertising_data::LeSetExtendedAdvertisingData,
+    le_set_extended_advertising_enable::LeSetExtendedAdvertisingEnable,
+    le_set_extended_advertising_parameters::LeSetExtendedAdvertisingParameters,
+    le_set_extended_scan_enable::LeSetExtendedScanEnable,
+    le_set_extended_scan_parameters::LeSetExtendedScanParameters,
+    le_set_extended_scan_response_data::LeSetExtendedScanResponseData,
+    le_set_scan_enable::LeSetScanEnable, le_set_scan_parameters::LeSetScanParameters,
+    le_set_scan_response_data::LeSetScanResponseData, read_bd_addr::ReadBdAddr,
+    read_buffer_size::ReadBufferSize, read_local_supported_features::ReadLocalSupportedFeatures,
+    read_local_version_information::ReadLocalVersionInformation, reset::Reset,
+    set_event_mask::SetEventMask, CommandParameters,
+};
+
+use super::{opcode::Opcode, Decode, DecodeError, Decoder, Encode, Encoder, EncoderFull};
+
+/// Where the commands this crate knows of are in [SupportedCommands], as (opcode, octet, bit).
+const COMMAND_BITS: &[(Opcode, usize, u8)] = &[
+    (SetEventMask::OPCODE, 5, 6),
+    (Reset::OPCODE, 5, 7),
+    (ReadLocalVersionInformation::OPCODE, 14, 3),
+    (ReadLocalSupportedFeatures::OPCODE, 14, 5),
+    (ReadBufferSize::OPCODE, 14, 7),
+    (ReadBdAddr::OPCODE, 15, 1),
+    (LeSetEventMask::OPCODE, 25, 0),
+    (LeReadBufferSize::OPCODE, 25, 1),
+    (LeReadLocalSupportedFeatures::OPCODE, 25, 2),
+    (LeSetAdvertisingParameters::OPCODE, 25, 5),
+    (LeSetAdvertisingData::OPCODE, 25, 7),
+    (LeSetScanResponseData::OPCODE, 26, 0),
+    (LeSetAdvertisingEnable::OPCODE, 26, 1),
+    (LeSetScanParameters::OPCODE, 26, 2),
+    (LeSetScanEnable::OPCODE, 26, 3),
+    (LeCreateConnection::OPCODE, 26, 4),
+    (LeSetExtendedAdvertisingParameters::OPCODE, 36, 2),
+    (LeSetExtendedAdvertisingData::OPCODE, 36, 3),
+    (LeSetExtendedScanResponseData::OPCODE, 36, 4),
+    (LeSetExtendedAdvertisingEnable::OPCODE, 36, 5),
+    (LeReadMaximumAdvertisingDataLength::OPCODE, 36, 6),
+    (LeReadNumberOfSupportedAdvertisingSets::OPCODE, 36, 7),
+    (LeRemoveAdvertisingSet::OPCODE, 37, 0),
+    (LeClearAdvertisingSets::OPCODE, 37, 1),
+    (LeSetExtendedScanParameters::OPCODE, 37, 5),
+    (LeSetExtendedScanEnable::OPCODE, 37, 6),
+    (LeReadBufferSizeV2::OPCODE, 41, 5),
+];
+
+/// The commands a controller supports, one bit per command (Core v5.4, Vol 4, Part E, 6.27).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupportedCommands(pub [u8; 64]);
+
+impl SupportedCommands {
+    /// Whether the command with `opcode` is supported, or `None` if this crate doesn't know which
+    /// bit stands for it.
+    pub fn supports(&self, opcode: Opcode) -> Option<bool> {
+        let &(_, octet, bit) = COMMAND_BITS.iter().find(|(known, ..)| *known == opcode)?;
+        Some(self.0[octet] & (1 << bit) != 0)
+    }
+}
+
+impl Encode for SupportedCommands {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&self.0)
+    }
+}
+
+impl Decode for SupportedCommands {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        Ok(Self(d.decode()?))
+    }
+}
@@ -1,17 +1,26 @@
 use core::fmt::Debug;
 
-use super::data::{Buffer, DecodeError, MaybeDecode};
+use super::data::{Buffer, DecodeError, Encode, EncoderFull, MaybeDecodeRef};
 
 pub mod command_complete;
 pub mod command_status;
+pub mod disconnection_complete;
+pub mod hardware_error;
 pub mod le_advertising_report;
+pub mod le_advertising_set_terminated;
 pub mod le_connection_complete;
+pub mod le_extended_advertising_report;
+pub mod number_of_completed_packets;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct EventCode(pub u8);
 
-pub trait EventParameters: MaybeDecode {
+/// The parameters of an event with the code `EVENT_CODE`, decoded from an [EncodedEvent] that
+/// they may borrow from for `'a`. Events that own their data implement this for every `'a`.
+pub trait EventParameters<'a>: MaybeDecodeRef<'a> {
     const EVENT_CODE: EventCode;
+    /// For LE Meta events, the subevent code that is checked while decoding.
+    const SUBEVENT_CODE: Option<u8> = None;
 }
 
 pub struct EncodedEvent {
@@ -20,11 +29,21 @@ pub struct EncodedEvent {
 }
 
 impl EncodedEvent {
-    pub fn decode<E: EventParameters>(&self) -> Result<Option<E>, DecodeError> {
+    /// Encodes `event` as a controller would send it.
+    pub fn encode<'a, E: EventParameters<'a> + Encode>(event: &E) -> Result<Self, EncoderFull> {
+        let mut parameters = Buffer::new();
+        event.encode(&mut parameters)?;
+        Ok(EncodedEvent {
+            code: E::EVENT_CODE,
+            parameters,
+        })
+    }
+
+    pub fn decode<'a, E: EventParameters<'a>>(&'a self) -> Result<Option<E>, DecodeError> {
         if self.code != E::EVENT_CODE {
             return Ok(None);
         }
 
-        E::maybe_decode(&mut &*self.parameters)
+        E::maybe_decode_ref(&mut &*self.parameters)
     }
 }
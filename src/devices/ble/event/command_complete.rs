@@ -1,11 +1,15 @@
 use crate::devices::ble::{
     command::{AnyCommand, MatchOpcode},
-    data::{opcode::Opcode, Decode, DecodeError, MaybeDecode, MaybeDecoder},
+    data::{
+        opcode::Opcode,
+        status_code::{StatusCode, StatusError, WithStatus},
+        Decode, DecodeError, Encode, Encoder, EncoderFull, MaybeDecode, MaybeDecoder,
+    },
     private::Internal,
-    CommandReceiptIndicator, EventCode,
+    CommandReceiptIndicator,
 };
 
-use super::EventParameters;
+use super::{EventCode, EventParameters};
 
 pub trait CommandWithCompleteEvent: MatchOpcode {
     type ReturnParameters: Decode;
@@ -15,6 +19,31 @@ impl CommandWithCompleteEvent for AnyCommand {
     type ReturnParameters = ();
 }
 
+/// Return parameters that begin with a status, which [Ble::run_checked](crate::devices::ble::Ble::run_checked)
+/// checks before handing over the rest.
+pub trait ReturnParametersWithStatus {
+    /// The return parameters after the status.
+    type Rest;
+
+    fn into_result(self) -> Result<Self::Rest, StatusError>;
+}
+
+impl ReturnParametersWithStatus for StatusCode {
+    type Rest = ();
+
+    fn into_result(self) -> Result<(), StatusError> {
+        self.assert()
+    }
+}
+
+impl<T> ReturnParametersWithStatus for WithStatus<T> {
+    type Rest = T;
+
+    fn into_result(self) -> Result<T, StatusError> {
+        WithStatus::into_result(self)
+    }
+}
+
 impl<C: CommandWithCompleteEvent> Internal for CommandComplete<C> {}
 impl<C: CommandWithCompleteEvent> CommandReceiptIndicator<C> for CommandComplete<C> {}
 
@@ -25,6 +54,33 @@ pub struct CommandComplete<C: CommandWithCompleteEvent> {
     pub return_parameters: C::ReturnParameters,
 }
 
+impl<C> PartialEq for CommandComplete<C>
+where
+    C: CommandWithCompleteEvent,
+    C::ReturnParameters: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.num_hci_command_packets == other.num_hci_command_packets
+            && self.command_opcode == other.command_opcode
+            && self.return_parameters == other.return_parameters
+    }
+}
+
+impl<C> Encode for CommandComplete<C>
+where
+    C: CommandWithCompleteEvent,
+    C::ReturnParameters: Encode,
+{
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&self.num_hci_command_packets)?;
+        e.encode(&self.command_opcode)?;
+        e.encode(&self.return_parameters)
+    }
+}
+
 impl<C, R> MaybeDecode for CommandComplete<C>
 where
     C: CommandWithCompleteEvent<ReturnParameters = R>,
@@ -48,6 +104,6 @@ where
     }
 }
 
-impl<C: CommandWithCompleteEvent> EventParameters for CommandComplete<C> {
+impl<C: CommandWithCompleteEvent> EventParameters<'_> for CommandComplete<C> {
     const EVENT_CODE: EventCode = EventCode(0x0E);
 }
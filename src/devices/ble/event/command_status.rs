@@ -2,7 +2,10 @@ use core::marker::PhantomData;
 
 use crate::devices::ble::{
     command::{AnyCommand, MatchOpcode},
-    data::{opcode::Opcode, DecodeError, MaybeDecode, MaybeDecoder},
+    data::{
+        opcode::Opcode, status_code::StatusCode, DecodeError, Encode, Encoder, EncoderFull,
+        MaybeDecode, MaybeDecoder,
+    },
     private::Internal,
     CommandReceiptIndicator,
 };
@@ -11,6 +14,23 @@ use super::{EventCode, EventParameters};
 
 pub trait CommandWithStatusEvent: MatchOpcode {}
 
+/// A command whose outcome is reported by a `Completion` event some time after its Command
+/// Status, such as [LeCreateConnection](crate::devices::ble::command::le_create_connection::LeCreateConnection)
+/// and [LeConnectionComplete](super::le_connection_complete::LeConnectionComplete).
+pub trait CommandWithCompletionEvent: CommandWithStatusEvent {
+    type Completion: CompletionEvent;
+
+    /// Whether `completion` reports on this command, rather than on another procedure that
+    /// finishes with the same event.
+    fn is_completed_by(&self, completion: &Self::Completion) -> bool;
+}
+
+/// An event that finishes a [CommandWithCompletionEvent]. Completions are handed back to the
+/// caller, so they own their data.
+pub trait CompletionEvent: for<'a> EventParameters<'a> {
+    fn status(&self) -> StatusCode;
+}
+
 impl CommandWithStatusEvent for AnyCommand {}
 
 impl<C> Internal for CommandStatus<C> {}
@@ -19,11 +39,41 @@ impl<C: CommandWithStatusEvent> CommandReceiptIndicator<C> for CommandStatus<C>
 #[derive(Debug)]
 pub struct CommandStatus<C> {
     _phantom: PhantomData<C>,
-    pub status: u8,
+    pub status: StatusCode,
     pub num_hci_command_packets: u8,
     pub command_opcode: Opcode,
 }
 
+impl<C> CommandStatus<C> {
+    pub fn new(status: StatusCode, num_hci_command_packets: u8, command_opcode: Opcode) -> Self {
+        CommandStatus {
+            _phantom: PhantomData,
+            status,
+            num_hci_command_packets,
+            command_opcode,
+        }
+    }
+}
+
+impl<C> PartialEq for CommandStatus<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.status == other.status
+            && self.num_hci_command_packets == other.num_hci_command_packets
+            && self.command_opcode == other.command_opcode
+    }
+}
+
+impl<C> Encode for CommandStatus<C> {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&self.status)?;
+        e.encode(&self.num_hci_command_packets)?;
+        e.encode(&self.command_opcode)
+    }
+}
+
 impl<C> MaybeDecode for CommandStatus<C>
 where
     C: CommandWithStatusEvent,
@@ -48,6 +98,6 @@ where
     }
 }
 
-impl<C: CommandWithStatusEvent> EventParameters for CommandStatus<C> {
+impl<C: CommandWithStatusEvent> EventParameters<'_> for CommandStatus<C> {
     const EVENT_CODE: EventCode = EventCode(0x0F);
 }
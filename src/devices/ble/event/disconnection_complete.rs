@@ -0,0 +1,9 @@
+use crate::devices::ble::data::{status_code::StatusCode, Decode, Encode};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[event(code = 0x05)]
+pub struct DisconnectionComplete {
+    pub status: StatusCode,
+    pub connection_handle: u16,
+    pub reason: StatusCode,
+}
@@ -0,0 +1,7 @@
+use crate::devices::ble::data::{Decode, Encode};
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[event(code = 0x10)]
+pub struct HardwareError {
+    pub hardware_code: u8,
+}
@@ -1,93 +1,259 @@
 use crate::devices::ble::{
-    data::{Buffer, DecodeError, MaybeDecode, MaybeDecoder},
+    data::{
+        Buffer, DecodeError, Decoder, Encode, Encoder, EncoderFull, MaybeDecode, MaybeDecodeRef,
+        MaybeDecoder,
+    },
     ParseError,
 };
 
-use super::{EventParameters, EventCode};
+use super::{EventCode, EventParameters};
 
-pub struct LeAdvertisingReport {
+const SUBEVENT_CODE: u8 = 0x02;
+
+/// What's left of the event parameters after the subevent code and the number of reports.
+const MAX_REPORTS_LEN: usize = 253;
+
+/// The longest advertising data a legacy advertisement can carry.
+const MAX_DATA_LEN: u8 = 0x1F;
+
+/// An LE Advertising Report borrowing its reports from the received event, so that polling for
+/// advertisements doesn't copy them. Use [OwnedLeAdvertisingReport] to keep reports around, e.g.
+/// in a [Subscription](crate::devices::ble::router::Subscription).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeAdvertisingReport<'a> {
+    num_reports: u8,
+    data: &'a [u8],
+}
+
+impl<'a> MaybeDecodeRef<'a> for LeAdvertisingReport<'a> {
+    fn maybe_decode_ref(d: &mut &'a [u8]) -> Result<Option<Self>, DecodeError> {
+        let SUBEVENT_CODE = d.decode()? else {
+            return Ok(None);
+        };
+        let num_reports = d.decode()?;
+        if d.len() > MAX_REPORTS_LEN {
+            return Err(DecodeError::Malformed(
+                "advertising reports are longer than an event can be",
+            ));
+        }
+
+        Ok(Some(LeAdvertisingReport {
+            num_reports,
+            data: core::mem::take(d),
+        }))
+    }
+}
+
+impl Encode for LeAdvertisingReport<'_> {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&SUBEVENT_CODE)?;
+        e.encode(&self.num_reports)?;
+        e.encode(self.data)
+    }
+}
+
+impl<'a> EventParameters<'a> for LeAdvertisingReport<'a> {
+    const EVENT_CODE: EventCode = EventCode(0x3E);
+    const SUBEVENT_CODE: Option<u8> = Some(SUBEVENT_CODE);
+}
+
+impl<'a> LeAdvertisingReport<'a> {
+    pub fn items(&self) -> LeAdvertisingReportItems<'a> {
+        LeAdvertisingReportItems {
+            num_left: self.num_reports as usize,
+            data: self.data,
+        }
+    }
+
+    /// Copies the reports out of the received event.
+    pub fn into_owned(self) -> OwnedLeAdvertisingReport {
+        let mut data = Buffer::new();
+        // Decoding made sure that the reports fit.
+        let _ = data.write(self.data);
+
+        OwnedLeAdvertisingReport {
+            num_reports: self.num_reports,
+            data,
+        }
+    }
+}
+
+/// An LE Advertising Report with its own copy of the reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedLeAdvertisingReport {
     num_reports: u8,
-    data: Buffer<253>,
+    data: Buffer<MAX_REPORTS_LEN>,
 }
 
-impl MaybeDecode for LeAdvertisingReport {
+impl MaybeDecode for OwnedLeAdvertisingReport {
     fn maybe_decode<D>(d: &mut D) -> Result<Option<Self>, DecodeError>
     where
         D: MaybeDecoder + ?Sized,
     {
-        let 0x02u8 = d.decode()? else { return Ok(None) };
+        let SUBEVENT_CODE = d.decode()? else {
+            return Ok(None);
+        };
 
-        Ok(Some(LeAdvertisingReport {
+        Ok(Some(OwnedLeAdvertisingReport {
             num_reports: d.decode()?,
             data: d.decode()?,
         }))
     }
 }
 
-impl EventParameters for LeAdvertisingReport {
+impl Encode for OwnedLeAdvertisingReport {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        self.report().encode(e)
+    }
+}
+
+impl EventParameters<'_> for OwnedLeAdvertisingReport {
     const EVENT_CODE: EventCode = EventCode(0x3E);
+    const SUBEVENT_CODE: Option<u8> = Some(SUBEVENT_CODE);
 }
 
-impl LeAdvertisingReport {
-    pub fn items(&self) -> LeAdvertisingReportItems {
-        LeAdvertisingReportItems {
-            num_left: self.num_reports as usize,
+impl OwnedLeAdvertisingReport {
+    /// Puts `items` together into a report, as a controller would send them.
+    pub fn from_items<'a>(
+        items: impl IntoIterator<Item = LeAdvertisingReportItem<'a>>,
+    ) -> Result<Self, EncoderFull> {
+        let mut num_reports: u8 = 0;
+        let mut data = Buffer::new();
+        for item in items {
+            num_reports = num_reports.checked_add(1).ok_or(EncoderFull)?;
+            data.encode(&item)?;
+        }
+
+        Ok(OwnedLeAdvertisingReport { num_reports, data })
+    }
+
+    pub fn report(&self) -> LeAdvertisingReport<'_> {
+        LeAdvertisingReport {
+            num_reports: self.num_reports,
             data: &self.data,
         }
     }
+
+    pub fn items(&self) -> LeAdvertisingReportItems<'_> {
+        self.report().items()
+    }
 }
 
-#[derive(Debug)]
-pub struct LeAdvertisingReportItem {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeAdvertisingReportItem<'a> {
     pub event_type: u8,
     pub address_type: u8,
     pub address: [u8; 6],
-    pub data: Buffer<0x1F>,
-    pub rssi: u8,
+    pub data: &'a [u8],
+    /// In dBm, or 127 if it isn't available.
+    pub rssi: i8,
 }
 
-pub struct LeAdvertisingReportItems<'a> {
-    num_left: usize,
-    data: &'a [u8],
+/// Fails if `data` is longer than advertising data can be.
+impl Encode for LeAdvertisingReportItem<'_> {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        let data_length = u8::try_from(self.data.len())
+            .ok()
+            .filter(|&len| len <= MAX_DATA_LEN)
+            .ok_or(EncoderFull)?;
+
+        e.encode(&self.event_type)?;
+        e.encode(&self.address_type)?;
+        e.encode(&self.address)?;
+        e.encode(&data_length)?;
+        e.encode(self.data)?;
+        e.encode(&self.rssi)
+    }
 }
 
-impl<'a> Iterator for LeAdvertisingReportItems<'a> {
-    type Item = Result<LeAdvertisingReportItem, ParseError>;
+impl LeAdvertisingReportItem<'_> {
+    /// Copies the advertising data out of the received event, failing if `data` is longer than
+    /// advertising data can be, which is only possible for items that weren't decoded.
+    pub fn into_owned(self) -> Result<OwnedLeAdvertisingReportItem, EncoderFull> {
+        Ok(OwnedLeAdvertisingReportItem {
+            event_type: self.event_type,
+            address_type: self.address_type,
+            address: self.address,
+            data: Buffer::try_from(self.data)?,
+            rssi: self.rssi,
+        })
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.num_left == 0 {
-            return None;
-        }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedLeAdvertisingReportItem {
+    pub event_type: u8,
+    pub address_type: u8,
+    pub address: [u8; 6],
+    pub data: Buffer<{ MAX_DATA_LEN as usize }>,
+    /// In dBm, or 127 if it isn't available.
+    pub rssi: i8,
+}
 
-        self.num_left -= 1;
+pub struct LeAdvertisingReportItems<'a> {
+    num_left: usize,
+    data: &'a [u8],
+}
 
+impl<'a> LeAdvertisingReportItems<'a> {
+    fn decode_item(&mut self) -> Result<LeAdvertisingReportItem<'a>, ParseError> {
         let [event_type, address_type, rest @ ..] = self.data else {
-            return Some(Err(ParseError));
+            return Err(ParseError);
         };
         let [address0, address1, address2, address3, address4, address5, rest @ ..] = rest else {
-            return Some(Err(ParseError));
+            return Err(ParseError);
         };
         let address = [
             *address0, *address1, *address2, *address3, *address4, *address5,
         ];
         let [data_length, rest @ ..] = rest else {
-            return Some(Err(ParseError));
+            return Err(ParseError);
         };
-        if rest.len() < *data_length as usize {
-            return Some(Err(ParseError));
+        if rest.len() < *data_length as usize || *data_length > MAX_DATA_LEN {
+            return Err(ParseError);
         }
-        let data = &rest[..*data_length as usize];
-        let rest = &rest[*data_length as usize..];
-        let [rssi] = rest else {
-            return Some(Err(ParseError));
+        let (data, rest) = rest.split_at(*data_length as usize);
+        let Some((rssi, rest)) = rest.split_first() else {
+            return Err(ParseError);
         };
+        self.data = rest;
 
-        Some(Ok(LeAdvertisingReportItem {
+        Ok(LeAdvertisingReportItem {
             event_type: *event_type,
             address_type: *address_type,
             address,
-            data: Buffer::from(data),
-            rssi: *rssi,
-        }))
+            data,
+            rssi: *rssi as i8,
+        })
+    }
+}
+
+impl<'a> Iterator for LeAdvertisingReportItems<'a> {
+    type Item = Result<LeAdvertisingReportItem<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.num_left == 0 {
+            return None;
+        }
+
+        self.num_left -= 1;
+        match self.decode_item() {
+            // The last report ends the event.
+            Ok(item) if self.num_left > 0 || self.data.is_empty() => Some(Ok(item)),
+            // Nothing tells where the next report starts after a malformed one.
+            _ => {
+                self.num_left = 0;
+                Some(Err(ParseError))
+            }
+        }
     }
 }
@@ -0,0 +1,13 @@
+use crate::devices::ble::data::{status_code::StatusCode, Decode, Encode};
+
+/// An advertising set stopped advertising, because a central connected to it or because its
+/// duration or number of events ran out.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[event(subevent = 0x12)]
+pub struct LeAdvertisingSetTerminated {
+    /// Successful if a central connected, in which case `connection_handle` is its connection.
+    pub status: StatusCode,
+    pub advertising_handle: u8,
+    pub connection_handle: u16,
+    pub num_completed_extended_advertising_events: u8,
+}
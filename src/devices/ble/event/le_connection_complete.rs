@@ -1,7 +1,9 @@
-use crate::devices::ble::data::{address::Address, status_code::StatusCode, DecodeError, MaybeDecode, MaybeDecoder};
+use crate::devices::ble::data::{address::Address, status_code::StatusCode, Decode, Encode};
 
-use super::{EventParameters, EventCode};
+use super::command_status::CompletionEvent;
 
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[event(subevent = 0x01)]
 pub struct LeConnectionComplete {
     pub status: StatusCode,
     pub connection_handle: u16,
@@ -14,27 +16,8 @@ pub struct LeConnectionComplete {
     pub central_clock_accuracy: u8,
 }
 
-impl MaybeDecode for LeConnectionComplete {
-    fn maybe_decode<D>(d: &mut D) -> Result<Option<Self>, DecodeError>
-    where
-        D: MaybeDecoder + ?Sized,
-    {
-        let 0x01u8 = d.decode()? else { return Ok(None) };
-        
-        Ok(Some(Self {
-            status: d.decode()?,
-            connection_handle: d.decode()?,
-            role: d.decode()?,
-            peer_address_type: d.decode()?,
-            peer_address: d.decode()?,
-            connection_interval: d.decode()?,
-            peripheral_latency: d.decode()?,
-            supervision_timeout: d.decode()?,
-            central_clock_accuracy: d.decode()?,
-        }))
+impl CompletionEvent for LeConnectionComplete {
+    fn status(&self) -> StatusCode {
+        self.status
     }
 }
-
-impl EventParameters for LeConnectionComplete {
-    const EVENT_CODE: super::EventCode = EventCode(0x3E);
-}
@@ -0,0 +1,497 @@
+use heapless::Vec;
+
+use crate::devices::ble::{
+    data::{
+        address::Address, advertising_data::MAX_EXTENDED_ADVERTISING_DATA_LEN, Buffer, DecodeError,
+        Decoder, Encode, Encoder, EncoderFull, MaybeDecode, MaybeDecodeRef, MaybeDecoder,
+    },
+    ParseError,
+};
+
+use super::{EventCode, EventParameters};
+
+const SUBEVENT_CODE: u8 = 0x0D;
+
+/// What's left of the event parameters after the subevent code and the number of reports.
+const MAX_REPORTS_LEN: usize = 253;
+
+/// The fields of a report before its data.
+const ITEM_HEADER_LEN: usize = 24;
+
+/// The longest data a single report can carry, if it is the only one in its event.
+const MAX_DATA_LEN: usize = MAX_REPORTS_LEN - ITEM_HEADER_LEN;
+
+/// The advertisements whose data is being reassembled at once.
+const MAX_PARTIAL_ADVERTISEMENTS: usize = 2;
+
+/// Bits of [LeExtendedAdvertisingReportItem::event_type].
+pub const CONNECTABLE: u16 = 0x0001;
+pub const SCANNABLE: u16 = 0x0002;
+pub const DIRECTED: u16 = 0x0004;
+pub const SCAN_RESPONSE: u16 = 0x0008;
+/// The advertisement was made with legacy PDUs.
+pub const LEGACY: u16 = 0x0010;
+
+const DATA_STATUS_SHIFT: u16 = 5;
+const DATA_STATUS_MASK: u16 = 0b11 << DATA_STATUS_SHIFT;
+
+/// Whether the data of a report is all there is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataStatus {
+    Complete,
+    /// More of the data follows in the next report from the same advertiser.
+    MoreToCome,
+    /// The controller didn't receive the rest of the data.
+    Truncated,
+}
+
+impl DataStatus {
+    fn from_event_type(event_type: u16) -> DataStatus {
+        match (event_type & DATA_STATUS_MASK) >> DATA_STATUS_SHIFT {
+            0b00 => DataStatus::Complete,
+            0b01 => DataStatus::MoreToCome,
+            // The reserved value can't be relied on to be followed by more data either.
+            _ => DataStatus::Truncated,
+        }
+    }
+
+    fn bits(self) -> u16 {
+        let status = match self {
+            DataStatus::Complete => 0b00,
+            DataStatus::MoreToCome => 0b01,
+            DataStatus::Truncated => 0b10,
+        };
+        status << DATA_STATUS_SHIFT
+    }
+}
+
+/// An LE Extended Advertising Report borrowing its reports from the received event. See
+/// [LeAdvertisingReport](super::le_advertising_report::LeAdvertisingReport), which it extends
+/// with the PHYs, long data and more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeExtendedAdvertisingReport<'a> {
+    num_reports: u8,
+    data: &'a [u8],
+}
+
+impl<'a> MaybeDecodeRef<'a> for LeExtendedAdvertisingReport<'a> {
+    fn maybe_decode_ref(d: &mut &'a [u8]) -> Result<Option<Self>, DecodeError> {
+        let SUBEVENT_CODE = d.decode()? else {
+            return Ok(None);
+        };
+        let num_reports = d.decode()?;
+        if d.len() > MAX_REPORTS_LEN {
+            return Err(DecodeError::Malformed(
+                "advertising reports are longer than an event can be",
+            ));
+        }
+
+        Ok(Some(LeExtendedAdvertisingReport {
+            num_reports,
+            data: core::mem::take(d),
+        }))
+    }
+}
+
+impl Encode for LeExtendedAdvertisingReport<'_> {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&SUBEVENT_CODE)?;
+        e.encode(&self.num_reports)?;
+        e.encode(self.data)
+    }
+}
+
+impl<'a> EventParameters<'a> for LeExtendedAdvertisingReport<'a> {
+    const EVENT_CODE: EventCode = EventCode(0x3E);
+    const SUBEVENT_CODE: Option<u8> = Some(SUBEVENT_CODE);
+}
+
+impl<'a> LeExtendedAdvertisingReport<'a> {
+    pub fn items(&self) -> LeExtendedAdvertisingReportItems<'a> {
+        LeExtendedAdvertisingReportItems {
+            num_left: self.num_reports as usize,
+            data: self.data,
+        }
+    }
+
+    /// Copies the reports out of the received event.
+    pub fn into_owned(self) -> OwnedLeExtendedAdvertisingReport {
+        let mut data = Buffer::new();
+        // Decoding made sure that the reports fit.
+        let _ = data.write(self.data);
+
+        OwnedLeExtendedAdvertisingReport {
+            num_reports: self.num_reports,
+            data,
+        }
+    }
+}
+
+/// An LE Extended Advertising Report with its own copy of the reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedLeExtendedAdvertisingReport {
+    num_reports: u8,
+    data: Buffer<MAX_REPORTS_LEN>,
+}
+
+impl MaybeDecode for OwnedLeExtendedAdvertisingReport {
+    fn maybe_decode<D>(d: &mut D) -> Result<Option<Self>, DecodeError>
+    where
+        D: MaybeDecoder + ?Sized,
+    {
+        let SUBEVENT_CODE = d.decode()? else {
+            return Ok(None);
+        };
+
+        Ok(Some(OwnedLeExtendedAdvertisingReport {
+            num_reports: d.decode()?,
+            data: d.decode()?,
+        }))
+    }
+}
+
+impl Encode for OwnedLeExtendedAdvertisingReport {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        self.report().encode(e)
+    }
+}
+
+impl EventParameters<'_> for OwnedLeExtendedAdvertisingReport {
+    const EVENT_CODE: EventCode = EventCode(0x3E);
+    const SUBEVENT_CODE: Option<u8> = Some(SUBEVENT_CODE);
+}
+
+impl OwnedLeExtendedAdvertisingReport {
+    /// Puts `items` together into a report, as a controller would send them.
+    pub fn from_items<'a>(
+        items: impl IntoIterator<Item = LeExtendedAdvertisingReportItem<'a>>,
+    ) -> Result<Self, EncoderFull> {
+        let mut num_reports: u8 = 0;
+        let mut data = Buffer::new();
+        for item in items {
+            num_reports = num_reports.checked_add(1).ok_or(EncoderFull)?;
+            data.encode(&item)?;
+        }
+
+        Ok(OwnedLeExtendedAdvertisingReport { num_reports, data })
+    }
+
+    pub fn report(&self) -> LeExtendedAdvertisingReport<'_> {
+        LeExtendedAdvertisingReport {
+            num_reports: self.num_reports,
+            data: &self.data,
+        }
+    }
+
+    pub fn items(&self) -> LeExtendedAdvertisingReportItems<'_> {
+        self.report().items()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeExtendedAdvertisingReportItem<'a> {
+    /// The bits above and the [DataStatus].
+    pub event_type: u16,
+    pub address_type: u8,
+    pub address: Address,
+    pub primary_phy: u8,
+    /// 0x00 if nothing was received on a secondary PHY.
+    pub secondary_phy: u8,
+    /// 0xFF if the advertisement doesn't have one.
+    pub advertising_sid: u8,
+    /// In dBm, or 127 if it isn't available.
+    pub tx_power: i8,
+    /// In dBm, or 127 if it isn't available.
+    pub rssi: i8,
+    /// In units of 1.25 ms, or 0 if there is no periodic advertising.
+    pub periodic_advertising_interval: u16,
+    pub direct_address_type: u8,
+    pub direct_address: Address,
+    pub data: &'a [u8],
+}
+
+/// Fails if `data` is longer than a report can carry.
+impl Encode for LeExtendedAdvertisingReportItem<'_> {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        let data_length = u8::try_from(self.data.len())
+            .ok()
+            .filter(|&len| len as usize <= MAX_DATA_LEN)
+            .ok_or(EncoderFull)?;
+
+        e.encode(&self.event_type)?;
+        e.encode(&self.address_type)?;
+        e.encode(&self.address)?;
+        e.encode(&self.primary_phy)?;
+        e.encode(&self.secondary_phy)?;
+        e.encode(&self.advertising_sid)?;
+        e.encode(&self.tx_power)?;
+        e.encode(&self.rssi)?;
+        e.encode(&self.periodic_advertising_interval)?;
+        e.encode(&self.direct_address_type)?;
+        e.encode(&self.direct_address)?;
+        e.encode(&data_length)?;
+        e.encode(self.data)
+    }
+}
+
+impl LeExtendedAdvertisingReportItem<'_> {
+    pub fn data_status(&self) -> DataStatus {
+        DataStatus::from_event_type(self.event_type)
+    }
+}
+
+pub struct LeExtendedAdvertisingReportItems<'a> {
+    num_left: usize,
+    data: &'a [u8],
+}
+
+impl<'a> LeExtendedAdvertisingReportItems<'a> {
+    fn decode_item(&mut self) -> Result<LeExtendedAdvertisingReportItem<'a>, DecodeError> {
+        let d = &mut self.data;
+        let event_type = d.decode()?;
+        let address_type = d.decode()?;
+        let address = d.decode()?;
+        let primary_phy = d.decode()?;
+        let secondary_phy = d.decode()?;
+        let advertising_sid = d.decode()?;
+        let tx_power = d.decode()?;
+        let rssi = d.decode()?;
+        let periodic_advertising_interval = d.decode()?;
+        let direct_address_type = d.decode()?;
+        let direct_address = d.decode()?;
+        let data_length = d.decode::<u8>()? as usize;
+        if d.len() < data_length {
+            return Err(DecodeError::Empty);
+        }
+        let (data, rest) = d.split_at(data_length);
+        *d = rest;
+
+        Ok(LeExtendedAdvertisingReportItem {
+            event_type,
+            address_type,
+            address,
+            primary_phy,
+            secondary_phy,
+            advertising_sid,
+            tx_power,
+            rssi,
+            periodic_advertising_interval,
+            direct_address_type,
+            direct_address,
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for LeExtendedAdvertisingReportItems<'a> {
+    type Item = Result<LeExtendedAdvertisingReportItem<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.num_left == 0 {
+            return None;
+        }
+
+        self.num_left -= 1;
+        match self.decode_item() {
+            // The last report ends the event.
+            Ok(item) if self.num_left > 0 || self.data.is_empty() => Some(Ok(item)),
+            // Nothing tells where the next report starts after a malformed one.
+            _ => {
+                self.num_left = 0;
+                Some(Err(ParseError))
+            }
+        }
+    }
+}
+
+/// An extended advertisement with all of its data, put back together by an
+/// [ExtendedAdvertisingReassembler]. The other fields are those of its last report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedAdvertisement {
+    /// The bits above and the [DataStatus], which is never [DataStatus::MoreToCome].
+    pub event_type: u16,
+    pub address_type: u8,
+    pub address: Address,
+    pub primary_phy: u8,
+    pub secondary_phy: u8,
+    pub advertising_sid: u8,
+    pub tx_power: i8,
+    pub rssi: i8,
+    pub periodic_advertising_interval: u16,
+    pub direct_address_type: u8,
+    pub direct_address: Address,
+    pub data: Buffer<MAX_EXTENDED_ADVERTISING_DATA_LEN>,
+}
+
+impl ExtendedAdvertisement {
+    pub fn data_status(&self) -> DataStatus {
+        DataStatus::from_event_type(self.event_type)
+    }
+
+    fn new(item: &LeExtendedAdvertisingReportItem<'_>) -> ExtendedAdvertisement {
+        let mut advertisement = ExtendedAdvertisement {
+            event_type: 0,
+            address_type: 0,
+            address: Address([0; 6]),
+            primary_phy: 0,
+            secondary_phy: 0,
+            advertising_sid: 0,
+            tx_power: 0,
+            rssi: 0,
+            periodic_advertising_interval: 0,
+            direct_address_type: 0,
+            direct_address: Address([0; 6]),
+            data: Buffer::new(),
+        };
+        advertisement.update(item);
+        advertisement
+    }
+
+    fn update(&mut self, item: &LeExtendedAdvertisingReportItem<'_>) {
+        self.event_type = item.event_type;
+        self.address_type = item.address_type;
+        self.address = item.address;
+        self.primary_phy = item.primary_phy;
+        self.secondary_phy = item.secondary_phy;
+        self.advertising_sid = item.advertising_sid;
+        self.tx_power = item.tx_power;
+        self.rssi = item.rssi;
+        self.periodic_advertising_interval = item.periodic_advertising_interval;
+        self.direct_address_type = item.direct_address_type;
+        self.direct_address = item.direct_address;
+    }
+
+    fn chain(&self) -> Chain {
+        Chain {
+            address_type: self.address_type,
+            address: self.address,
+            advertising_sid: self.advertising_sid,
+            scan_response: self.event_type & SCAN_RESPONSE != 0,
+        }
+    }
+
+    /// Marks the data as truncated, since the rest of it is lost.
+    fn truncate(&mut self) {
+        self.event_type &= !DATA_STATUS_MASK;
+        self.event_type |= DataStatus::Truncated.bits();
+    }
+}
+
+/// Tells apart the chains of reports that the data of different advertisements arrives in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Chain {
+    address_type: u8,
+    address: Address,
+    advertising_sid: u8,
+    scan_response: bool,
+}
+
+impl Chain {
+    fn of(item: &LeExtendedAdvertisingReportItem<'_>) -> Chain {
+        Chain {
+            address_type: item.address_type,
+            address: item.address,
+            advertising_sid: item.advertising_sid,
+            scan_response: item.event_type & SCAN_RESPONSE != 0,
+        }
+    }
+}
+
+/// Puts the data of extended advertisements back together from the reports that it was split
+/// into. Data from a few advertisers can be in progress at once; if yet another one starts, the
+/// oldest is dropped, along with the rest of its reports.
+#[derive(Debug, Default)]
+pub struct ExtendedAdvertisingReassembler {
+    partial: Vec<ExtendedAdvertisement, MAX_PARTIAL_ADVERTISEMENTS>,
+    /// Chains whose advertisement was dropped or already returned truncated, so that the reports
+    /// still to come for them aren't taken for new advertisements.
+    discarded: Vec<Chain, MAX_PARTIAL_ADVERTISEMENTS>,
+}
+
+impl ExtendedAdvertisingReassembler {
+    pub fn new() -> ExtendedAdvertisingReassembler {
+        ExtendedAdvertisingReassembler {
+            partial: Vec::new(),
+            discarded: Vec::new(),
+        }
+    }
+
+    /// Adds `item` to the advertisement it belongs to, returning the advertisement once its data
+    /// is whole or truncated. Data beyond [MAX_EXTENDED_ADVERTISING_DATA_LEN] is truncated too,
+    /// and the rest of the reports of a dropped or truncated advertisement are ignored.
+    pub fn push(
+        &mut self,
+        item: &LeExtendedAdvertisingReportItem<'_>,
+    ) -> Option<ExtendedAdvertisement> {
+        let chain = Chain::of(item);
+        let more_to_come = item.data_status() == DataStatus::MoreToCome;
+
+        if let Some(index) = self.discarded.iter().position(|&c| c == chain) {
+            if !more_to_come {
+                self.discarded.remove(index);
+            }
+            return None;
+        }
+
+        let position = self
+            .partial
+            .iter()
+            .position(|advertisement| advertisement.chain() == chain);
+        let mut advertisement = match position {
+            Some(index) => {
+                let mut advertisement = self.partial.remove(index);
+                advertisement.update(item);
+                advertisement
+            }
+            None => ExtendedAdvertisement::new(item),
+        };
+
+        let room = MAX_EXTENDED_ADVERTISING_DATA_LEN - advertisement.data.len();
+        let (data, overflow) = item.data.split_at(item.data.len().min(room));
+        // It fits, as measured above.
+        let _ = advertisement.data.write(data);
+        if !overflow.is_empty() {
+            advertisement.truncate();
+            if more_to_come {
+                self.discard(chain);
+            }
+            return Some(advertisement);
+        }
+
+        if !more_to_come {
+            return Some(advertisement);
+        }
+        if self.partial.is_full() {
+            let oldest = self.partial.remove(0);
+            self.discard(oldest.chain());
+        }
+        // There is room now.
+        let _ = self.partial.push(advertisement);
+        None
+    }
+
+    /// Drops the advertisements in progress, as when scanning stops.
+    pub fn clear(&mut self) {
+        self.partial.clear();
+        self.discarded.clear();
+    }
+
+    /// Ignores the rest of the reports of `chain`, forgetting the oldest such chain if there are
+    /// too many.
+    fn discard(&mut self, chain: Chain) {
+        if self.discarded.is_full() {
+            self.discarded.remove(0);
+        }
+        // There is room now.
+        let _ = self.discarded.push(chain);
+    }
+}
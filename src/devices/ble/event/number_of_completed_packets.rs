@@ -0,0 +1,77 @@
+use crate::devices::ble::data::{
+    Buffer, Decode, DecodeError, Decoder, Encode, Encoder, EncoderFull,
+};
+
+use super::{EventCode, EventParameters};
+
+/// The longest the entries can be: as many whole entries as fit after the number of handles.
+const MAX_ENTRIES_LEN: usize = 252;
+
+/// Tells the host how many of the ACL data packets it sent on each connection the controller is
+/// done with, so that their buffers can be used again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberOfCompletedPackets {
+    num_handles: u8,
+    data: Buffer<MAX_ENTRIES_LEN>,
+}
+
+impl Decode for NumberOfCompletedPackets {
+    fn decode<D>(d: &mut D) -> Result<Self, DecodeError>
+    where
+        D: Decoder + ?Sized,
+    {
+        let num_handles: u8 = d.decode()?;
+        if d.available() != num_handles as usize * 4 {
+            return Err(DecodeError::Malformed(
+                "completed packets don't match the number of handles",
+            ));
+        }
+
+        Ok(NumberOfCompletedPackets {
+            num_handles,
+            data: d.decode()?,
+        })
+    }
+}
+
+impl Encode for NumberOfCompletedPackets {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&self.num_handles)?;
+        e.encode(&*self.data)
+    }
+}
+
+impl EventParameters<'_> for NumberOfCompletedPackets {
+    const EVENT_CODE: EventCode = EventCode(0x13);
+}
+
+impl NumberOfCompletedPackets {
+    /// Puts `(connection_handle, num_completed_packets)` entries together, as a controller would
+    /// send them.
+    pub fn from_entries(
+        entries: impl IntoIterator<Item = (u16, u16)>,
+    ) -> Result<Self, EncoderFull> {
+        let mut num_handles: u8 = 0;
+        let mut data = Buffer::new();
+        for (connection_handle, num_completed_packets) in entries {
+            num_handles = num_handles.checked_add(1).ok_or(EncoderFull)?;
+            data.encode(&connection_handle)?;
+            data.encode(&num_completed_packets)?;
+        }
+
+        Ok(NumberOfCompletedPackets { num_handles, data })
+    }
+
+    /// The `(connection_handle, num_completed_packets)` entries.
+    pub fn entries(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.data.chunks_exact(4).map(|entry| {
+            (
+                u16::from_le_bytes([entry[0], entry[1]]),
+                u16::from_le_bytes([entry[2], entry[3]]),
+            )
+        })
+    }
+}
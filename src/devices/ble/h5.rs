@@ -0,0 +1,652 @@
+//! The Three-wire UART (H5) transport.
+//!
+//! Controllers on a plain UART without hardware flow control, such as many boards running
+//! Zephyr's HCI UART sample in H5 mode, can't use H4 because a single dropped byte loses the
+//! framing for good. H5 wraps each HCI packet in a SLIP frame with a checksummed header, and
+//! makes commands, data and events reliable with sequence numbers, acknowledgements and
+//! retransmission.
+//!
+//! [H5] wraps the serial port and presents the same H4 byte stream as any other transport, so it
+//! can be handed straight to [Ble](super::Ble) or wrapped in a [Tap](super::capture::Tap).
+
+use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write};
+use heapless::Deque;
+
+use super::{
+    packet::{H4Assembler, PacketType, MAX_HEADER_LEN, MAX_PAYLOAD_LEN},
+    Clock,
+};
+
+const SLIP_DELIMITER: u8 = 0xC0;
+const SLIP_ESCAPE: u8 = 0xDB;
+const SLIP_ESCAPED_DELIMITER: u8 = 0xDC;
+const SLIP_ESCAPED_ESCAPE: u8 = 0xDD;
+
+const ACK_PACKET: u8 = 0x00;
+const LINK_CONTROL_PACKET: u8 = 0x0F;
+
+const SYNC: [u8; 2] = [0x01, 0x7E];
+const SYNC_RESPONSE: [u8; 2] = [0x02, 0x7D];
+const CONFIG: [u8; 2] = [0x03, 0xFC];
+const CONFIG_RESPONSE: [u8; 2] = [0x04, 0x7B];
+
+/// The largest sliding window this implementation offers, in reliable packets.
+pub const MAX_WINDOW: usize = 4;
+
+/// How long an unacknowledged packet or link establishment message waits before it is resent.
+pub const RETRANSMIT_MS: u64 = 250;
+
+/// The largest H5 payload: an HCI packet without its H4 indicator.
+const MAX_H5_PAYLOAD_LEN: usize = MAX_HEADER_LEN + MAX_PAYLOAD_LEN;
+
+/// A whole frame between delimiters: the header, the payload and the data integrity check.
+const MAX_FRAME_LEN: usize = 4 + MAX_H5_PAYLOAD_LEN + 2;
+
+/// Enough received H4 bytes for two of the largest packets, so that one can be read while the
+/// next arrives.
+const RECEIVED_LEN: usize = 2 * (1 + MAX_H5_PAYLOAD_LEN);
+
+/// The number of whole packets written by the host that may wait for room in the window.
+const PENDING_LEN: usize = 2;
+
+const CONFIG_WINDOW_MASK: u8 = 0b0000_0111;
+const CONFIG_DATA_INTEGRITY: u8 = 0b0001_0000;
+
+/// The configuration field we offer: our window size, no out-of-frame flow control, and support
+/// for the data integrity check.
+const CONFIG_FIELD: u8 = MAX_WINDOW as u8 | CONFIG_DATA_INTEGRITY;
+
+/// The link establishment state of an [H5] transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// Sending SYNC until the peer answers.
+    Uninitialized,
+    /// Synchronized, and sending CONFIG until the peer answers.
+    Initialized,
+    /// Configured, and carrying HCI packets.
+    Active,
+}
+
+/// An error from an [H5] transport.
+#[derive(Debug)]
+pub enum H5Error<E> {
+    /// An HCI packet written to the link is longer than an H5 payload can be. It was dropped.
+    PacketTooLong,
+    /// The peer sent SYNC on the active link, meaning the controller restarted, and the link is
+    /// being established again. The `dropped_commands` HCI commands that were still held or
+    /// unacknowledged were dropped, and the controller forgot everything else; start over with
+    /// [Ble::reset_controller](super::Ble::reset_controller), which gives back their command
+    /// credits.
+    PeerReset {
+        dropped_commands: usize,
+    },
+    Serial(E),
+}
+
+impl<E> From<E> for H5Error<E> {
+    fn from(value: E) -> Self {
+        Self::Serial(value)
+    }
+}
+
+impl<E: embedded_io::Error> embedded_io::Error for H5Error<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            H5Error::PacketTooLong => ErrorKind::InvalidInput,
+            H5Error::PeerReset { .. } => ErrorKind::ConnectionReset,
+            H5Error::Serial(e) => e.kind(),
+        }
+    }
+}
+
+/// Counters for the faults the link layer recovered from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct H5Stats {
+    /// Reliable packets sent again because they weren't acknowledged in time.
+    pub retransmissions: usize,
+    /// Reliable packets discarded because they weren't the one expected next.
+    pub out_of_order: usize,
+    /// Frames discarded for a bad header checksum, length or data integrity check.
+    pub bad_frames: usize,
+    /// Times the peer sent SYNC on an active link, meaning it restarted.
+    pub peer_resets: usize,
+}
+
+#[derive(Clone)]
+struct H5Packet {
+    packet_type: u8,
+    len: usize,
+    data: [u8; MAX_H5_PAYLOAD_LEN],
+}
+
+impl H5Packet {
+    /// Takes the HCI packet collected by `h4`, failing if it is too long for an H5 payload.
+    fn new<E>(h4: &H4Assembler) -> Result<H5Packet, H5Error<E>> {
+        let len = h4.len() - 1;
+        if len > MAX_H5_PAYLOAD_LEN {
+            return Err(H5Error::PacketTooLong);
+        }
+
+        let packet = h4.packet();
+        let mut data = [0; MAX_H5_PAYLOAD_LEN];
+        data[..len].copy_from_slice(&packet[1..]);
+        Ok(H5Packet {
+            packet_type: packet[0],
+            len,
+            data,
+        })
+    }
+
+    fn payload(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+struct SentPacket {
+    seq: u8,
+    packet: H5Packet,
+}
+
+/// An HCI transport that runs H5 over the serial port `S`, timing retransmissions with `K`.
+///
+/// The serial port is only read when it reports that it's ready, so reading from an [H5] never
+/// blocks; like the ESP32's own connector, it returns zero bytes when nothing has arrived. Link
+/// establishment starts on the first read or write, and HCI packets written before the link is
+/// active are held until it is.
+pub struct H5<S, K> {
+    serial: S,
+    clock: K,
+    state: LinkState,
+    window: usize,
+    data_integrity: bool,
+    /// The sequence number of the next reliable packet we send.
+    next_seq: u8,
+    /// The sequence number expected next from the peer, which is our acknowledgement number.
+    next_expected: u8,
+    ack_pending: bool,
+    /// When SYNC, CONFIG or the unacknowledged packets were last sent.
+    last_sent_ms: Option<u64>,
+    unacked: Deque<SentPacket, MAX_WINDOW>,
+    pending: Deque<H5Packet, PENDING_LEN>,
+    outgoing: H4Assembler,
+    frame: [u8; MAX_FRAME_LEN],
+    frame_len: usize,
+    escaped: bool,
+    received: Deque<u8, RECEIVED_LEN>,
+    /// The number of HCI commands dropped by a peer reset that hasn't been reported yet.
+    unreported_reset: Option<usize>,
+    stats: H5Stats,
+}
+
+impl<S, K> H5<S, K>
+where
+    S: Read + ReadReady + Write,
+    K: Clock,
+{
+    pub fn new(serial: S, clock: K) -> H5<S, K> {
+        H5 {
+            serial,
+            clock,
+            state: LinkState::Uninitialized,
+            window: 1,
+            data_integrity: false,
+            next_seq: 0,
+            next_expected: 0,
+            ack_pending: false,
+            last_sent_ms: None,
+            unacked: Deque::new(),
+            pending: Deque::new(),
+            outgoing: H4Assembler::new(),
+            frame: [0; MAX_FRAME_LEN],
+            frame_len: 0,
+            escaped: false,
+            received: Deque::new(),
+            unreported_reset: None,
+            stats: H5Stats::default(),
+        }
+    }
+
+    pub fn serial(&self) -> &S {
+        &self.serial
+    }
+
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state == LinkState::Active
+    }
+
+    /// The sliding window agreed with the peer, in reliable packets.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn stats(&self) -> H5Stats {
+        self.stats
+    }
+
+    /// Processes whatever has arrived on the serial port and sends whatever is due: link
+    /// establishment messages, packets waiting for the window, retransmissions and
+    /// acknowledgements. Reads and writes do this themselves; call it directly to keep the link
+    /// going while the host has nothing else to do.
+    pub fn poll(&mut self) -> Result<(), S::Error> {
+        let mut buf = [0; 32];
+        while self.serial.read_ready()? {
+            let len = self.serial.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            for &byte in &buf[..len] {
+                self.receive_byte(byte)?;
+            }
+        }
+
+        let now = self.clock.now_millis();
+        let due =
+            !matches!(self.last_sent_ms, Some(sent) if now.saturating_sub(sent) < RETRANSMIT_MS);
+
+        match self.state {
+            LinkState::Uninitialized => {
+                if due {
+                    self.send_link_control(&SYNC)?;
+                    self.last_sent_ms = Some(now);
+                }
+            }
+            LinkState::Initialized => {
+                if due {
+                    self.send_link_control(&[CONFIG[0], CONFIG[1], CONFIG_FIELD])?;
+                    self.last_sent_ms = Some(now);
+                }
+            }
+            LinkState::Active => {
+                if due && !self.unacked.is_empty() {
+                    // Go-back-N: the peer discards everything after a lost packet, so resend the
+                    // whole window in order.
+                    for i in 0..self.unacked.len() {
+                        let Some(sent) = self.unacked.iter().nth(i) else {
+                            break;
+                        };
+                        let packet = sent.packet.clone();
+                        self.send_frame(packet.packet_type, Some(sent.seq), packet.payload())?;
+                    }
+                    self.stats.retransmissions += self.unacked.len();
+                    self.last_sent_ms = Some(now);
+                }
+
+                while self.unacked.len() < self.window {
+                    let Some(packet) = self.pending.pop_front() else {
+                        break;
+                    };
+                    let seq = self.next_seq;
+                    self.next_seq = (seq + 1) % 8;
+                    self.send_frame(packet.packet_type, Some(seq), packet.payload())?;
+                    if self.unacked.is_empty() {
+                        self.last_sent_ms = Some(now);
+                    }
+                    // The window is never larger than the deque.
+                    let _ = self.unacked.push_back(SentPacket { seq, packet });
+                }
+            }
+        }
+
+        if self.ack_pending {
+            self.send_frame(ACK_PACKET, None, &[])?;
+        }
+
+        Ok(())
+    }
+
+    fn receive_byte(&mut self, byte: u8) -> Result<(), S::Error> {
+        match byte {
+            SLIP_DELIMITER => {
+                if self.frame_len > 0 {
+                    let len = self.frame_len;
+                    self.frame_len = 0;
+                    self.escaped = false;
+                    if len > MAX_FRAME_LEN {
+                        self.stats.bad_frames += 1;
+                    } else {
+                        let frame = self.frame;
+                        self.receive_frame(&frame[..len])?;
+                    }
+                }
+                return Ok(());
+            }
+            SLIP_ESCAPE => {
+                self.escaped = true;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let byte = match (self.escaped, byte) {
+            (true, SLIP_ESCAPED_DELIMITER) => SLIP_DELIMITER,
+            (true, SLIP_ESCAPED_ESCAPE) => SLIP_ESCAPE,
+            _ => byte,
+        };
+        self.escaped = false;
+
+        if let Some(slot) = self.frame.get_mut(self.frame_len) {
+            *slot = byte;
+        }
+        // Keep counting past the end so that an oversized frame is discarded as a whole.
+        self.frame_len += 1;
+
+        Ok(())
+    }
+
+    fn receive_frame(&mut self, frame: &[u8]) -> Result<(), S::Error> {
+        let Some(header) = frame.get(..4) else {
+            self.stats.bad_frames += 1;
+            return Ok(());
+        };
+
+        let checksum = header.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        let seq = header[0] & 0b111;
+        let ack = (header[0] >> 3) & 0b111;
+        let has_crc = header[0] & 0x40 != 0;
+        let reliable = header[0] & 0x80 != 0;
+        let packet_type = header[1] & 0x0F;
+        let payload_len = (header[1] >> 4) as usize | (header[2] as usize) << 4;
+
+        let crc_len = if has_crc { 2 } else { 0 };
+        if checksum != 0xFF || frame.len() != 4 + payload_len + crc_len {
+            self.stats.bad_frames += 1;
+            return Ok(());
+        }
+
+        let (packet, crc) = frame.split_at(4 + payload_len);
+        if has_crc && crc != crc_ccitt(packet).to_be_bytes() {
+            self.stats.bad_frames += 1;
+            return Ok(());
+        }
+        let payload = &packet[4..];
+
+        if packet_type == LINK_CONTROL_PACKET {
+            return self.receive_link_control(payload);
+        }
+
+        if self.state != LinkState::Active {
+            return Ok(());
+        }
+
+        self.receive_ack(ack);
+
+        if packet_type == ACK_PACKET {
+            return Ok(());
+        }
+
+        if reliable {
+            // Acknowledge even a packet we discard, so the peer learns what we expect next.
+            self.ack_pending = true;
+            if seq != self.next_expected {
+                self.stats.out_of_order += 1;
+                return Ok(());
+            }
+            if self.received.capacity() - self.received.len() < 1 + payload.len() {
+                // Leave it unacknowledged, and the peer will send it again once we've made room.
+                return Ok(());
+            }
+            self.next_expected = (seq + 1) % 8;
+        } else if self.received.capacity() - self.received.len() < 1 + payload.len() {
+            return Ok(());
+        }
+
+        for &byte in core::iter::once(&packet_type).chain(payload) {
+            // Room was checked above.
+            let _ = self.received.push_back(byte);
+        }
+
+        Ok(())
+    }
+
+    fn receive_link_control(&mut self, payload: &[u8]) -> Result<(), S::Error> {
+        let Some(message) = payload.get(..2) else {
+            self.stats.bad_frames += 1;
+            return Ok(());
+        };
+        let config = payload.get(2).copied().unwrap_or(0);
+
+        if message == SYNC {
+            if self.state == LinkState::Active {
+                self.stats.peer_resets += 1;
+                self.reset_link();
+            }
+            self.send_link_control(&SYNC_RESPONSE)?;
+        } else if message == SYNC_RESPONSE {
+            if self.state == LinkState::Uninitialized {
+                self.state = LinkState::Initialized;
+                self.last_sent_ms = None;
+            }
+        } else if message == CONFIG {
+            if self.state != LinkState::Uninitialized {
+                self.configure(config);
+                self.send_link_control(&[CONFIG_RESPONSE[0], CONFIG_RESPONSE[1], CONFIG_FIELD])?;
+            }
+        } else if message == CONFIG_RESPONSE && self.state == LinkState::Initialized {
+            self.configure(config);
+            self.state = LinkState::Active;
+            self.last_sent_ms = None;
+        }
+
+        Ok(())
+    }
+
+    /// Agrees on the lesser of the two windows, and on the data integrity check if both sides
+    /// support it.
+    fn configure(&mut self, config: u8) {
+        let window = (config & CONFIG_WINDOW_MASK) as usize;
+        self.window = window.clamp(1, MAX_WINDOW);
+        self.data_integrity = config & CONFIG_DATA_INTEGRITY != 0;
+    }
+
+    /// Releases the packets acknowledged by `ack`, the sequence number the peer expects next.
+    fn receive_ack(&mut self, ack: u8) {
+        let Some(oldest) = self.unacked.front().map(|sent| sent.seq) else {
+            return;
+        };
+        let acked = ((ack + 8 - oldest) % 8) as usize;
+        if acked == 0 || acked > self.unacked.len() {
+            return;
+        }
+        for _ in 0..acked {
+            self.unacked.pop_front();
+        }
+        // Restart the retransmission timer for whatever is still outstanding.
+        self.last_sent_ms = Some(self.clock.now_millis());
+    }
+
+    fn reset_link(&mut self) {
+        let dropped_commands = self
+            .unacked
+            .iter()
+            .map(|sent| &sent.packet)
+            .chain(&self.pending)
+            .filter(|packet| packet.packet_type == PacketType::COMMAND.0)
+            .count();
+        *self.unreported_reset.get_or_insert(0) += dropped_commands;
+
+        self.state = LinkState::Uninitialized;
+        self.window = 1;
+        self.data_integrity = false;
+        self.next_seq = 0;
+        self.next_expected = 0;
+        self.ack_pending = false;
+        self.last_sent_ms = None;
+        self.unacked.clear();
+        self.pending.clear();
+    }
+
+    fn send_link_control(&mut self, payload: &[u8]) -> Result<(), S::Error> {
+        // Link establishment happens before the data integrity check is agreed.
+        let data_integrity = core::mem::replace(&mut self.data_integrity, false);
+        let result = self.send_frame(LINK_CONTROL_PACKET, None, payload);
+        self.data_integrity = data_integrity;
+        result
+    }
+
+    /// Sends a frame, reliable if it has a sequence number, acknowledging everything received so
+    /// far.
+    fn send_frame(
+        &mut self,
+        packet_type: u8,
+        seq: Option<u8>,
+        payload: &[u8],
+    ) -> Result<(), S::Error> {
+        let mut flags = seq.unwrap_or(0) | self.next_expected << 3;
+        if self.data_integrity {
+            flags |= 0x40;
+        }
+        if seq.is_some() {
+            flags |= 0x80;
+        }
+
+        let len = payload.len();
+        let mut header = [
+            flags,
+            packet_type | (len as u8 & 0x0F) << 4,
+            (len >> 4) as u8,
+            0,
+        ];
+        header[3] = 0xFF - header[..3].iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+
+        self.serial.write_all(&[SLIP_DELIMITER])?;
+        slip_encode(&mut self.serial, &header)?;
+        slip_encode(&mut self.serial, payload)?;
+        if self.data_integrity {
+            let mut crc = [0; 4 + MAX_H5_PAYLOAD_LEN];
+            crc[..4].copy_from_slice(&header);
+            crc[4..4 + len].copy_from_slice(payload);
+            slip_encode(&mut self.serial, &crc_ccitt(&crc[..4 + len]).to_be_bytes())?;
+        }
+        self.serial.write_all(&[SLIP_DELIMITER])?;
+
+        self.ack_pending = false;
+        Ok(())
+    }
+}
+
+fn slip_encode<W: Write>(serial: &mut W, data: &[u8]) -> Result<(), W::Error> {
+    for chunk in data.split_inclusive(|&b| b == SLIP_DELIMITER || b == SLIP_ESCAPE) {
+        match chunk.split_last() {
+            Some((&SLIP_DELIMITER, rest)) => {
+                serial.write_all(rest)?;
+                serial.write_all(&[SLIP_ESCAPE, SLIP_ESCAPED_DELIMITER])?;
+            }
+            Some((&SLIP_ESCAPE, rest)) => {
+                serial.write_all(rest)?;
+                serial.write_all(&[SLIP_ESCAPE, SLIP_ESCAPED_ESCAPE])?;
+            }
+            _ => serial.write_all(chunk)?,
+        }
+    }
+    Ok(())
+}
+
+/// The data integrity check: CRC-CCITT computed least significant bit first from 0xFFFF, then
+/// bit-reversed so that it goes on the wire most significant byte first.
+fn crc_ccitt(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc.reverse_bits()
+}
+
+impl<S, K> H5<S, K>
+where
+    S: ErrorType,
+{
+    /// Reports a peer reset once, from the first read or write after it.
+    fn report_reset(&mut self) -> Result<(), H5Error<S::Error>> {
+        match self.unreported_reset.take() {
+            Some(dropped_commands) => Err(H5Error::PeerReset { dropped_commands }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S, K> ErrorType for H5<S, K>
+where
+    S: ErrorType,
+{
+    type Error = H5Error<S::Error>;
+}
+
+impl<S, K> Read for H5<S, K>
+where
+    S: Read + ReadReady + Write,
+    K: Clock,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.poll()?;
+        self.report_reset()?;
+
+        let mut len = 0;
+        while len < buf.len() {
+            let Some(byte) = self.received.pop_front() else {
+                break;
+            };
+            buf[len] = byte;
+            len += 1;
+        }
+
+        Ok(len)
+    }
+}
+
+impl<S, K> Write for H5<S, K>
+where
+    S: Read + ReadReady + Write,
+    K: Clock,
+{
+    /// Queues the HCI packets in `buf` for the link. Like a UART with a full FIFO, this blocks
+    /// while the window and the queue are both full, polling the link meanwhile so that
+    /// acknowledgements are taken in and lost packets are sent again when they are due.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.poll()?;
+        self.report_reset()?;
+
+        for &byte in buf {
+            if !self.outgoing.push(byte) {
+                continue;
+            }
+
+            if PacketType(self.outgoing.packet()[0]).header_len().is_none() {
+                self.outgoing.clear();
+                continue;
+            }
+            let packet = H5Packet::new(&self.outgoing);
+            self.outgoing.clear();
+            let packet = packet?;
+
+            while self.pending.is_full() {
+                self.poll()?;
+                self.report_reset()?;
+            }
+            // There's room now.
+            let _ = self.pending.push_back(packet);
+            self.poll()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.poll()?;
+        Ok(self.serial.flush()?)
+    }
+}
@@ -0,0 +1,378 @@
+use heapless::Deque;
+
+use super::{
+    acl::{AclState, L2capFrame},
+    command::{AnyCommand, EncodedCommand, HasOpcode},
+    controller::ControllerInfo,
+    data::{opcode::Opcode, DecodeError},
+    event::{
+        command_complete::CommandComplete, command_status::CommandStatus,
+        disconnection_complete::DisconnectionComplete,
+        number_of_completed_packets::NumberOfCompletedPackets, EncodedEvent, EventParameters,
+    },
+    packet::{self, AclPacket, FramingError, FramingStats, HciPacket, PacketType},
+    router::EventRouter,
+    BleError, QueueSlot, ACL_QUEUE_LEN, COMMAND_QUEUE_LEN,
+};
+
+/// Returned when a command is queued while the command queue is already full.
+pub(super) struct QueueFull;
+
+/// Tells queued commands apart, even ones with the same opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct CommandId(u32);
+
+/// Host-side bookkeeping shared by the blocking and async hosts. It performs no I/O itself;
+/// instead, it tells the caller which commands are ready to be written to the controller.
+pub(super) struct HostState {
+    /// The most recent num_hci_command_packets value received from the controller, decremented
+    /// whenever a command is sent. If this field is 0, no commands can be sent.
+    num_hci_command_packets: usize,
+    /// Commands waiting for the controller to grant credits, in the order they were queued.
+    queued_commands: Deque<(CommandId, EncodedCommand), COMMAND_QUEUE_LEN>,
+    /// The id of the next command to be queued.
+    next_command_id: u32,
+    /// The most recently polled event. Events decoded by the host borrow from it, so it stays here
+    /// until the next poll.
+    held_event: Option<EncodedEvent>,
+    /// Whether `held_event` wasn't what the caller was polling for and should be polled again.
+    event_pending: bool,
+    /// The number of events that no one was waiting for and no subscriber took.
+    pub(super) unrouted_events: usize,
+    /// Received ACL data packets, in the order they arrived.
+    pub(super) acl_packets: Deque<AclPacket, ACL_QUEUE_LEN>,
+    pub(super) acl: AclState,
+    /// The number of received packets the host had no use or no room for.
+    pub(super) dropped_packets: usize,
+    /// Set after a framing error, until a packet boundary has been found again.
+    resynchronizing: bool,
+    /// Bytes that were read while looking for a packet boundary but need to be looked at again.
+    lookahead: Deque<u8, { packet::MAX_HEADER_LEN }>,
+    pub(super) framing_stats: FramingStats,
+    /// Queue slots of commands whose wait ended in an error that has no room for them. They are
+    /// handed back once the controller has been reset, so resets don't forget them.
+    kept_slots: usize,
+    /// Set once the controller has been probed. Resets don't change what the controller supports,
+    /// so it is kept across them.
+    pub(super) controller_info: Option<ControllerInfo>,
+}
+
+impl HostState {
+    pub(super) fn new() -> HostState {
+        HostState {
+            // The host may send one command before hearing from the controller (Core v5.4, Vol 4,
+            // Part E, 4.4).
+            num_hci_command_packets: 1,
+            queued_commands: Deque::new(),
+            next_command_id: 0,
+            held_event: None,
+            event_pending: false,
+            unrouted_events: 0,
+            acl_packets: Deque::new(),
+            acl: AclState::new(),
+            dropped_packets: 0,
+            resynchronizing: false,
+            lookahead: Deque::new(),
+            framing_stats: FramingStats::default(),
+            kept_slots: 0,
+            controller_info: None,
+        }
+    }
+
+    /// Forgets all queued commands, events and data, as when the controller has just been reset.
+    /// Statistics are kept.
+    pub(super) fn reset(&mut self) {
+        self.num_hci_command_packets = 1;
+        self.queued_commands.clear();
+        self.held_event = None;
+        self.event_pending = false;
+        self.acl_packets.clear();
+        self.acl.reset();
+    }
+
+    /// Whether the controller supports the command with `opcode`, as far as the host knows.
+    pub(super) fn supports(&self, opcode: Opcode) -> bool {
+        match &self.controller_info {
+            Some(info) => info.supports(opcode),
+            None => true,
+        }
+    }
+
+    /// Whether the controller is known to support the command with `opcode`, which is only the
+    /// case once it has been probed.
+    pub(super) fn known_to_support(&self, opcode: Opcode) -> bool {
+        match &self.controller_info {
+            Some(info) => info.commands.supports(opcode) == Some(true),
+            None => false,
+        }
+    }
+
+    /// Fills the start of `buf` with bytes that must be looked at again before reading more from
+    /// the controller, returning how many there were.
+    pub(super) fn take_lookahead(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        while len < buf.len() {
+            let Some(byte) = self.lookahead.pop_front() else {
+                break;
+            };
+            buf[len] = byte;
+            len += 1;
+        }
+        len
+    }
+
+    /// Whether there are bytes that must be looked at before reading more from the controller.
+    #[cfg(feature = "async")]
+    pub(super) fn has_lookahead(&self) -> bool {
+        !self.lookahead.is_empty()
+    }
+
+    /// Keeps a byte that was read ahead to be looked at first. Only called when there is no other
+    /// lookahead, so there is always room.
+    #[cfg(feature = "async")]
+    pub(super) fn push_lookahead(&mut self, byte: u8) {
+        let _ = self.lookahead.push_back(byte);
+    }
+
+    /// Checks a received packet indicator, returning the length of the header that follows it, or
+    /// `None` if the byte should be skipped because the host is looking for a packet boundary.
+    pub(super) fn check_packet_type(
+        &mut self,
+        packet_type: PacketType,
+    ) -> Result<Option<usize>, FramingError> {
+        match packet_type.header_len() {
+            Some(header_len) if packet_type.is_from_controller() => Ok(Some(header_len)),
+            _ if self.resynchronizing => {
+                self.framing_stats.dropped_bytes += 1;
+                Ok(None)
+            }
+            _ => {
+                self.framing_stats.invalid_packet_types += 1;
+                self.framing_stats.dropped_bytes += 1;
+                self.resynchronizing = true;
+                Err(FramingError::InvalidPacketType(packet_type.0))
+            }
+        }
+    }
+
+    /// Checks a received packet header, returning the length of the payload that follows it, or
+    /// `None` if the header should be skipped because the host is looking for a packet boundary.
+    pub(super) fn check_header(
+        &mut self,
+        packet_type: PacketType,
+        header: &[u8],
+    ) -> Result<Option<usize>, FramingError> {
+        let len = packet_type.payload_len(header);
+
+        if self.resynchronizing {
+            if packet_type.is_plausible_header(header) {
+                self.resynchronizing = false;
+                self.framing_stats.resyncs += 1;
+                Ok(Some(len))
+            } else {
+                // Only the packet indicator is dropped, since a packet may start within the header.
+                self.framing_stats.dropped_bytes += 1;
+                for &byte in header.iter().rev() {
+                    // The header was read after any earlier lookahead, so there is always room.
+                    let _ = self.lookahead.push_front(byte);
+                }
+                Ok(None)
+            }
+        } else if len > packet_type.max_payload_len() {
+            self.framing_stats.invalid_lengths += 1;
+            self.framing_stats.dropped_bytes += 1 + header.len();
+            self.resynchronizing = true;
+            Err(FramingError::InvalidLength { packet_type, len })
+        } else {
+            Ok(Some(len))
+        }
+    }
+
+    /// Consumes a command credit so that a command can be sent right away, returning false if the
+    /// controller can't accept any more commands or earlier commands are still waiting.
+    pub(super) fn take_credit(&mut self) -> bool {
+        if self.num_hci_command_packets == 0 || !self.queued_commands.is_empty() {
+            return false;
+        }
+
+        self.num_hci_command_packets -= 1;
+        true
+    }
+
+    /// Adds `command` to the back of the command queue. Use [HostState::next_command] to find out
+    /// when it can be sent.
+    pub(super) fn queue(&mut self, command: EncodedCommand) -> Result<CommandId, QueueFull> {
+        let id = CommandId(self.next_command_id);
+        self.queued_commands
+            .push_back((id, command))
+            .map_err(|_| QueueFull)?;
+        self.next_command_id = self.next_command_id.wrapping_add(1);
+        Ok(id)
+    }
+
+    /// Keeps `qslot` until the controller has been reset, since `error` has no room for it.
+    pub(super) fn keep_slot<E>(&mut self, _qslot: QueueSlot, error: BleError<E>) -> BleError<E> {
+        self.kept_slots += 1;
+        error
+    }
+
+    /// Takes back a slot kept by [HostState::keep_slot].
+    pub(super) fn take_kept_slot(&mut self) -> Option<QueueSlot> {
+        self.kept_slots = self.kept_slots.checked_sub(1)?;
+        Some(QueueSlot)
+    }
+
+    /// Gives up on the command queued as `id`, which the controller never answered. If it was
+    /// never sent it is taken out of the queue, and otherwise its command credit is given back.
+    pub(super) fn abandon(&mut self, id: CommandId) {
+        let queued = self.queued_commands.len();
+        let mut found = false;
+        for _ in 0..queued {
+            let Some(entry) = self.queued_commands.pop_front() else {
+                break;
+            };
+            if entry.0 == id {
+                found = true;
+            } else {
+                // There is room, since a command was just taken out.
+                let _ = self.queued_commands.push_back(entry);
+            }
+        }
+
+        if !found {
+            self.num_hci_command_packets += 1;
+        }
+    }
+
+    /// Takes the next queued command if the controller can accept it, consuming a command credit.
+    pub(super) fn next_command(&mut self) -> Option<EncodedCommand> {
+        if self.num_hci_command_packets == 0 {
+            return None;
+        }
+
+        let (_, command) = self.queued_commands.pop_front()?;
+        self.num_hci_command_packets -= 1;
+        Some(command)
+    }
+
+    /// Keeps `event` to be polled again.
+    pub(super) fn requeue(&mut self, event: EncodedEvent) {
+        self.held_event = Some(event);
+        self.event_pending = true;
+    }
+
+    /// Whether there is an event that should be polled again.
+    #[cfg(feature = "async")]
+    pub(super) fn event_pending(&self) -> bool {
+        self.event_pending
+    }
+
+    /// Takes the event that should be polled again, if there is one.
+    pub(super) fn take_pending(&mut self) -> Option<EncodedEvent> {
+        if !self.event_pending {
+            return None;
+        }
+
+        self.event_pending = false;
+        self.held_event.take()
+    }
+
+    /// Holds on to `event` and decodes it as `Ev`, borrowing from it. If it isn't an `Ev`, it is
+    /// kept to be polled again.
+    pub(super) fn hold<'a, Ev: EventParameters<'a>>(
+        &'a mut self,
+        event: EncodedEvent,
+    ) -> Result<Option<Ev>, DecodeError> {
+        let held: &'a EncodedEvent = self.held_event.insert(event);
+        let decoded = held.decode::<Ev>()?;
+        self.event_pending = decoded.is_none();
+
+        Ok(decoded)
+    }
+
+    /// Like [HostState::hold], but hands `event` over to `router` if it isn't an `Ev`.
+    pub(super) fn hold_or_route<'a, Ev: EventParameters<'a>, R: EventRouter>(
+        &'a mut self,
+        router: &mut R,
+        event: EncodedEvent,
+    ) -> Result<Option<Ev>, DecodeError> {
+        let held: &'a EncodedEvent = self.held_event.insert(event);
+        let decoded = held.decode::<Ev>()?;
+        if decoded.is_none() && !router.route(held)? {
+            self.unrouted_events += 1;
+        }
+
+        Ok(decoded)
+    }
+
+    /// Hands `event` over to `router`, counting it if no one takes it.
+    pub(super) fn route<R: EventRouter>(
+        &mut self,
+        router: &mut R,
+        event: &EncodedEvent,
+    ) -> Result<(), DecodeError> {
+        if !router.route(event)? {
+            self.unrouted_events += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Takes a received packet that isn't an event.
+    pub(super) fn receive_data(&mut self, packet: HciPacket) {
+        let stored = match packet {
+            HciPacket::AclData(acl) => self.acl_packets.push_back(acl).is_ok(),
+            // Controllers don't send commands, and the host doesn't support synchronous or isochronous
+            // connections.
+            _ => false,
+        };
+
+        if !stored {
+            self.dropped_packets += 1;
+        }
+    }
+
+    /// Adds a received ACL data packet to the frame on its connection, returning the frame once
+    /// it is whole.
+    pub(super) fn reassemble(&mut self, packet: AclPacket) -> Option<L2capFrame> {
+        self.acl.reassemble(packet, &mut self.dropped_packets)
+    }
+
+    /// Updates the command and ACL data flow control state from `event`.
+    pub(super) fn receive(&mut self, event: &EncodedEvent) -> Result<(), DecodeError> {
+        if let Some(event) = event.decode::<CommandComplete<AnyCommand>>()? {
+            self.num_hci_command_packets = event.num_hci_command_packets as usize;
+        } else if let Some(event) = event.decode::<CommandStatus<AnyCommand>>()? {
+            self.num_hci_command_packets = event.num_hci_command_packets as usize;
+        } else if let Some(event) = event.decode::<NumberOfCompletedPackets>()? {
+            for (connection_handle, count) in event.entries() {
+                self.acl.complete(connection_handle, count);
+            }
+        } else if let Some(event) = event.decode::<DisconnectionComplete>()? {
+            if event.status.is_successful() {
+                self.acl.disconnect(event.connection_handle);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the H4 packet indicator and command packet header for `command`.
+pub(super) fn command_header(command: &EncodedCommand) -> [u8; 4] {
+    let [opcode_lo, opcode_hi] = command.opcode().0.to_le_bytes();
+    [
+        PacketType::COMMAND.0,
+        opcode_lo,
+        opcode_hi,
+        command.parameters.len() as u8,
+    ]
+}
+
+/// Returns the H4 packet indicator and ACL data packet header for a fragment of `len` bytes.
+pub(super) fn acl_header(connection_handle: u16, packet_boundary_flag: u8, len: usize) -> [u8; 5] {
+    let [handle_lo, handle_hi] =
+        (connection_handle | (packet_boundary_flag as u16) << 12).to_le_bytes();
+    let [len_lo, len_hi] = (len as u16).to_le_bytes();
+    [PacketType::ACL_DATA.0, handle_lo, handle_hi, len_lo, len_hi]
+}
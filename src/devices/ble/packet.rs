@@ -0,0 +1,313 @@
+//! HCI packets as they are framed by the H4 (UART) transport: a one-byte packet indicator followed
+//! by the packet itself.
+
+use super::{
+    command::EncodedCommand,
+    data::{opcode::Opcode, Buffer, DecodeError, Decoder, Encode, Encoder, EncoderFull},
+    event::{EncodedEvent, EventCode},
+};
+
+/// The maximum length of a packet header, excluding the packet indicator.
+pub const MAX_HEADER_LEN: usize = 4;
+
+/// The maximum payload length of any packet the host accepts.
+pub const MAX_PAYLOAD_LEN: usize = 255;
+
+/// The maximum data length of an ACL packet the host accepts. This is the largest LE ACL data
+/// length a controller may support.
+pub const MAX_ACL_DATA_LEN: usize = 251;
+
+/// The maximum data length of an ISO packet the host accepts.
+pub const MAX_ISO_DATA_LEN: usize = 251;
+
+/// The maximum length of a whole H4 packet, including the packet indicator.
+pub const MAX_H4_PACKET_LEN: usize = 1 + MAX_HEADER_LEN + MAX_PAYLOAD_LEN;
+
+/// An H4 packet indicator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketType(pub u8);
+
+impl PacketType {
+    pub const COMMAND: PacketType = PacketType(0x01);
+    pub const ACL_DATA: PacketType = PacketType(0x02);
+    pub const SYNCHRONOUS_DATA: PacketType = PacketType(0x03);
+    pub const EVENT: PacketType = PacketType(0x04);
+    pub const ISO_DATA: PacketType = PacketType(0x05);
+
+    /// The length of the header that follows the packet indicator, or `None` if this isn't a known
+    /// packet type.
+    pub fn header_len(self) -> Option<usize> {
+        match self {
+            PacketType::COMMAND => Some(3),
+            PacketType::ACL_DATA => Some(4),
+            PacketType::SYNCHRONOUS_DATA => Some(3),
+            PacketType::EVENT => Some(2),
+            PacketType::ISO_DATA => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Whether a controller may send packets of this type to the host.
+    pub fn is_from_controller(self) -> bool {
+        matches!(
+            self,
+            PacketType::ACL_DATA
+                | PacketType::SYNCHRONOUS_DATA
+                | PacketType::EVENT
+                | PacketType::ISO_DATA
+        )
+    }
+
+    /// The longest payload the host accepts in a packet of this type.
+    pub fn max_payload_len(self) -> usize {
+        match self {
+            PacketType::ACL_DATA => MAX_ACL_DATA_LEN,
+            PacketType::ISO_DATA => MAX_ISO_DATA_LEN,
+            _ => MAX_PAYLOAD_LEN,
+        }
+    }
+
+    /// Whether `header` looks like the start of a real packet. This is stricter than what is
+    /// accepted during normal operation, and is used to find a packet boundary after the byte
+    /// stream got out of sync.
+    pub fn is_plausible_header(self, header: &[u8]) -> bool {
+        if self.payload_len(header) > self.max_payload_len() {
+            return false;
+        }
+
+        match self {
+            // Event codes are allocated contiguously from 0x01, with 0xFF for vendor events.
+            PacketType::EVENT => matches!(header[0], 0x01..=0x5A | 0xFF),
+            // Controllers only send first (0b10) and continuing (0b01) fragments.
+            PacketType::ACL_DATA => matches!(header[1] >> 4 & 0b11, 0b01 | 0b10),
+            _ => true,
+        }
+    }
+
+    /// Reads the payload length out of `header`, which must be [PacketType::header_len] bytes
+    /// long.
+    pub fn payload_len(self, header: &[u8]) -> usize {
+        match self {
+            PacketType::COMMAND => header[2] as usize,
+            PacketType::ACL_DATA => u16::from_le_bytes([header[2], header[3]]) as usize,
+            PacketType::SYNCHRONOUS_DATA => header[2] as usize,
+            PacketType::EVENT => header[1] as usize,
+            PacketType::ISO_DATA => (u16::from_le_bytes([header[2], header[3]]) & 0x3FFF) as usize,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AclPacket {
+    pub connection_handle: u16,
+    pub packet_boundary_flag: u8,
+    pub broadcast_flag: u8,
+    pub data: Buffer<MAX_ACL_DATA_LEN>,
+}
+
+#[derive(Debug)]
+pub struct SynchronousPacket {
+    pub connection_handle: u16,
+    pub packet_status_flag: u8,
+    pub data: Buffer<255>,
+}
+
+#[derive(Debug)]
+pub struct IsoPacket {
+    pub connection_handle: u16,
+    pub packet_boundary_flag: u8,
+    pub time_stamp_flag: u8,
+    /// The ISO data load, including the time stamp and packet sequence number fields.
+    pub data: Buffer<MAX_ISO_DATA_LEN>,
+}
+
+/// Collects a stream of H4 bytes into whole packets, for transports and taps that see the stream
+/// a few bytes at a time.
+pub(super) struct H4Assembler {
+    buf: [u8; MAX_H4_PACKET_LEN],
+    /// The number of bytes of the current packet seen so far, which may exceed the buffer length.
+    seen: usize,
+    /// The total length of the current packet, once its header has been seen.
+    total: Option<usize>,
+}
+
+impl H4Assembler {
+    pub(super) fn new() -> H4Assembler {
+        H4Assembler {
+            buf: [0; MAX_H4_PACKET_LEN],
+            seen: 0,
+            total: None,
+        }
+    }
+
+    /// Adds `byte` to the current packet, returning whether the packet is complete. A byte that
+    /// isn't a known packet indicator is a complete packet on its own.
+    pub(super) fn push(&mut self, byte: u8) -> bool {
+        if let Some(slot) = self.buf.get_mut(self.seen) {
+            *slot = byte;
+        }
+        self.seen += 1;
+
+        let packet_type = PacketType(self.buf[0]);
+        let Some(header_len) = packet_type.header_len() else {
+            return true;
+        };
+
+        if self.total.is_none() && self.seen == 1 + header_len {
+            let payload_len = packet_type.payload_len(&self.buf[1..1 + header_len]);
+            self.total = Some(1 + header_len + payload_len);
+        }
+
+        self.total == Some(self.seen)
+    }
+
+    /// The current packet, truncated to [MAX_H4_PACKET_LEN] bytes.
+    pub(super) fn packet(&self) -> &[u8] {
+        &self.buf[..self.seen.min(MAX_H4_PACKET_LEN)]
+    }
+
+    /// The length of the current packet before truncation.
+    pub(super) fn len(&self) -> usize {
+        self.seen
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.seen = 0;
+        self.total = None;
+    }
+}
+
+/// A sign that the byte stream from the controller is out of sync with packet boundaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramingError {
+    /// A packet indicator that isn't a known packet type, or one the controller never sends.
+    InvalidPacketType(u8),
+    /// A packet whose payload is longer than the host accepts for its type.
+    InvalidLength { packet_type: PacketType, len: usize },
+}
+
+/// Counts of framing errors and recovery from them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FramingStats {
+    pub invalid_packet_types: usize,
+    pub invalid_lengths: usize,
+    /// Bytes thrown away while looking for a packet boundary.
+    pub dropped_bytes: usize,
+    /// The number of times a packet boundary was found again after a framing error.
+    pub resyncs: usize,
+}
+
+pub enum HciPacket {
+    Command(EncodedCommand),
+    AclData(AclPacket),
+    SynchronousData(SynchronousPacket),
+    Event(EncodedEvent),
+    IsoData(IsoPacket),
+}
+
+impl HciPacket {
+    pub fn packet_type(&self) -> PacketType {
+        match self {
+            HciPacket::Command(_) => PacketType::COMMAND,
+            HciPacket::AclData(_) => PacketType::ACL_DATA,
+            HciPacket::SynchronousData(_) => PacketType::SYNCHRONOUS_DATA,
+            HciPacket::Event(_) => PacketType::EVENT,
+            HciPacket::IsoData(_) => PacketType::ISO_DATA,
+        }
+    }
+
+    /// Decodes a packet of type `packet_type` from its `header` and `payload`.
+    pub fn decode(
+        packet_type: PacketType,
+        mut header: &[u8],
+        mut payload: &[u8],
+    ) -> Result<HciPacket, DecodeError> {
+        let header = &mut header;
+        let payload = &mut payload;
+
+        Ok(match packet_type {
+            PacketType::COMMAND => HciPacket::Command(EncodedCommand {
+                opcode: header.decode::<Opcode>()?,
+                parameters: payload.decode()?,
+            }),
+            PacketType::ACL_DATA => {
+                let handle = header.decode::<u16>()?;
+                HciPacket::AclData(AclPacket {
+                    connection_handle: handle & 0x0FFF,
+                    packet_boundary_flag: (handle >> 12) as u8 & 0b11,
+                    broadcast_flag: (handle >> 14) as u8 & 0b11,
+                    data: payload.decode()?,
+                })
+            }
+            PacketType::SYNCHRONOUS_DATA => {
+                let handle = header.decode::<u16>()?;
+                HciPacket::SynchronousData(SynchronousPacket {
+                    connection_handle: handle & 0x0FFF,
+                    packet_status_flag: (handle >> 12) as u8 & 0b11,
+                    data: payload.decode()?,
+                })
+            }
+            PacketType::EVENT => HciPacket::Event(EncodedEvent {
+                code: EventCode(header.decode()?),
+                parameters: payload.decode()?,
+            }),
+            PacketType::ISO_DATA => {
+                let handle = header.decode::<u16>()?;
+                HciPacket::IsoData(IsoPacket {
+                    connection_handle: handle & 0x0FFF,
+                    packet_boundary_flag: (handle >> 12) as u8 & 0b11,
+                    time_stamp_flag: (handle >> 14) as u8 & 0b1,
+                    data: payload.decode()?,
+                })
+            }
+            _ => return Err(DecodeError::Malformed("unknown packet type")),
+        })
+    }
+}
+
+/// Encodes the packet with its H4 packet indicator.
+impl Encode for HciPacket {
+    fn encode<E>(&self, e: &mut E) -> Result<(), EncoderFull>
+    where
+        E: Encoder + ?Sized,
+    {
+        e.encode(&self.packet_type().0)?;
+
+        match self {
+            HciPacket::Command(command) => {
+                e.encode(&command.opcode.0)?;
+                e.encode(&(command.parameters.len() as u8))?;
+                e.encode(&*command.parameters)
+            }
+            HciPacket::AclData(acl) => {
+                e.encode(
+                    &(acl.connection_handle
+                        | (acl.packet_boundary_flag as u16) << 12
+                        | (acl.broadcast_flag as u16) << 14),
+                )?;
+                e.encode(&(acl.data.len() as u16))?;
+                e.encode(&*acl.data)
+            }
+            HciPacket::SynchronousData(sco) => {
+                e.encode(&(sco.connection_handle | (sco.packet_status_flag as u16) << 12))?;
+                e.encode(&(sco.data.len() as u8))?;
+                e.encode(&*sco.data)
+            }
+            HciPacket::Event(event) => {
+                e.encode(&event.code.0)?;
+                e.encode(&(event.parameters.len() as u8))?;
+                e.encode(&*event.parameters)
+            }
+            HciPacket::IsoData(iso) => {
+                e.encode(
+                    &(iso.connection_handle
+                        | (iso.packet_boundary_flag as u16) << 12
+                        | (iso.time_stamp_flag as u16) << 14),
+                )?;
+                e.encode(&(iso.data.len() as u16))?;
+                e.encode(&*iso.data)
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Delivery of events that the host itself isn't waiting for.
+//!
+//! Components subscribe to the event types they care about, either with a bounded [Subscription]
+//! queue or a [Handler] callback, and the subscriptions are combined into a single [EventRouter]
+//! with a tuple, e.g. `(adverts, disconnections, hardware_errors)`. Every event is offered to every
+//! subscription.
+//!
+//! Since the router knows which event types are wanted, it can also tell the controller to only
+//! send those, with [EventRouter::event_masks].
+
+use core::marker::PhantomData;
+
+use heapless::Deque;
+
+use super::{
+    data::{event_mask::EventMasks, DecodeError},
+    event::{EncodedEvent, EventParameters},
+};
+
+/// Receives events that the host itself isn't waiting for.
+pub trait EventRouter {
+    /// Offers `event` to the router, returning whether any subscriber took it.
+    fn route(&mut self, event: &EncodedEvent) -> Result<bool, DecodeError>;
+
+    /// Enables the event types that the router has subscribers for in `masks`.
+    fn add_events(&self, masks: &mut EventMasks);
+
+    /// The masks that enable exactly the event types the router has subscribers for, and the
+    /// [EventMasks::HOST] ones. Add the events the application polls for itself before sending
+    /// them to the controller.
+    fn event_masks(&self) -> EventMasks {
+        let mut masks = EventMasks::HOST;
+        self.add_events(&mut masks);
+        masks
+    }
+}
+
+/// The router with no subscribers.
+impl EventRouter for () {
+    fn route(&mut self, _event: &EncodedEvent) -> Result<bool, DecodeError> {
+        Ok(false)
+    }
+
+    fn add_events(&self, _masks: &mut EventMasks) {}
+}
+
+impl<R: EventRouter + ?Sized> EventRouter for &mut R {
+    fn route(&mut self, event: &EncodedEvent) -> Result<bool, DecodeError> {
+        (**self).route(event)
+    }
+
+    fn add_events(&self, masks: &mut EventMasks) {
+        (**self).add_events(masks)
+    }
+}
+
+macro_rules! impl_event_router_for_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: EventRouter),+> EventRouter for ($($name,)+) {
+            fn route(&mut self, event: &EncodedEvent) -> Result<bool, DecodeError> {
+                #[allow(non_snake_case)]
+                let ($($name,)+) = self;
+                let mut taken = false;
+                $(taken |= $name.route(event)?;)+
+                Ok(taken)
+            }
+
+            fn add_events(&self, masks: &mut EventMasks) {
+                #[allow(non_snake_case)]
+                let ($($name,)+) = self;
+                $($name.add_events(masks);)+
+            }
+        }
+    };
+}
+
+impl_event_router_for_tuple!(A);
+impl_event_router_for_tuple!(A B);
+impl_event_router_for_tuple!(A B C);
+impl_event_router_for_tuple!(A B C D);
+impl_event_router_for_tuple!(A B C D E);
+impl_event_router_for_tuple!(A B C D E F);
+impl_event_router_for_tuple!(A B C D E F G);
+impl_event_router_for_tuple!(A B C D E F G H);
+
+/// A bounded queue of events of type `Ev`. When the queue is full, newer events are counted as
+/// missed rather than stored.
+///
+/// The queued events outlive the receive buffer, so `Ev` has to own its data, e.g.
+/// [OwnedLeAdvertisingReport](super::event::le_advertising_report::OwnedLeAdvertisingReport).
+pub struct Subscription<Ev, const N: usize> {
+    events: Deque<Ev, N>,
+    missed: usize,
+}
+
+impl<Ev, const N: usize> Subscription<Ev, N> {
+    pub fn new() -> Subscription<Ev, N> {
+        Subscription {
+            events: Deque::new(),
+            missed: 0,
+        }
+    }
+
+    /// Takes the oldest event from the queue.
+    pub fn pop(&mut self) -> Option<Ev> {
+        self.events.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The number of events that arrived while the queue was full.
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+}
+
+impl<Ev, const N: usize> Default for Subscription<Ev, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Ev: for<'a> EventParameters<'a>, const N: usize> EventRouter for Subscription<Ev, N> {
+    fn route(&mut self, event: &EncodedEvent) -> Result<bool, DecodeError> {
+        let Some(event) = event.decode::<Ev>()? else {
+            return Ok(false);
+        };
+
+        if self.events.push_back(event).is_err() {
+            self.missed += 1;
+        }
+
+        Ok(true)
+    }
+
+    fn add_events(&self, masks: &mut EventMasks) {
+        masks.add::<Ev>();
+    }
+}
+
+/// Calls a function with every event of type `Ev`, which has to own its data as for
+/// [Subscription].
+pub struct Handler<Ev, F> {
+    _phantom: PhantomData<fn(Ev)>,
+    f: F,
+}
+
+impl<Ev, F> Handler<Ev, F>
+where
+    Ev: for<'a> EventParameters<'a>,
+    F: FnMut(Ev),
+{
+    pub fn new(f: F) -> Handler<Ev, F> {
+        Handler {
+            _phantom: PhantomData,
+            f,
+        }
+    }
+}
+
+impl<Ev, F> EventRouter for Handler<Ev, F>
+where
+    Ev: for<'a> EventParameters<'a>,
+    F: FnMut(Ev),
+{
+    fn route(&mut self, event: &EncodedEvent) -> Result<bool, DecodeError> {
+        let Some(event) = event.decode::<Ev>()? else {
+            return Ok(false);
+        };
+
+        (self.f)(event);
+
+        Ok(true)
+    }
+
+    fn add_events(&self, masks: &mut EventMasks) {
+        masks.add::<Ev>();
+    }
+}
@@ -0,0 +1,165 @@
+//! Running the host on a workstation.
+//!
+//! [StdTransport] adapts any [std::io::Read] + [std::io::Write] stream carrying H4 (a pty, a
+//! Unix socket, or a TCP connection to a software controller such as Bumble or Zephyr's
+//! native_sim controller) into a transport for [Ble](super::Ble). [StdDelay] and [StdClock] fill
+//! in the rest of what the host needs from the platform.
+
+use std::{
+    io::{self, ErrorKind},
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::{ErrorType, Read, Write};
+
+use super::{packet::H4Assembler, Clock};
+
+/// How long a read waits for the controller before reporting that nothing has arrived, for the
+/// streams opened by [StdTransport::connect_tcp] and [StdTransport::connect_unix].
+pub const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// An H4 transport over a [std::io] stream.
+///
+/// If the stream is non-blocking or has a read timeout, a read that finds nothing between
+/// packets returns zero bytes, which the host treats as "nothing yet" just like on the watch. In
+/// the middle of a packet the read keeps waiting instead, so a packet split across TCP segments
+/// is never mistaken for a truncated one. With a plain blocking stream every read blocks until
+/// the controller sends something.
+///
+/// The stream closing is reported as an [ErrorKind::UnexpectedEof] error rather than as zero
+/// bytes, so that a controller going away isn't polled forever.
+pub struct StdTransport<T> {
+    inner: T,
+    incoming: H4Assembler,
+}
+
+impl<T> StdTransport<T>
+where
+    T: io::Read + io::Write,
+{
+    pub fn new(inner: T) -> StdTransport<T> {
+        StdTransport {
+            inner,
+            incoming: H4Assembler::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl StdTransport<TcpStream> {
+    /// Connects to a controller serving H4 over TCP, like Bumble's `tcp-server` transport.
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> io::Result<StdTransport<TcpStream>> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(StdTransport::new(stream))
+    }
+}
+
+#[cfg(unix)]
+impl StdTransport<std::os::unix::net::UnixStream> {
+    /// Connects to a controller serving H4 on a Unix socket.
+    pub fn connect_unix(
+        path: impl AsRef<std::path::Path>,
+    ) -> io::Result<StdTransport<std::os::unix::net::UnixStream>> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(StdTransport::new(stream))
+    }
+}
+
+impl<T> ErrorType for StdTransport<T> {
+    type Error = io::Error;
+}
+
+impl<T> Read for StdTransport<T>
+where
+    T: io::Read + io::Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.inner.read(buf) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(len) => {
+                    for &byte in &buf[..len] {
+                        if self.incoming.push(byte) {
+                            self.incoming.clear();
+                        }
+                    }
+                    return Ok(len);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if self.incoming.len() == 0 {
+                        return Ok(0);
+                    }
+                    thread::yield_now();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<T> Write for StdTransport<T>
+where
+    T: io::Read + io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.inner.flush()
+    }
+}
+
+/// Delays by sleeping the current thread.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdDelay;
+
+impl DelayNs for StdDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        thread::sleep(Duration::from_nanos(ns.into()));
+    }
+}
+
+/// Milliseconds since the clock was created.
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    start: Instant,
+}
+
+impl StdClock {
+    pub fn new() -> StdClock {
+        StdClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for StdClock {
+    fn default() -> StdClock {
+        StdClock::new()
+    }
+}
+
+impl Clock for StdClock {
+    fn now_millis(&mut self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
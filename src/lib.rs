@@ -0,0 +1,13 @@
+#![no_std]
+
+// Lets the derive macros name this crate as `::wable` from inside it too.
+extern crate self as wable;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod devices {
+    pub mod ble;
+    #[cfg(feature = "esp32")]
+    pub mod vibration_motor;
+}
@@ -1,13 +1,15 @@
 #![no_std]
 #![no_main]
 
-use devices::{
+use wable::devices::{
     ble::{
         command::{
-            le_set_scan_enable::LeSetScanEnable, le_set_scan_parameters::LeSetScanParameters,
-            reset::Reset, set_event_mask::SetEventMask,
+            le_set_event_mask::LeSetEventMask, le_set_scan_enable::LeSetScanEnable,
+            le_set_scan_parameters::LeSetScanParameters, reset::Reset,
+            set_event_mask::SetEventMask,
         },
         event::le_advertising_report::LeAdvertisingReport,
+        router::EventRouter,
         Ble, PollBehavior,
     },
     vibration_motor::VibrationMotor,
@@ -32,11 +34,6 @@ use core::cell::RefCell;
 use critical_section::Mutex;
 use esp_println::println;
 
-mod devices {
-    pub mod ble;
-    pub mod vibration_motor;
-}
-
 static BUTTON: Mutex<RefCell<Option<Input<GpioPin<26>>>>> = Mutex::new(RefCell::new(None));
 
 #[entry]
@@ -132,18 +129,39 @@ fn main() -> ! {
 
     let (mut ble, qslot) = Ble::new(ble_conn, delay);
 
-    let (status, qslot) = ble
-        .run_until_complete(qslot, PollBehavior::Strict, Reset {})
+    let ((), qslot) = ble
+        .run_checked(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+
+    // Commands the controller doesn't support fail with BleError::Unsupported from here on.
+    let (info, qslot) = ble.probe_controller(qslot, PollBehavior::Strict).unwrap();
+    println!("controller {:?} at {:?}", info.version, info.address);
+
+    // Only the events the host needs itself, and those subscribed to or polled for.
+    let masks = ble.router().event_masks().with::<LeAdvertisingReport>();
+
+    let ((), qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            SetEventMask {
+                mask: masks.events,
+            },
+        )
         .unwrap();
-    status.assert().unwrap();
 
-    let (status, qslot) = ble
-        .run_until_complete(qslot, PollBehavior::Strict, SetEventMask { mask: !0 })
+    let ((), qslot) = ble
+        .run_checked(
+            qslot,
+            PollBehavior::Strict,
+            LeSetEventMask {
+                le_event_mask: masks.le_events,
+            },
+        )
         .unwrap();
-    status.assert().unwrap();
 
-    let (status, qslot) = ble
-        .run_until_complete(
+    let ((), qslot) = ble
+        .run_checked(
             qslot,
             PollBehavior::Strict,
             LeSetScanParameters {
@@ -155,10 +173,9 @@ fn main() -> ! {
             },
         )
         .unwrap();
-    status.assert().unwrap();
 
-    let (status, _qslot) = ble
-        .run_until_complete(
+    let ((), _qslot) = ble
+        .run_checked(
             qslot,
             PollBehavior::Strict,
             LeSetScanEnable {
@@ -167,7 +184,6 @@ fn main() -> ! {
             },
         )
         .unwrap();
-    status.assert().unwrap();
 
     loop {
         if let Some(event) = ble.filter_poll::<LeAdvertisingReport>().unwrap() {
@@ -0,0 +1,311 @@
+mod common;
+
+use common::{MockController, NoDelay};
+use wable::devices::ble::{
+    advertiser::{Advertiser, AdvertisingSet, OnConnection},
+    command::{
+        le_remove_advertising_set::LeRemoveAdvertisingSet,
+        le_set_advertising_data::LeSetAdvertisingData,
+        le_set_advertising_enable::LeSetAdvertisingEnable,
+        le_set_advertising_parameters::LeSetAdvertisingParameters,
+        le_set_extended_advertising_data::LeSetExtendedAdvertisingData,
+        le_set_extended_advertising_enable::LeSetExtendedAdvertisingEnable,
+        le_set_extended_advertising_parameters::{
+            LeSetExtendedAdvertisingParameters, CONNECTABLE, NO_TX_POWER_PREFERENCE,
+        },
+        le_set_extended_scan_response_data::LeSetExtendedScanResponseData,
+        le_set_scan_response_data::LeSetScanResponseData,
+        CommandParameters,
+    },
+    data::{
+        address::Address,
+        advertising_data::{AdType, AdvertisingData, LE_GENERAL_DISCOVERABLE_MODE},
+        status_code::StatusCode,
+        U24,
+    },
+    event::{
+        le_advertising_set_terminated::LeAdvertisingSetTerminated,
+        le_connection_complete::LeConnectionComplete,
+    },
+    Ble, PollBehavior,
+};
+
+fn advertiser(on_connection: OnConnection) -> Advertiser {
+    Advertiser::new(
+        LeSetAdvertisingParameters {
+            advertising_interval_min: 0x0800,
+            advertising_interval_max: 0x0800,
+            advertising_type: 0x00,
+            own_address_type: 0x00,
+            peer_address_type: 0x00,
+            peer_address: Address([0; 6]),
+            advertising_channel_map: 0x07,
+            advertising_filter_policy: 0x00,
+        },
+        AdvertisingData::new()
+            .with(AdType::FLAGS, &[LE_GENERAL_DISCOVERABLE_MODE])
+            .unwrap(),
+        AdvertisingData::new()
+            .with(AdType::COMPLETE_LOCAL_NAME, b"wable")
+            .unwrap(),
+        on_connection,
+    )
+}
+
+fn connection(role: u8) -> LeConnectionComplete {
+    LeConnectionComplete {
+        status: StatusCode::SUCCESS,
+        connection_handle: 0x0040,
+        role,
+        peer_address_type: 0x01,
+        peer_address: Address([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]),
+        connection_interval: 0x0018,
+        peripheral_latency: 0x0000,
+        supervision_timeout: 0x01F4,
+        central_clock_accuracy: 0x00,
+    }
+}
+
+/// The parameters of a legacy advertising data command carrying `data`, padded to 31 bytes.
+fn padded(data: &[u8]) -> Vec<u8> {
+    let mut parameters = vec![data.len() as u8];
+    parameters.extend_from_slice(data);
+    parameters.resize(32, 0x00);
+    parameters
+}
+
+fn expect_start(hci: MockController) -> MockController {
+    hci.expect_command(
+        LeSetAdvertisingParameters::OPCODE,
+        &[
+            0x00, 0x08, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07,
+            0x00,
+        ],
+    )
+    .reply_command_complete(1, LeSetAdvertisingParameters::OPCODE, &[0x00])
+    .expect_command(LeSetAdvertisingData::OPCODE, &padded(&[0x02, 0x01, 0x02]))
+    .reply_command_complete(1, LeSetAdvertisingData::OPCODE, &[0x00])
+    .expect_command(
+        LeSetScanResponseData::OPCODE,
+        &padded(&[0x06, 0x09, b'w', b'a', b'b', b'l', b'e']),
+    )
+    .reply_command_complete(1, LeSetScanResponseData::OPCODE, &[0x00])
+    .expect_command(LeSetAdvertisingEnable::OPCODE, &[0x01])
+    .reply_command_complete(1, LeSetAdvertisingEnable::OPCODE, &[0x00])
+}
+
+#[test]
+fn advertising_restarts_after_a_central_connects() {
+    let hci = expect_start(MockController::new())
+        .expect_command(LeSetAdvertisingEnable::OPCODE, &[0x01])
+        .reply_command_complete(1, LeSetAdvertisingEnable::OPCODE, &[0x00])
+        .expect_command(LeSetAdvertisingEnable::OPCODE, &[0x00])
+        .reply_command_complete(1, LeSetAdvertisingEnable::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+    let mut advertiser = advertiser(OnConnection::Restart);
+
+    let qslot = advertiser
+        .start(&mut ble, qslot, PollBehavior::Strict)
+        .unwrap();
+    assert!(advertiser.is_advertising());
+
+    // A connection the watch initiated as the central leaves advertising alone.
+    let qslot = advertiser
+        .handle_connection(&mut ble, qslot, PollBehavior::Strict, &connection(0x00))
+        .unwrap();
+    let qslot = advertiser
+        .handle_connection(&mut ble, qslot, PollBehavior::Strict, &connection(0x01))
+        .unwrap();
+    assert!(advertiser.is_advertising());
+
+    advertiser
+        .stop(&mut ble, qslot, PollBehavior::Strict)
+        .unwrap();
+    assert!(!advertiser.is_advertising());
+
+    ble.hci().finish();
+}
+
+#[test]
+fn advertising_stays_stopped_after_a_central_connects() {
+    let hci = expect_start(MockController::new());
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+    let mut advertiser = advertiser(OnConnection::Stop);
+
+    let qslot = advertiser
+        .start(&mut ble, qslot, PollBehavior::Strict)
+        .unwrap();
+    let qslot = advertiser
+        .handle_connection(&mut ble, qslot, PollBehavior::Strict, &connection(0x01))
+        .unwrap();
+    assert!(!advertiser.is_advertising());
+
+    // The controller already stopped advertising, so there is nothing to tell it.
+    advertiser
+        .stop(&mut ble, qslot, PollBehavior::Strict)
+        .unwrap();
+
+    ble.hci().finish();
+}
+
+fn extended_parameters(
+    advertising_handle: u8,
+    properties: u16,
+) -> LeSetExtendedAdvertisingParameters {
+    LeSetExtendedAdvertisingParameters {
+        advertising_handle,
+        advertising_event_properties: properties,
+        primary_advertising_interval_min: U24::new(0x000800).unwrap(),
+        primary_advertising_interval_max: U24::new(0x000800).unwrap(),
+        primary_advertising_channel_map: 0x07,
+        own_address_type: 0x00,
+        peer_address_type: 0x00,
+        peer_address: Address([0; 6]),
+        advertising_filter_policy: 0x00,
+        advertising_tx_power: NO_TX_POWER_PREFERENCE,
+        primary_advertising_phy: 0x01,
+        secondary_advertising_max_skip: 0x00,
+        secondary_advertising_phy: 0x02,
+        advertising_sid: advertising_handle,
+        scan_request_notification_enable: 0x00,
+    }
+}
+
+fn expected_parameters(advertising_handle: u8, properties: u8) -> Vec<u8> {
+    let mut parameters = vec![advertising_handle, properties, 0x00];
+    // Intervals and channel map.
+    parameters.extend_from_slice(&[0x00, 0x08, 0x00, 0x00, 0x08, 0x00, 0x07]);
+    // Addresses and filter policy.
+    parameters.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    // Transmit power and PHYs.
+    parameters.extend_from_slice(&[0x7F, 0x01, 0x00, 0x02]);
+    parameters.extend_from_slice(&[advertising_handle, 0x00]);
+    parameters
+}
+
+/// The parameters of an extended advertising or scan response data command.
+fn fragment(advertising_handle: u8, operation: u8, data: &[u8]) -> Vec<u8> {
+    let mut parameters = vec![advertising_handle, operation, 0x01, data.len() as u8];
+    parameters.extend_from_slice(data);
+    parameters
+}
+
+fn terminated(status: StatusCode, advertising_handle: u8) -> LeAdvertisingSetTerminated {
+    LeAdvertisingSetTerminated {
+        status,
+        advertising_handle,
+        connection_handle: 0x0040,
+        num_completed_extended_advertising_events: 0x00,
+    }
+}
+
+#[test]
+fn advertising_sets_advertise_side_by_side() {
+    let name: Vec<u8> = (0..40).collect();
+    let readings: Vec<u8> = (0..300).map(|i| i as u8).collect();
+
+    let hci = MockController::new()
+        // The connectable set, with a scan response too long for legacy advertising.
+        .expect_command(
+            LeSetExtendedAdvertisingParameters::OPCODE,
+            &expected_parameters(0x00, 0x01),
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingParameters::OPCODE, &[0x00, 0x04])
+        .expect_command(
+            LeSetExtendedScanResponseData::OPCODE,
+            &fragment(0x00, 0x03, &name),
+        )
+        .reply_command_complete(1, LeSetExtendedScanResponseData::OPCODE, &[0x00])
+        .expect_command(
+            LeSetExtendedAdvertisingEnable::OPCODE,
+            &[0x01, 0x01, 0x00, 0x00, 0x00, 0x00],
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingEnable::OPCODE, &[0x00])
+        // The broadcast set, whose data takes two commands.
+        .expect_command(
+            LeSetExtendedAdvertisingParameters::OPCODE,
+            &expected_parameters(0x01, 0x00),
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingParameters::OPCODE, &[0x00, 0xFC])
+        .expect_command(
+            LeSetExtendedAdvertisingData::OPCODE,
+            &fragment(0x01, 0x01, &readings[..251]),
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingData::OPCODE, &[0x00])
+        .expect_command(
+            LeSetExtendedAdvertisingData::OPCODE,
+            &fragment(0x01, 0x02, &readings[251..]),
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingData::OPCODE, &[0x00])
+        .expect_command(
+            LeSetExtendedAdvertisingEnable::OPCODE,
+            &[0x01, 0x01, 0x01, 0x2C, 0x01, 0x00],
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingEnable::OPCODE, &[0x00])
+        // A phone connects to the first set, which restarts.
+        .expect_command(
+            LeSetExtendedAdvertisingEnable::OPCODE,
+            &[0x01, 0x01, 0x00, 0x00, 0x00, 0x00],
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingEnable::OPCODE, &[0x00])
+        // New readings fit into one command.
+        .expect_command(
+            LeSetExtendedAdvertisingData::OPCODE,
+            &fragment(0x01, 0x03, &readings[..20]),
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingData::OPCODE, &[0x00])
+        .expect_command(
+            LeSetExtendedAdvertisingEnable::OPCODE,
+            &[0x00, 0x01, 0x00, 0x00, 0x00, 0x00],
+        )
+        .reply_command_complete(1, LeSetExtendedAdvertisingEnable::OPCODE, &[0x00])
+        .expect_command(LeRemoveAdvertisingSet::OPCODE, &[0x00])
+        .reply_command_complete(1, LeRemoveAdvertisingSet::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let mut phone = AdvertisingSet::new(
+        extended_parameters(0x00, CONNECTABLE),
+        &[],
+        &name,
+        OnConnection::Restart,
+    );
+    let mut broadcast = AdvertisingSet::new(
+        extended_parameters(0x01, 0x0000),
+        &readings,
+        &[],
+        OnConnection::Stop,
+    );
+    broadcast.duration = 300;
+
+    let (tx_power, qslot) = phone.start(&mut ble, qslot, PollBehavior::Strict).unwrap();
+    assert_eq!(tx_power, 4);
+    let (tx_power, qslot) = broadcast
+        .start(&mut ble, qslot, PollBehavior::Strict)
+        .unwrap();
+    assert_eq!(tx_power, -4);
+
+    let event = terminated(StatusCode::SUCCESS, 0x00);
+    let qslot = broadcast
+        .handle_terminated(&mut ble, qslot, PollBehavior::Strict, &event)
+        .unwrap();
+    let qslot = phone
+        .handle_terminated(&mut ble, qslot, PollBehavior::Strict, &event)
+        .unwrap();
+    assert!(phone.is_advertising());
+    assert!(broadcast.is_advertising());
+
+    let qslot = broadcast
+        .update_data(&mut ble, qslot, PollBehavior::Strict, &readings[..20])
+        .unwrap();
+    // The broadcast's duration runs out.
+    let event = terminated(StatusCode(0x3C), 0x01);
+    let qslot = broadcast
+        .handle_terminated(&mut ble, qslot, PollBehavior::Strict, &event)
+        .unwrap();
+    assert!(!broadcast.is_advertising());
+
+    phone.remove(&mut ble, qslot, PollBehavior::Strict).unwrap();
+    assert!(!phone.is_advertising());
+
+    ble.hci().finish();
+}
@@ -0,0 +1,809 @@
+mod common;
+
+use common::{MockController, NoDelay};
+use wable::devices::ble::{
+    acl::{AclBufferSize, L2capFrame},
+    command::{
+        le_create_connection::LeCreateConnection, le_read_buffer_size::LeReadBufferSize,
+        le_read_local_supported_features::LeReadLocalSupportedFeatures,
+        le_set_scan_enable::LeSetScanEnable, read_bd_addr::ReadBdAddr,
+        read_local_supported_commands::ReadLocalSupportedCommands,
+        read_local_supported_features::ReadLocalSupportedFeatures,
+        read_local_version_information::ReadLocalVersionInformation, reset::Reset,
+        CommandParameters,
+    },
+    data::{
+        address::Address,
+        features::{LeFeatures, LmpFeatures},
+        opcode::Opcode,
+        status_code::StatusError,
+    },
+    event::{
+        command_complete::CommandComplete,
+        command_status::CommandStatus,
+        hardware_error::HardwareError,
+        le_advertising_report::LeAdvertisingReport,
+        le_connection_complete::LeConnectionComplete,
+        le_extended_advertising_report::{
+            DataStatus, ExtendedAdvertisement, ExtendedAdvertisingReassembler,
+            LeExtendedAdvertisingReport,
+        },
+    },
+    packet::{FramingError, FramingStats},
+    Ble, BleError, PollBehavior,
+};
+
+const SCAN_ENABLE: LeSetScanEnable = LeSetScanEnable {
+    le_scan_enable: 0x01,
+    filter_duplicates: 0x00,
+};
+
+const CREATE_CONNECTION_PARAMETERS: &[u8] = &[
+    0x60, 0x00, 0x30, 0x00, 0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x00, 0x18, 0x00, 0x28,
+    0x00, 0x00, 0x00, 0xF4, 0x01, 0x00, 0x00, 0x00, 0x00,
+];
+
+fn create_connection() -> LeCreateConnection {
+    LeCreateConnection {
+        le_scan_interval: 0x0060,
+        le_scan_window: 0x0030,
+        initiator_filter_policy: 0x00,
+        peer_address_type: 0x00,
+        peer_address: Address([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]),
+        own_address_type: 0x00,
+        connection_interval_min: 0x0018,
+        connection_interval_max: 0x0028,
+        max_latency: 0x0000,
+        supervision_timeout: 0x01F4,
+        min_ce_length: 0x0000,
+        max_ce_length: 0x0000,
+    }
+}
+
+#[test]
+fn run_until_complete_returns_return_parameters() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let (status, _qslot) = ble
+        .run_until_complete(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+    assert!(status.is_successful());
+
+    ble.hci().finish();
+}
+
+#[test]
+fn run_until_complete_filters_unrelated_events() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_le_meta(0x02, &[0x00])
+        .reply_command_complete(1, LeSetScanEnable::OPCODE, &[0x0C]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let (status, _qslot) = ble
+        .run_until_complete(qslot, PollBehavior::Filter, SCAN_ENABLE)
+        .unwrap();
+    assert!(!status.is_successful());
+
+    ble.hci().finish();
+}
+
+#[test]
+fn run_checked_returns_failed_status_as_error() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_command_complete(1, LeSetScanEnable::OPCODE, &[0x0C])
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let Err(BleError::Command {
+        opcode,
+        status,
+        qslot,
+    }) = ble.run_checked(qslot, PollBehavior::Strict, SCAN_ENABLE)
+    else {
+        panic!("expected a command error");
+    };
+    assert_eq!(opcode, LeSetScanEnable::OPCODE);
+    assert_eq!(status, StatusError::CommandDisallowed);
+
+    let ((), _qslot) = ble
+        .run_checked(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+
+    ble.hci().finish();
+}
+
+fn connection_complete(status: u8, peer_address: [u8; 6]) -> Vec<u8> {
+    let mut parameters = vec![status, 0x40, 0x00, 0x00, 0x00];
+    parameters.extend_from_slice(&peer_address);
+    parameters.extend_from_slice(&[0x28, 0x00, 0x00, 0x00, 0xF4, 0x01, 0x00]);
+    parameters
+}
+
+const PEER: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+
+#[test]
+fn run_until_completion_waits_for_connection_complete() {
+    let hci = MockController::new()
+        .expect_command(LeCreateConnection::OPCODE, CREATE_CONNECTION_PARAMETERS)
+        .reply_command_status(0x00, 1, LeCreateConnection::OPCODE)
+        .reply_le_meta(0x02, &[0x00])
+        .reply_le_meta(0x01, &connection_complete(0x00, PEER));
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let (complete, _qslot) = ble
+        .run_until_completion(qslot, PollBehavior::Strict, create_connection())
+        .unwrap();
+    assert_eq!(complete.connection_handle, 0x0040);
+    assert_eq!(complete.peer_address, Address(PEER));
+    assert_eq!(ble.unrouted_events(), 1);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn run_until_completion_reports_failures() {
+    let hci = MockController::new()
+        .expect_command(LeCreateConnection::OPCODE, CREATE_CONNECTION_PARAMETERS)
+        .reply_command_status(0x0C, 1, LeCreateConnection::OPCODE)
+        .expect_command(LeCreateConnection::OPCODE, CREATE_CONNECTION_PARAMETERS)
+        .reply_command_status(0x00, 1, LeCreateConnection::OPCODE)
+        .reply_le_meta(0x01, &connection_complete(0x3E, PEER));
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let Err(BleError::Command { status, qslot, .. }) =
+        ble.run_until_completion(qslot, PollBehavior::Strict, create_connection())
+    else {
+        panic!("expected the command status to fail");
+    };
+    assert_eq!(status, StatusError::CommandDisallowed);
+
+    let Err(BleError::Command { opcode, status, .. }) =
+        ble.run_until_completion(qslot, PollBehavior::Strict, create_connection())
+    else {
+        panic!("expected the connection to fail");
+    };
+    assert_eq!(opcode, LeCreateConnection::OPCODE);
+    assert_eq!(status, StatusError::ConnectionFailedToBeEstablished);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn run_until_completion_rejects_completion_for_another_peer() {
+    let hci = MockController::new()
+        .expect_command(LeCreateConnection::OPCODE, CREATE_CONNECTION_PARAMETERS)
+        .reply_command_status(0x00, 1, LeCreateConnection::OPCODE)
+        .reply_le_meta(0x01, &connection_complete(0x00, [0xAA; 6]));
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let result = ble.run_until_completion(qslot, PollBehavior::Strict, create_connection());
+    assert!(matches!(
+        result,
+        Err(BleError::CompletionMismatch { opcode, .. }) if opcode == LeCreateConnection::OPCODE
+    ));
+
+    // The other connection is left for the next poll.
+    let other = ble.maybe_poll::<LeConnectionComplete>().unwrap().unwrap();
+    assert_eq!(other.peer_address, Address([0xAA; 6]));
+    ble.hci().finish();
+}
+
+#[test]
+fn run_until_completion_rejects_connections_as_peripheral() {
+    // The peer connected to the watch while it was advertising.
+    let mut peripheral = connection_complete(0x00, PEER);
+    peripheral[3] = 0x01;
+    let hci = MockController::new()
+        .expect_command(LeCreateConnection::OPCODE, CREATE_CONNECTION_PARAMETERS)
+        .reply_command_status(0x00, 1, LeCreateConnection::OPCODE)
+        .reply_le_meta(0x01, &peripheral);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let result = ble.run_until_completion(qslot, PollBehavior::Strict, create_connection());
+    assert!(matches!(result, Err(BleError::CompletionMismatch { .. })));
+
+    let other = ble.maybe_poll::<LeConnectionComplete>().unwrap().unwrap();
+    assert_eq!(other.role, 0x01);
+    ble.hci().finish();
+}
+
+#[test]
+fn run_until_completion_reports_cancelled_connections() {
+    // After LE Create Connection Cancel, only the status is valid.
+    let hci = MockController::new()
+        .expect_command(LeCreateConnection::OPCODE, CREATE_CONNECTION_PARAMETERS)
+        .reply_command_status(0x00, 1, LeCreateConnection::OPCODE)
+        .reply_le_meta(0x01, &connection_complete(0x02, [0x00; 6]));
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let Err(BleError::Command { opcode, status, .. }) =
+        ble.run_until_completion(qslot, PollBehavior::Strict, create_connection())
+    else {
+        panic!("expected the connection to be cancelled");
+    };
+    assert_eq!(opcode, LeCreateConnection::OPCODE);
+    assert_eq!(status, StatusError::UnknownConnectionIdentifier);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn run_until_complete_strict_rejects_unrelated_events() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_le_meta(0x02, &[0x00])
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let Err(BleError::UnexpectedEvent { qslot }) =
+        ble.run_until_complete(qslot, PollBehavior::Strict, SCAN_ENABLE)
+    else {
+        panic!("expected an unexpected event");
+    };
+
+    // The unrelated event is left for the next poll, and the slot can be used again.
+    assert!(ble.maybe_poll::<LeAdvertisingReport>().unwrap().is_some());
+    ble.run_until_complete(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+    ble.hci().finish();
+}
+
+#[test]
+fn reset_controller_gives_back_slots_lost_to_errors() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_raw(&[0xFF])
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00])
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_command_complete(1, LeSetScanEnable::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let result = ble.run_until_complete(qslot, PollBehavior::Strict, SCAN_ENABLE);
+    assert!(matches!(result, Err(BleError::Framing(_))));
+
+    let (_, reset) = ble.reset_controller().unwrap();
+    let qslot = ble.reclaim_slot(&reset).unwrap();
+    assert!(ble.reclaim_slot(&reset).is_none());
+    ble.run_until_complete(qslot, PollBehavior::Strict, SCAN_ENABLE)
+        .unwrap();
+
+    ble.hci().finish();
+}
+
+#[test]
+fn advertising_reports_borrow_from_the_receive_buffer() {
+    let hci = MockController::new().reply_le_meta(
+        0x02,
+        &[
+            0x01, 0x00, 0x01, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x03, 0x02, 0x01, 0x06, 0xC8,
+        ],
+    );
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+
+    let report = ble.filter_poll::<LeAdvertisingReport>().unwrap().unwrap();
+    let item = report.items().next().unwrap().unwrap();
+    assert_eq!(item.address, [0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    assert_eq!(item.data, &[0x02, 0x01, 0x06]);
+    assert_eq!(item.rssi, -56);
+
+    let owned = item.into_owned().unwrap();
+    assert_eq!(&*owned.data, &[0x02, 0x01, 0x06]);
+    assert_eq!(report.into_owned().items().count(), 1);
+    ble.hci().finish();
+}
+
+/// The parameters of an LE Extended Advertising Report with one report, from a device on the LE
+/// Coded PHY.
+fn extended_report(data_status: u8, data: &[u8]) -> Vec<u8> {
+    extended_report_with_sid(0x05, data_status, data)
+}
+
+fn extended_report_with_sid(advertising_sid: u8, data_status: u8, data: &[u8]) -> Vec<u8> {
+    let mut parameters = vec![0x01, data_status << 5, 0x00, 0x01];
+    parameters.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    // PHYs, SID, TX power and RSSI.
+    parameters.extend_from_slice(&[0x03, 0x03, advertising_sid, 0x7F, 0xC8]);
+    // No periodic advertising and no direct address.
+    parameters.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    parameters.push(data.len() as u8);
+    parameters.extend_from_slice(data);
+    parameters
+}
+
+#[test]
+fn extended_advertising_data_is_reassembled_from_reports() {
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let hci = MockController::new()
+        .reply_le_meta(0x0D, &extended_report(0b01, &data[..200]))
+        .reply_le_meta(0x0D, &extended_report(0b00, &data[200..]))
+        .reply_le_meta(0x0D, &extended_report(0b01, &data[..200]))
+        .reply_le_meta(0x0D, &extended_report(0b10, &data[200..210]));
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+    let mut reassembler = ExtendedAdvertisingReassembler::new();
+
+    let mut advertisements = Vec::new();
+    for _ in 0..4 {
+        let report = ble
+            .filter_poll::<LeExtendedAdvertisingReport>()
+            .unwrap()
+            .unwrap();
+        for item in report.items() {
+            advertisements.extend(reassembler.push(&item.unwrap()));
+        }
+    }
+
+    let [complete, truncated] = &advertisements[..] else {
+        panic!("expected two advertisements, got {advertisements:?}");
+    };
+    assert_eq!(
+        complete.address,
+        Address([0x11, 0x22, 0x33, 0x44, 0x55, 0x66])
+    );
+    assert_eq!(complete.primary_phy, 0x03);
+    assert_eq!(complete.advertising_sid, 0x05);
+    assert_eq!(complete.rssi, -56);
+    assert_eq!(complete.data_status(), DataStatus::Complete);
+    assert_eq!(&*complete.data, &data[..]);
+    assert_eq!(truncated.data_status(), DataStatus::Truncated);
+    assert_eq!(&*truncated.data, &data[..210]);
+    ble.hci().finish();
+}
+
+/// Polls for `reports` and puts the advertisements in them back together.
+fn reassemble(reports: &[Vec<u8>]) -> Vec<ExtendedAdvertisement> {
+    let mut hci = MockController::new();
+    for report in reports {
+        hci = hci.reply_le_meta(0x0D, report);
+    }
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+    let mut reassembler = ExtendedAdvertisingReassembler::new();
+
+    let mut advertisements = Vec::new();
+    for _ in reports {
+        let report = ble
+            .filter_poll::<LeExtendedAdvertisingReport>()
+            .unwrap()
+            .unwrap();
+        for item in report.items() {
+            advertisements.extend(reassembler.push(&item.unwrap()));
+        }
+    }
+    ble.hci().finish();
+    advertisements
+}
+
+#[test]
+fn extended_advertising_data_is_truncated_at_the_maximum_length() {
+    let data: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+    let mut reports: Vec<_> = data[..1800]
+        .chunks(200)
+        .map(|chunk| extended_report(0b01, chunk))
+        .collect();
+    reports.push(extended_report(0b00, &data[1800..]));
+    reports.push(extended_report(0b00, &data[..10]));
+
+    let advertisements = reassemble(&reports);
+    let [truncated, next] = &advertisements[..] else {
+        panic!("expected two advertisements, got {advertisements:?}");
+    };
+    assert_eq!(truncated.data_status(), DataStatus::Truncated);
+    assert_eq!(&*truncated.data, &data[..1650]);
+    // The rest of the truncated advertisement isn't taken for a new one.
+    assert_eq!(next.data_status(), DataStatus::Complete);
+    assert_eq!(&*next.data, &data[..10]);
+}
+
+#[test]
+fn reports_of_dropped_advertisements_are_ignored() {
+    let reports = [
+        extended_report_with_sid(1, 0b01, &[0x01; 100]),
+        extended_report_with_sid(2, 0b01, &[0x02; 100]),
+        // There is only room for two advertisements in progress, so the first is dropped.
+        extended_report_with_sid(3, 0b01, &[0x03; 100]),
+        extended_report_with_sid(1, 0b00, &[0x01; 10]),
+        extended_report_with_sid(2, 0b00, &[0x02; 10]),
+        extended_report_with_sid(3, 0b00, &[0x03; 10]),
+    ];
+
+    let advertisements = reassemble(&reports);
+    let sids: Vec<_> = advertisements.iter().map(|a| a.advertising_sid).collect();
+    assert_eq!(sids, [2, 3]);
+    assert!(advertisements.iter().all(|a| a.data.len() == 110));
+}
+
+#[test]
+fn queue_slot_is_released_by_command_status() {
+    let hci = MockController::new()
+        .expect_command(LeCreateConnection::OPCODE, CREATE_CONNECTION_PARAMETERS)
+        .reply_command_status(0x00, 1, LeCreateConnection::OPCODE)
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let qlock = ble.queue(qslot, create_connection()).unwrap();
+    let status = ble
+        .maybe_poll::<CommandStatus<LeCreateConnection>>()
+        .unwrap()
+        .unwrap();
+    assert!(status.status.is_successful());
+    let qslot = qlock.release_with(&status);
+
+    ble.run_until_complete(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+
+    ble.hci().finish();
+}
+
+#[test]
+fn command_is_held_until_controller_grants_credits() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        // The controller accepts no further commands for now...
+        .reply_command_complete(0, Reset::OPCODE, &[0x00])
+        // ...until it sends a credit-only Command Complete.
+        .reply_command_complete(1, Opcode(0x0000), &[])
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_command_complete(1, LeSetScanEnable::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let qlock = ble.queue(qslot, Reset {}).unwrap();
+    let complete = ble.maybe_poll::<CommandComplete<Reset>>().unwrap().unwrap();
+    let qslot = qlock.release_with(&complete);
+
+    assert!(matches!(
+        ble.try_issue(SCAN_ENABLE),
+        Err(BleError::WouldBlock)
+    ));
+
+    let (status, _qslot) = ble
+        .run_until_complete(qslot, PollBehavior::Filter, SCAN_ENABLE)
+        .unwrap();
+    assert!(status.is_successful());
+
+    ble.hci().finish();
+}
+
+#[test]
+fn multiple_commands_in_flight() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        // Only once the first command completes does the controller say it has room for two.
+        .reply_command_complete(2, Reset::OPCODE, &[0x00])
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, LeSetScanEnable::OPCODE, &[0x00])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, [qslot_a, qslot_b, qslot_c]) = Ble::with_slots(hci, NoDelay);
+
+    let qlock_a = ble.queue(qslot_a, Reset {}).unwrap();
+    let qlock_b = ble.queue(qslot_b, SCAN_ENABLE).unwrap();
+    let qlock_c = ble.queue(qslot_c, Reset {}).unwrap();
+
+    let complete = ble.maybe_poll::<CommandComplete<Reset>>().unwrap().unwrap();
+    let _qslot_a = qlock_a.release_with(&complete);
+    let complete = ble
+        .maybe_poll::<CommandComplete<LeSetScanEnable>>()
+        .unwrap()
+        .unwrap();
+    let _qslot_b = qlock_b.release_with(&complete);
+    let complete = ble.maybe_poll::<CommandComplete<Reset>>().unwrap().unwrap();
+    let _qslot_c = qlock_c.release_with(&complete);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn sending_a_command_consumes_a_credit() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+
+    ble.try_issue(Reset {}).unwrap();
+    assert!(matches!(ble.try_issue(Reset {}), Err(BleError::WouldBlock)));
+
+    ble.poll_raw().unwrap();
+    ble.hci().finish();
+}
+
+#[test]
+fn data_packets_are_kept_while_polling_for_events() {
+    let hci = MockController::new()
+        // ACL data on handle 0x0040, first automatically flushable fragment.
+        .reply_raw(&[0x02, 0x40, 0x20, 0x03, 0x00, 0xAA, 0xBB, 0xCC])
+        // ISO data, which the host doesn't support.
+        .reply_raw(&[0x05, 0x41, 0x00, 0x01, 0x00, 0xDD])
+        .reply_event(0x10, &[0x01]);
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+
+    assert!(ble.maybe_poll::<HardwareError>().unwrap().is_some());
+
+    let acl = ble.take_acl_packet().unwrap();
+    assert_eq!(acl.connection_handle, 0x0040);
+    assert_eq!(acl.packet_boundary_flag, 0b10);
+    assert_eq!(acl.broadcast_flag, 0b00);
+    assert_eq!(&*acl.data, &[0xAA, 0xBB, 0xCC]);
+    assert!(ble.take_acl_packet().is_none());
+    assert_eq!(ble.dropped_packets(), 1);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn resynchronizes_after_invalid_packet_type() {
+    let hci = MockController::new()
+        .reply_raw(&[0x07, 0x00, 0x13])
+        .reply_event(0x10, &[0x01]);
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+
+    assert!(matches!(
+        ble.poll_raw(),
+        Err(BleError::Framing(FramingError::InvalidPacketType(0x07)))
+    ));
+    assert!(ble.maybe_poll::<HardwareError>().unwrap().is_some());
+    assert_eq!(
+        ble.framing_stats(),
+        FramingStats {
+            invalid_packet_types: 1,
+            invalid_lengths: 0,
+            dropped_bytes: 3,
+            resyncs: 1,
+        }
+    );
+
+    ble.hci().finish();
+}
+
+#[test]
+fn resynchronizes_after_impossible_length() {
+    let hci = MockController::new()
+        // An ACL packet claiming to be 0x0404 bytes long.
+        .reply_raw(&[0x02, 0x40, 0x20, 0x04, 0x04])
+        // Something that looks like an event but has an unallocated event code, with a real
+        // event starting in its header.
+        .reply_raw(&[0x04, 0x00, 0x04, 0x10, 0x01, 0x2A]);
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+
+    assert!(matches!(
+        ble.poll_raw(),
+        Err(BleError::Framing(FramingError::InvalidLength {
+            len: 0x0404,
+            ..
+        }))
+    ));
+    let error = ble.maybe_poll::<HardwareError>().unwrap().unwrap();
+    assert_eq!(error.hardware_code, 0x2A);
+    assert_eq!(ble.framing_stats().invalid_lengths, 1);
+    assert_eq!(ble.framing_stats().dropped_bytes, 7);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn reset_controller_starts_over() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_raw(&[0xFF])
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let qlock = ble.queue(qslot, SCAN_ENABLE).unwrap();
+    assert!(matches!(ble.poll_raw(), Err(BleError::Framing(_))));
+
+    let (status, reset) = ble.reset_controller().unwrap();
+    assert!(status.is_successful());
+    let _qslot = qlock.release_with(&reset);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn timed_out_command_gives_back_its_credit_and_slot() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+    let mut now = 0;
+    let mut clock = || {
+        now += 10;
+        now
+    };
+
+    let Err(BleError::Timeout { opcode, qslot }) =
+        ble.run_until_complete_timeout(qslot, PollBehavior::Strict, SCAN_ENABLE, &mut clock, 100)
+    else {
+        panic!("expected a timeout");
+    };
+    assert_eq!(opcode, LeSetScanEnable::OPCODE);
+
+    ble.run_until_complete_timeout(qslot, PollBehavior::Strict, Reset {}, &mut clock, 100)
+        .unwrap();
+
+    ble.hci().finish();
+}
+
+#[test]
+fn timed_out_command_is_taken_out_of_the_queue() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(0, Reset::OPCODE, &[0x00]);
+    let (mut ble, [qslot_a, qslot_b]) = Ble::with_slots(hci, NoDelay);
+    let mut now = 0;
+    let mut clock = || {
+        now += 10;
+        now
+    };
+
+    let (_, _qslot_a) = ble
+        .run_until_complete(qslot_a, PollBehavior::Strict, Reset {})
+        .unwrap();
+    let result =
+        ble.run_until_complete_timeout(qslot_b, PollBehavior::Strict, SCAN_ENABLE, &mut clock, 100);
+    assert!(matches!(result, Err(BleError::Timeout { .. })));
+
+    // The controller has no room for commands, and the abandoned one must not be sent.
+    assert!(matches!(ble.try_issue(Reset {}), Err(BleError::WouldBlock)));
+    ble.hci().finish();
+}
+
+#[test]
+fn timeout_is_kept_while_unrelated_events_keep_arriving() {
+    let mut hci = MockController::new().expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00]);
+    for _ in 0..50 {
+        hci = hci.reply_event(0x10, &[0x2A]);
+    }
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+    let mut now = 0;
+    let mut clock = || {
+        now += 10;
+        now
+    };
+
+    let result =
+        ble.run_until_complete_timeout(qslot, PollBehavior::Filter, SCAN_ENABLE, &mut clock, 100);
+    assert!(matches!(result, Err(BleError::Timeout { .. })));
+    let routed = ble.unrouted_events();
+    assert!(routed < 50, "read all {routed} events before giving up");
+
+    for _ in routed..50 {
+        ble.try_poll_raw().unwrap();
+    }
+    ble.hci().finish();
+}
+
+#[test]
+fn probed_controller_rejects_unsupported_commands() {
+    // Only Reset (octet 5, bit 7) is supported, besides the commands that always are.
+    let mut commands = [0x00; 65];
+    commands[1 + 5] = 0x80;
+    let hci = MockController::new()
+        .expect_command(ReadLocalVersionInformation::OPCODE, &[])
+        .reply_command_complete(
+            1,
+            ReadLocalVersionInformation::OPCODE,
+            &[0x00, 0x0D, 0x34, 0x12, 0x0D, 0x5F, 0x00, 0x78, 0x56],
+        )
+        .expect_command(ReadBdAddr::OPCODE, &[])
+        .reply_command_complete(
+            1,
+            ReadBdAddr::OPCODE,
+            &[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66],
+        )
+        .expect_command(ReadLocalSupportedCommands::OPCODE, &[])
+        .reply_command_complete(1, ReadLocalSupportedCommands::OPCODE, &commands)
+        .expect_command(ReadLocalSupportedFeatures::OPCODE, &[])
+        .reply_command_complete(
+            1,
+            ReadLocalSupportedFeatures::OPCODE,
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00],
+        )
+        .expect_command(LeReadLocalSupportedFeatures::OPCODE, &[])
+        .reply_command_complete(
+            1,
+            LeReadLocalSupportedFeatures::OPCODE,
+            &[0x00, 0x01, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        )
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+    assert!(ble.controller_info().is_none());
+
+    let (info, qslot) = ble.probe_controller(qslot, PollBehavior::Strict).unwrap();
+    assert_eq!(info.version.hci_version, 0x0D);
+    assert_eq!(info.version.company_identifier, 0x005F);
+    assert_eq!(info.address, Address([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]));
+    assert_eq!(
+        info.features,
+        LmpFeatures::BR_EDR_NOT_SUPPORTED | LmpFeatures::LE_SUPPORTED
+    );
+    assert_eq!(
+        info.le_features,
+        LeFeatures::ENCRYPTION | LeFeatures::EXTENDED_ADVERTISING
+    );
+    assert!(info.supports(Reset::OPCODE));
+    assert!(!info.supports(LeSetScanEnable::OPCODE));
+
+    let Err(BleError::Unsupported { opcode, qslot }) =
+        ble.run_checked(qslot, PollBehavior::Strict, SCAN_ENABLE)
+    else {
+        panic!("expected the command to be unsupported");
+    };
+    assert_eq!(opcode, LeSetScanEnable::OPCODE);
+
+    ble.run_checked(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+    ble.hci().finish();
+}
+
+#[test]
+fn acl_frames_are_sent_in_fragments_as_buffers_free_up() {
+    let hci = MockController::new()
+        .expect_command(LeReadBufferSize::OPCODE, &[])
+        .reply_command_complete(1, LeReadBufferSize::OPCODE, &[0x00, 0x04, 0x00, 0x01])
+        .expect_acl(0x0040, 0b00, &[0x03, 0x00, 0x04, 0x00])
+        .reply_event(0x13, &[0x01, 0x40, 0x00, 0x01, 0x00])
+        .expect_acl(0x0040, 0b01, &[0x01, 0x02, 0x03])
+        .reply_event(0x05, &[0x00, 0x40, 0x00, 0x13])
+        .expect_acl(0x0041, 0b00, &[0x00, 0x00, 0x05, 0x00]);
+    let (mut ble, qslot) = Ble::new(hci, NoDelay);
+
+    let frame = L2capFrame::new(0x0040, 0x0004, &[0x01, 0x02, 0x03]).unwrap();
+    assert!(matches!(
+        ble.send_acl(&frame),
+        Err(BleError::AclBufferSizeUnknown)
+    ));
+
+    let (buffer_size, _qslot) = ble
+        .read_acl_buffer_size(qslot, PollBehavior::Strict)
+        .unwrap();
+    assert_eq!(
+        buffer_size,
+        AclBufferSize {
+            data_len: 4,
+            num_packets: 1
+        }
+    );
+
+    // The second fragment waits for Number Of Completed Packets to free the only buffer.
+    ble.send_acl(&frame).unwrap();
+    // The controller flushes the packets of a closed connection without completing them.
+    ble.send_acl(&L2capFrame::new(0x0041, 0x0005, &[]).unwrap())
+        .unwrap();
+
+    ble.hci().finish();
+}
+
+#[test]
+fn acl_frames_are_reassembled_from_fragments() {
+    let hci = MockController::new()
+        // The first fragment doesn't even hold the whole L2CAP header.
+        .reply_acl(0x0040, 0b10, &[0x05, 0x00, 0x04])
+        .reply_event(0x10, &[0x01])
+        // A continuing fragment without a first one.
+        .reply_acl(0x0041, 0b01, &[0xFF])
+        .reply_acl(0x0040, 0b01, &[0x00, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+    let (mut ble, _qslot) = Ble::new(hci, NoDelay);
+
+    assert_eq!(ble.poll_acl().unwrap(), None);
+    assert_eq!(ble.poll_acl().unwrap(), None);
+    assert_eq!(ble.unrouted_events(), 1);
+    assert_eq!(ble.poll_acl().unwrap(), None);
+    assert_eq!(ble.dropped_packets(), 1);
+
+    let frame = ble.poll_acl().unwrap().unwrap();
+    assert_eq!(frame.connection_handle, 0x0040);
+    assert_eq!(frame.channel_id(), 0x0004);
+    assert_eq!(frame.payload(), &[0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+
+    ble.hci().finish();
+}
@@ -0,0 +1,128 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::MockController;
+use embassy_futures::{block_on, yield_now};
+use wable::devices::ble::{
+    asynch::AsyncBle,
+    command::{le_set_scan_enable::LeSetScanEnable, reset::Reset, CommandParameters},
+    event::{command_complete::CommandComplete, le_advertising_report::LeAdvertisingReport},
+    BleError, PollBehavior,
+};
+
+#[test]
+fn run_until_complete_returns_return_parameters() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = AsyncBle::new(hci);
+
+    let (status, _qslot) =
+        block_on(ble.run_until_complete(qslot, PollBehavior::Strict, Reset {})).unwrap();
+    assert!(status.is_successful());
+
+    ble.hci().finish();
+}
+
+#[test]
+fn maybe_poll_keeps_unmatched_events() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_command_complete(1, LeSetScanEnable::OPCODE, &[0x00])
+        .reply_le_meta(0x02, &[0x00]);
+    let (mut ble, qslot) = AsyncBle::new(hci);
+
+    block_on(async {
+        let qlock = ble
+            .queue(
+                qslot,
+                LeSetScanEnable {
+                    le_scan_enable: 0x01,
+                    filter_duplicates: 0x00,
+                },
+            )
+            .await
+            .unwrap();
+        let complete = ble
+            .maybe_poll::<CommandComplete<LeSetScanEnable>>()
+            .await
+            .unwrap()
+            .unwrap();
+        let _qslot = qlock.release_with(&complete);
+
+        assert!(ble
+            .maybe_poll::<LeAdvertisingReport>()
+            .await
+            .unwrap()
+            .is_some());
+    });
+
+    ble.hci().finish();
+}
+
+#[test]
+fn run_until_complete_timeout_gives_up() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let (mut ble, qslot) = AsyncBle::new(hci);
+
+    block_on(async {
+        let Err(BleError::Timeout { opcode, qslot }) = ble
+            .run_until_complete_timeout(qslot, PollBehavior::Strict, Reset {}, async {})
+            .await
+        else {
+            panic!("expected a timeout");
+        };
+        assert_eq!(opcode, Reset::OPCODE);
+
+        ble.run_until_complete(qslot, PollBehavior::Strict, Reset {})
+            .await
+            .unwrap();
+    });
+
+    ble.hci().finish();
+}
+
+/// Hands out one byte per read, after making the reader wait, like a slow UART.
+struct Trickle(MockController);
+
+impl embedded_io_async::ErrorType for Trickle {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_io_async::Read for Trickle {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        yield_now().await;
+        let len = buf.len().min(1);
+        self.0.read(&mut buf[..len]).await
+    }
+}
+
+impl embedded_io_async::Write for Trickle {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf).await
+    }
+}
+
+#[test]
+fn run_until_complete_timeout_reads_started_packets_in_full() {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        .reply_event(0x10, &[0x2A]);
+    let (mut ble, qslot) = AsyncBle::new(Trickle(hci));
+    let timeout = async {
+        yield_now().await;
+        yield_now().await;
+    };
+
+    let result =
+        block_on(ble.run_until_complete_timeout(qslot, PollBehavior::Filter, Reset {}, timeout));
+    assert!(matches!(result, Err(BleError::Timeout { .. })));
+    assert_eq!(ble.unrouted_events(), 1);
+    assert_eq!(ble.framing_stats().dropped_bytes, 0);
+
+    ble.hci().0.finish();
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{MockController, NoDelay};
+use wable::devices::ble::{
+    capture::{Btsnoop, Pcap, Tap},
+    command::{reset::Reset, CommandParameters},
+    Ble, PollBehavior,
+};
+
+fn run_reset<F: wable::devices::ble::capture::CaptureFormat>(format: F) -> Vec<u8> {
+    let hci = MockController::new()
+        .expect_command(Reset::OPCODE, &[])
+        .reply_command_complete(1, Reset::OPCODE, &[0x00]);
+    let mut now = 1_000;
+    let clock = move || {
+        now += 1;
+        now
+    };
+    let (mut ble, qslot) = Ble::new(Tap::new(hci, Vec::new(), format, clock), NoDelay);
+
+    ble.run_until_complete(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+
+    let tap = ble.hci();
+    tap.inner().finish();
+    assert_eq!(tap.drops(), 0);
+    tap.sink().clone()
+}
+
+#[test]
+fn captures_btsnoop() {
+    let capture = run_reset(Btsnoop);
+
+    let (header, records) = capture.split_at(16);
+    assert_eq!(header, b"btsnoop\0\0\0\0\x01\0\0\x03\xEA");
+
+    let (command, event) = records.split_at(24 + 4);
+    assert_eq!(
+        &command[..16],
+        &[0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 2, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        u64::from_be_bytes(command[16..24].try_into().unwrap()),
+        0x00DC_DDB3_0F2F_8000 + 1_001_000
+    );
+    assert_eq!(&command[24..], &[0x01, 0x03, 0x0C, 0x00]);
+
+    assert_eq!(
+        &event[..16],
+        &[0, 0, 0, 7, 0, 0, 0, 7, 0, 0, 0, 3, 0, 0, 0, 0]
+    );
+    assert_eq!(&event[24..], &[0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]);
+}
+
+#[test]
+fn captures_pcap() {
+    let capture = run_reset(Pcap);
+
+    let (header, records) = capture.split_at(24);
+    assert_eq!(&header[..4], &[0xD4, 0xC3, 0xB2, 0xA1]);
+    assert_eq!(&header[20..], &201u32.to_le_bytes());
+
+    let (command, event) = records.split_at(16 + 4 + 4);
+    assert_eq!(&command[..4], &1u32.to_le_bytes());
+    assert_eq!(&command[4..8], &1_000u32.to_le_bytes());
+    assert_eq!(&command[8..16], &[8, 0, 0, 0, 8, 0, 0, 0]);
+    assert_eq!(&command[16..], &[0, 0, 0, 0, 0x01, 0x03, 0x0C, 0x00]);
+
+    assert_eq!(&event[8..16], &[11, 0, 0, 0, 11, 0, 0, 0]);
+    assert_eq!(&event[16..20], &[0, 0, 0, 1]);
+    assert_eq!(&event[20..], &[0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]);
+}
@@ -0,0 +1,234 @@
+use wable::devices::ble::{
+    command::{le_set_scan_parameters::LeSetScanParameters, reset::Reset, EncodedCommand},
+    data::{
+        opcode::Opcode,
+        status_code::{ErrorCategory, StatusCode, StatusError, WithStatus},
+        Buffer, Decoder, Encoder, U24,
+    },
+    event::{
+        command_complete::CommandComplete, le_advertising_report::LeAdvertisingReport,
+        le_extended_advertising_report::LeExtendedAdvertisingReport, EncodedEvent, EventCode,
+    },
+    packet::{HciPacket, PacketType},
+};
+
+#[test]
+fn encodes_le_set_scan_parameters() {
+    let encoded = EncodedCommand::encode(LeSetScanParameters {
+        le_scan_type: 0x01,
+        le_scan_interval: 0x0100,
+        le_scan_window: 0x0010,
+        own_address_type: 0x00,
+        scanning_filter_policy: 0x00,
+    })
+    .unwrap();
+
+    assert_eq!(encoded.opcode, Opcode(0x200B));
+    assert_eq!(
+        &*encoded.parameters,
+        &[0x01, 0x00, 0x01, 0x10, 0x00, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn decodes_command_complete() {
+    let event = EncodedEvent {
+        code: EventCode(0x0E),
+        parameters: Buffer::try_from(&[0x01, 0x03, 0x0C, 0x00][..]).unwrap(),
+    };
+
+    let complete = event.decode::<CommandComplete<Reset>>().unwrap().unwrap();
+    assert_eq!(complete.num_hci_command_packets, 1);
+    assert_eq!(complete.command_opcode, Opcode(0x0C03));
+    assert!(complete.return_parameters.is_successful());
+}
+
+#[test]
+fn ignores_command_complete_for_other_opcode() {
+    let event = EncodedEvent {
+        code: EventCode(0x0E),
+        parameters: Buffer::try_from(&[0x01, 0x0B, 0x20, 0x00][..]).unwrap(),
+    };
+
+    assert!(event.decode::<CommandComplete<Reset>>().unwrap().is_none());
+}
+
+#[test]
+fn acl_packet_round_trips() {
+    let bytes = [0x02, 0x01, 0x72, 0x02, 0x00, 0x12, 0x34];
+    let packet_type = PacketType(bytes[0]);
+    let header_len = packet_type.header_len().unwrap();
+    let header = &bytes[1..1 + header_len];
+    let payload = &bytes[1 + header_len..];
+    assert_eq!(packet_type.payload_len(header), payload.len());
+
+    let HciPacket::AclData(acl) = HciPacket::decode(packet_type, header, payload).unwrap() else {
+        panic!("expected an ACL data packet");
+    };
+    assert_eq!(acl.connection_handle, 0x0201);
+    assert_eq!(acl.packet_boundary_flag, 0b11);
+    assert_eq!(acl.broadcast_flag, 0b01);
+
+    let mut encoded = Buffer::<16>::new();
+    encoded.encode(&HciPacket::AclData(acl)).unwrap();
+    assert_eq!(&*encoded, &bytes);
+}
+
+#[test]
+fn status_codes_are_typed_and_lossless() {
+    assert_eq!(StatusCode(0x00).error(), None);
+    assert_eq!(format!("{:?}", StatusCode(0x00)), "Success");
+
+    let disallowed = StatusCode(0x0C).assert().unwrap_err();
+    assert_eq!(disallowed, StatusError::CommandDisallowed);
+    assert_eq!(disallowed.category(), ErrorCategory::Configuration);
+    assert_eq!(disallowed.to_string(), "Command Disallowed (0x0C)");
+
+    assert_eq!(
+        StatusCode(0x3A).error().unwrap().category(),
+        ErrorCategory::Transient
+    );
+
+    let unknown = StatusCode(0xF0).error().unwrap();
+    assert!(matches!(unknown, StatusError::Unknown(code) if code.get() == 0xF0));
+    assert_eq!(unknown.category(), ErrorCategory::Fatal);
+    assert_eq!(StatusCode::from(unknown), StatusCode(0xF0));
+}
+
+#[test]
+fn decodes_rest_of_return_parameters_only_on_success() {
+    let mut success: &[u8] = &[0x00, 0x34, 0x12];
+    let decoded: WithStatus<u16> = success.decode().unwrap();
+    assert_eq!(decoded, WithStatus::Success(0x1234));
+
+    // A failed command may come with nothing but its status.
+    let mut failure: &[u8] = &[0x12];
+    let decoded: WithStatus<u16> = failure.decode().unwrap();
+    assert_eq!(decoded.status(), StatusCode(0x12));
+    assert_eq!(
+        decoded.into_result(),
+        Err(StatusError::InvalidHciCommandParameters)
+    );
+}
+
+#[test]
+fn malformed_input_is_an_error_rather_than_a_panic() {
+    assert!(Buffer::<2>::try_from(&[0x01, 0x02, 0x03][..]).is_err());
+    assert_eq!(
+        &*Buffer::<3>::try_from(&[0x01, 0x02][..]).unwrap(),
+        &[0x01, 0x02]
+    );
+
+    // Two reports are announced, and the first claims more data than there is.
+    let event = EncodedEvent {
+        code: EventCode(0x3E),
+        parameters: Buffer::try_from(
+            &[
+                0x02, 0x02, 0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0xF0,
+            ][..],
+        )
+        .unwrap(),
+    };
+    let report = event.decode::<LeAdvertisingReport>().unwrap().unwrap();
+    let mut items = report.items();
+    assert!(items.next().unwrap().is_err());
+    assert!(items.next().is_none());
+}
+
+#[test]
+fn malformed_report_in_a_batch_ends_the_items() {
+    let mut parameters = vec![0x02, 0x03];
+    // A good report with two bytes of data.
+    parameters.extend_from_slice(&[0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    parameters.extend_from_slice(&[0x02, 0xA1, 0xA2, 0xC8]);
+    // One that claims more than the 31 bytes of data an advertisement can have.
+    parameters.extend_from_slice(&[0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    parameters.extend_from_slice(&[0x20]);
+    parameters.extend_from_slice(&[0xA5; 0x21]);
+    let event = EncodedEvent {
+        code: EventCode(0x3E),
+        parameters: Buffer::try_from(&parameters[..]).unwrap(),
+    };
+
+    let report = event.decode::<LeAdvertisingReport>().unwrap().unwrap();
+    let mut items = report.items();
+    assert_eq!(items.next().unwrap().unwrap().data, [0xA1, 0xA2]);
+    assert!(items.next().unwrap().is_err());
+    assert!(items.next().is_none());
+}
+
+fn extended_report_event(num_reports: u8, data_length: u8, data: &[u8]) -> EncodedEvent {
+    let mut parameters = vec![0x0D, num_reports, 0x10, 0x00, 0x00];
+    parameters.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    parameters.extend_from_slice(&[0x01, 0x00, 0xFF, 0x7F, 0xC8, 0x00, 0x00, 0x00]);
+    parameters.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, data_length]);
+    parameters.extend_from_slice(data);
+    EncodedEvent {
+        code: EventCode(0x3E),
+        parameters: Buffer::try_from(&parameters[..]).unwrap(),
+    }
+}
+
+#[test]
+fn malformed_extended_report_ends_the_items() {
+    // Two reports are announced, and the first claims 200 bytes of data but has only 60.
+    let event = extended_report_event(2, 200, &[0xA5; 60]);
+    let report = event
+        .decode::<LeExtendedAdvertisingReport>()
+        .unwrap()
+        .unwrap();
+    let mut items = report.items();
+    assert!(items.next().unwrap().is_err());
+    assert!(items.next().is_none());
+
+    // The last report must end the event.
+    let event = extended_report_event(1, 4, &[0xA5; 6]);
+    let report = event
+        .decode::<LeExtendedAdvertisingReport>()
+        .unwrap()
+        .unwrap();
+    let mut items = report.items();
+    assert!(items.next().unwrap().is_err());
+    assert!(items.next().is_none());
+
+    let event = extended_report_event(1, 4, &[0xA5; 4]);
+    let report = event
+        .decode::<LeExtendedAdvertisingReport>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.items().next().unwrap().unwrap().data, [0xA5; 4]);
+}
+
+#[test]
+fn encodes_signed_wide_and_boolean_primitives() {
+    let mut buf = Buffer::<16>::new();
+    buf.encode(&-56i8).unwrap();
+    buf.encode(&-2i16).unwrap();
+    buf.encode(&0x0403_0201u32).unwrap();
+    buf.encode(&U24::new(0x07_0605).unwrap()).unwrap();
+    buf.encode(&true).unwrap();
+    assert_eq!(
+        &*buf,
+        &[0xC8, 0xFE, 0xFF, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x01]
+    );
+
+    let mut decoder: &[u8] = &buf;
+    assert_eq!(decoder.decode::<i8>().unwrap(), -56);
+    assert_eq!(decoder.decode::<i16>().unwrap(), -2);
+    assert_eq!(decoder.decode::<u32>().unwrap(), 0x0403_0201);
+    assert_eq!(decoder.decode::<U24>().unwrap().get(), 0x07_0605);
+    assert!(decoder.decode::<bool>().unwrap());
+    assert!(decoder.is_empty());
+}
+
+#[test]
+fn validates_the_range_of_primitives() {
+    assert_eq!(U24::new(0x100_0000), None);
+    assert_eq!(U24::new(0xFF_FFFF), Some(U24::MAX));
+
+    let mut not_a_bool: &[u8] = &[0x02];
+    assert!(not_a_bool.decode::<bool>().is_err());
+
+    let mut too_short: &[u8] = &[0x01, 0x02];
+    assert!(too_short.decode::<U24>().is_err());
+}
@@ -0,0 +1,248 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+
+use embedded_hal::delay::DelayNs;
+use wable::devices::ble::data::opcode::Opcode;
+
+/// How many times in a row the host may read from an idle [MockController] before we assume it
+/// is waiting for a reply that was never scripted.
+const MAX_IDLE_READS: usize = 1000;
+
+enum Step {
+    /// The host must write exactly this H4 command or ACL data packet next.
+    Packet(Vec<u8>),
+    /// Bytes that become readable once every preceding [Step::Packet] has been written.
+    Reply(Vec<u8>),
+}
+
+/// A scripted stand-in for an HCI controller speaking H4.
+///
+/// Packets written by the host are checked byte for byte against the script, and each scripted
+/// reply becomes readable as soon as the packets before it have been received.
+pub struct MockController {
+    script: VecDeque<Step>,
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+    idle_reads: usize,
+}
+
+impl MockController {
+    pub fn new() -> MockController {
+        MockController {
+            script: VecDeque::new(),
+            rx: VecDeque::new(),
+            tx: Vec::new(),
+            idle_reads: 0,
+        }
+    }
+
+    /// Expects the host to send the command `opcode` with exactly `parameters`.
+    pub fn expect_command(mut self, opcode: Opcode, parameters: &[u8]) -> Self {
+        let mut packet = vec![0x01];
+        packet.extend_from_slice(&opcode.0.to_le_bytes());
+        packet.push(parameters.len() as u8);
+        packet.extend_from_slice(parameters);
+        self.script.push_back(Step::Packet(packet));
+        self
+    }
+
+    /// Expects the host to send an ACL data packet on `connection_handle` with exactly `data`.
+    pub fn expect_acl(
+        mut self,
+        connection_handle: u16,
+        packet_boundary_flag: u8,
+        data: &[u8],
+    ) -> Self {
+        let packet = acl_packet(connection_handle, packet_boundary_flag, data);
+        self.script.push_back(Step::Packet(packet));
+        self
+    }
+
+    /// Replies with an ACL data packet.
+    pub fn reply_acl(self, connection_handle: u16, packet_boundary_flag: u8, data: &[u8]) -> Self {
+        self.reply_raw(&acl_packet(connection_handle, packet_boundary_flag, data))
+    }
+
+    /// Replies with raw bytes, which need not be a well-formed packet.
+    pub fn reply_raw(mut self, bytes: &[u8]) -> Self {
+        self.script.push_back(Step::Reply(bytes.to_vec()));
+        self.release_replies();
+        self
+    }
+
+    /// Replies with an event packet.
+    pub fn reply_event(self, code: u8, parameters: &[u8]) -> Self {
+        let mut packet = vec![0x04, code, parameters.len() as u8];
+        packet.extend_from_slice(parameters);
+        self.reply_raw(&packet)
+    }
+
+    /// Replies with a Command Complete event.
+    pub fn reply_command_complete(
+        self,
+        num_hci_command_packets: u8,
+        opcode: Opcode,
+        return_parameters: &[u8],
+    ) -> Self {
+        let mut parameters = vec![num_hci_command_packets];
+        parameters.extend_from_slice(&opcode.0.to_le_bytes());
+        parameters.extend_from_slice(return_parameters);
+        self.reply_event(0x0E, &parameters)
+    }
+
+    /// Replies with a Command Status event.
+    pub fn reply_command_status(
+        self,
+        status: u8,
+        num_hci_command_packets: u8,
+        opcode: Opcode,
+    ) -> Self {
+        let mut parameters = vec![status, num_hci_command_packets];
+        parameters.extend_from_slice(&opcode.0.to_le_bytes());
+        self.reply_event(0x0F, &parameters)
+    }
+
+    /// Replies with an LE Meta event.
+    pub fn reply_le_meta(self, subevent_code: u8, parameters: &[u8]) -> Self {
+        let mut meta = vec![subevent_code];
+        meta.extend_from_slice(parameters);
+        self.reply_event(0x3E, &meta)
+    }
+
+    /// Panics unless every scripted step has been carried out and every reply has been read.
+    pub fn finish(&self) {
+        assert!(
+            self.tx.is_empty(),
+            "host wrote an incomplete packet: {:02X?}",
+            self.tx
+        );
+        assert!(
+            self.script.is_empty(),
+            "{} scripted step(s) never happened",
+            self.script.len()
+        );
+        assert!(
+            self.rx.is_empty(),
+            "host never read {} reply byte(s)",
+            self.rx.len()
+        );
+    }
+
+    fn release_replies(&mut self) {
+        while let Some(Step::Reply(_)) = self.script.front() {
+            let Some(Step::Reply(bytes)) = self.script.pop_front() else {
+                unreachable!()
+            };
+            self.rx.extend(bytes);
+        }
+    }
+
+    /// The length of the packet at the start of `tx`, once enough of it has been written to tell.
+    fn written_packet_len(&self) -> Option<usize> {
+        match self.tx[..] {
+            [0x01, _, _, len, ..] => Some(4 + len as usize),
+            [0x02, _, _, len_lo, len_hi, ..] => {
+                Some(5 + u16::from_le_bytes([len_lo, len_hi]) as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_packets(&mut self) {
+        while let Some(len) = self
+            .written_packet_len()
+            .filter(|&len| self.tx.len() >= len)
+        {
+            let packet: Vec<u8> = self.tx.drain(..len).collect();
+
+            match self.script.pop_front() {
+                Some(Step::Packet(expected)) => {
+                    assert_eq!(packet, expected, "host sent an unexpected packet")
+                }
+                _ => panic!("host sent an unscripted packet: {packet:02X?}"),
+            }
+
+            self.release_replies();
+        }
+    }
+}
+
+fn acl_packet(connection_handle: u16, packet_boundary_flag: u8, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x02];
+    packet.extend_from_slice(
+        &(connection_handle | (packet_boundary_flag as u16) << 12).to_le_bytes(),
+    );
+    packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    packet.extend_from_slice(data);
+    packet
+}
+
+impl embedded_io::ErrorType for MockController {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_io::Read for MockController {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.rx.is_empty() {
+            self.idle_reads += 1;
+            assert!(
+                self.idle_reads < MAX_IDLE_READS,
+                "host is waiting for a reply that was never scripted"
+            );
+            return Ok(0);
+        }
+
+        self.idle_reads = 0;
+        let len = buf.len().min(self.rx.len());
+        for (dst, src) in buf.iter_mut().zip(self.rx.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl embedded_io::Write for MockController {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.tx.is_empty() {
+            assert!(
+                matches!(buf.first(), Some(0x01 | 0x02)),
+                "host wrote a packet that is neither a command nor ACL data"
+            );
+        }
+
+        self.tx.extend_from_slice(buf);
+        self.receive_packets();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_io_async::Read for MockController {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        loop {
+            match embedded_io::Read::read(self, buf)? {
+                0 => embassy_futures::yield_now().await,
+                len => return Ok(len),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_io_async::Write for MockController {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(self, buf)
+    }
+}
+
+/// A delay that returns immediately, since the mock never makes the host wait.
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
@@ -0,0 +1,120 @@
+use wable::devices::ble::{
+    command::{CommandParameters, EncodedCommand},
+    data::{opcode::Opcode, Buffer, Decode, Decoder, Encode, Encoder},
+    event::{EncodedEvent, EventCode, EventParameters},
+};
+
+#[derive(Encode)]
+#[opcode(ogf = 0x3F, ocf = 0x0001)]
+struct VendorCommand {
+    first: u8,
+    second: u16,
+    mask: u64,
+}
+
+#[derive(Encode)]
+struct Pair(u8, u16);
+
+#[derive(Debug, PartialEq, Decode)]
+#[event(code = 0xFF)]
+struct VendorEvent {
+    kind: u8,
+    value: u16,
+}
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+#[event(subevent = 0x0C)]
+struct SomeSubevent(u16, u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+enum ScanType {
+    Passive = 0x00,
+    Active = 0x01,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+#[repr(u16)]
+enum WideEnum {
+    Small = 0x0001,
+    Large = 0x0200,
+}
+
+#[test]
+fn encodes_fields_in_order_little_endian() {
+    let encoded = EncodedCommand::encode(VendorCommand {
+        first: 0x01,
+        second: 0x0302,
+        mask: 0x0B0A_0908_0706_0504,
+    })
+    .unwrap();
+
+    assert_eq!(VendorCommand::OPCODE, Opcode(0xFC01));
+    assert_eq!(encoded.opcode, Opcode(0xFC01));
+    assert_eq!(
+        &*encoded.parameters,
+        &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B]
+    );
+
+    let mut buf = Buffer::<3>::new();
+    buf.encode(&Pair(0x01, 0x0302)).unwrap();
+    assert_eq!(&*buf, &[0x01, 0x02, 0x03]);
+}
+
+#[test]
+fn decodes_events_with_codes() {
+    assert_eq!(VendorEvent::EVENT_CODE, EventCode(0xFF));
+
+    let mut parameters: &[u8] = &[0x07, 0x34, 0x12];
+    let event: VendorEvent = parameters.decode().unwrap();
+    assert_eq!(
+        event,
+        VendorEvent {
+            kind: 0x07,
+            value: 0x1234
+        }
+    );
+}
+
+#[test]
+fn decodes_le_meta_subevents_by_subevent_code() {
+    assert_eq!(SomeSubevent::EVENT_CODE, EventCode(0x3E));
+
+    let matching = EncodedEvent {
+        code: EventCode(0x3E),
+        parameters: Buffer::try_from(&[0x0C, 0x34, 0x12, 0x05][..]).unwrap(),
+    };
+    assert_eq!(
+        matching.decode::<SomeSubevent>().unwrap(),
+        Some(SomeSubevent(0x1234, 0x05))
+    );
+
+    let other = EncodedEvent {
+        code: EventCode(0x3E),
+        parameters: Buffer::try_from(&[0x02, 0x34, 0x12, 0x05][..]).unwrap(),
+    };
+    assert_eq!(other.decode::<SomeSubevent>().unwrap(), None);
+}
+
+#[test]
+fn encodes_le_meta_subevents_with_subevent_code() {
+    let encoded = EncodedEvent::encode(&SomeSubevent(0x1234, 0x05)).unwrap();
+    assert_eq!(encoded.code, EventCode(0x3E));
+    assert_eq!(&*encoded.parameters, &[0x0C, 0x34, 0x12, 0x05]);
+}
+
+#[test]
+fn enums_are_encoded_as_their_discriminant() {
+    let mut buf = Buffer::<3>::new();
+    buf.encode(&ScanType::Active).unwrap();
+    buf.encode(&WideEnum::Large).unwrap();
+    assert_eq!(&*buf, &[0x01, 0x00, 0x02]);
+
+    let mut decoder: &[u8] = &buf;
+    assert_eq!(decoder.decode::<ScanType>().unwrap(), ScanType::Active);
+    assert_eq!(decoder.decode::<WideEnum>().unwrap(), WideEnum::Large);
+
+    let mut unknown: &[u8] = &[0x02];
+    assert!(unknown.decode::<ScanType>().is_err());
+    let mut small: &[u8] = &[0x01, 0x00];
+    assert_eq!(small.decode::<WideEnum>().unwrap(), WideEnum::Small);
+}
@@ -0,0 +1,410 @@
+mod common;
+
+use std::{cell::Cell, cell::RefCell, collections::VecDeque, convert::Infallible, rc::Rc};
+
+use common::NoDelay;
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use wable::devices::ble::{
+    acl::{AclBufferSize, L2capFrame},
+    h5::{H5Error, LinkState, H5, RETRANSMIT_MS},
+    Ble, BleError, PollBehavior,
+};
+
+const TO_CONTROLLER: usize = 0;
+const TO_HOST: usize = 1;
+
+/// One direction of the loopback UART, which holds whole frames in flight so that the tests can
+/// drop, corrupt or reorder them.
+#[derive(Default)]
+struct Line {
+    partial: Vec<u8>,
+    in_flight: VecDeque<Vec<u8>>,
+    delivered: VecDeque<u8>,
+}
+
+#[derive(Default)]
+struct Wire {
+    lines: [Line; 2],
+}
+
+impl Wire {
+    fn take_frames(&mut self, line: usize) -> Vec<Vec<u8>> {
+        self.lines[line].in_flight.drain(..).collect()
+    }
+
+    fn deliver(&mut self, line: usize, frame: &[u8]) {
+        self.lines[line].delivered.extend(frame);
+    }
+
+    fn deliver_all(&mut self) {
+        for line in &mut self.lines {
+            for frame in line.in_flight.drain(..) {
+                line.delivered.extend(frame);
+            }
+        }
+    }
+}
+
+struct Serial {
+    wire: Rc<RefCell<Wire>>,
+    tx: usize,
+    rx: usize,
+    /// Runs the other end of the wire whenever this end looks for input.
+    peer: Option<Box<dyn FnMut()>>,
+}
+
+impl ErrorType for Serial {
+    type Error = Infallible;
+}
+
+impl Read for Serial {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let mut wire = self.wire.borrow_mut();
+        let delivered = &mut wire.lines[self.rx].delivered;
+        let len = buf.len().min(delivered.len());
+        for (slot, byte) in buf.iter_mut().zip(delivered.drain(..len)) {
+            *slot = byte;
+        }
+        Ok(len)
+    }
+}
+
+impl ReadReady for Serial {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        if let Some(peer) = &mut self.peer {
+            peer();
+        }
+        Ok(!self.wire.borrow().lines[self.rx].delivered.is_empty())
+    }
+}
+
+impl Write for Serial {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        let mut wire = self.wire.borrow_mut();
+        let line = &mut wire.lines[self.tx];
+        for &byte in buf {
+            line.partial.push(byte);
+            if byte == 0xC0 && line.partial.len() > 1 {
+                let frame = std::mem::take(&mut line.partial);
+                line.in_flight.push_back(frame);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+type Endpoint = H5<Serial, Box<dyn FnMut() -> u64>>;
+
+struct Loopback {
+    wire: Rc<RefCell<Wire>>,
+    now: Rc<Cell<u64>>,
+    host: Endpoint,
+    controller: Endpoint,
+}
+
+fn endpoint(wire: &Rc<RefCell<Wire>>, now: &Rc<Cell<u64>>, tx: usize, rx: usize) -> Endpoint {
+    let now = now.clone();
+    let clock: Box<dyn FnMut() -> u64> = Box::new(move || now.get());
+    H5::new(
+        Serial {
+            wire: wire.clone(),
+            tx,
+            rx,
+            peer: None,
+        },
+        clock,
+    )
+}
+
+impl Loopback {
+    fn new() -> Loopback {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let now = Rc::new(Cell::new(0));
+        let host = endpoint(&wire, &now, TO_CONTROLLER, TO_HOST);
+        let controller = endpoint(&wire, &now, TO_HOST, TO_CONTROLLER);
+
+        Loopback {
+            wire,
+            now,
+            host,
+            controller,
+        }
+    }
+
+    /// Replaces the controller with a fresh one, as if it had restarted.
+    fn restart_controller(&mut self) {
+        self.controller = endpoint(&self.wire, &self.now, TO_HOST, TO_CONTROLLER);
+    }
+
+    /// Lets both ends run with a perfect wire.
+    fn step(&mut self) {
+        self.host.poll().unwrap();
+        self.wire.borrow_mut().deliver_all();
+        self.controller.poll().unwrap();
+        self.wire.borrow_mut().deliver_all();
+        self.host.poll().unwrap();
+    }
+
+    fn advance(&mut self, ms: u64) {
+        self.now.set(self.now.get() + ms);
+    }
+
+    fn establish(mut self) -> Loopback {
+        for _ in 0..10 {
+            self.step();
+            if self.host.is_active() && self.controller.is_active() {
+                return self;
+            }
+            self.advance(RETRANSMIT_MS);
+        }
+        panic!(
+            "link not established: host {:?}, controller {:?}",
+            self.host.state(),
+            self.controller.state()
+        );
+    }
+}
+
+fn unslip(frame: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut escaped = false;
+    for &byte in &frame[1..frame.len() - 1] {
+        match (escaped, byte) {
+            (false, 0xDB) => escaped = true,
+            (true, 0xDC) => (out.push(0xC0), escaped = false).1,
+            (true, 0xDD) => (out.push(0xDB), escaped = false).1,
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+fn is_reliable(frame: &[u8]) -> bool {
+    unslip(frame)[0] & 0x80 != 0
+}
+
+fn read_all(endpoint: &mut Endpoint) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut buf = [0; 64];
+    loop {
+        let len = endpoint.read(&mut buf).unwrap();
+        if len == 0 {
+            return out;
+        }
+        out.extend_from_slice(&buf[..len]);
+    }
+}
+
+const RESET: [u8; 4] = [0x01, 0x03, 0x0C, 0x00];
+const RESET_COMPLETE: [u8; 7] = [0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+const HARDWARE_ERROR: [u8; 4] = [0x04, 0x10, 0x01, 0x42];
+
+#[test]
+fn link_establishment_agrees_on_window() {
+    let link = Loopback::new();
+    assert_eq!(link.host.state(), LinkState::Uninitialized);
+
+    let link = link.establish();
+    assert_eq!(link.host.window(), 4);
+    assert_eq!(link.controller.window(), 4);
+}
+
+#[test]
+fn packets_written_before_the_link_is_active_are_held() {
+    let mut link = Loopback::new();
+    link.host.write_all(&RESET).unwrap();
+
+    let mut link = link.establish();
+    link.step();
+    assert_eq!(read_all(&mut link.controller), RESET);
+}
+
+#[test]
+fn lost_command_is_retransmitted() {
+    let mut link = Loopback::new().establish();
+
+    link.host.write_all(&RESET).unwrap();
+    let frames = link.wire.borrow_mut().take_frames(TO_CONTROLLER);
+    assert_eq!(frames.iter().filter(|f| is_reliable(f)).count(), 1);
+
+    link.step();
+    assert_eq!(read_all(&mut link.controller), []);
+
+    link.advance(RETRANSMIT_MS);
+    link.step();
+    assert_eq!(read_all(&mut link.controller), RESET);
+    assert_eq!(link.host.stats().retransmissions, 1);
+
+    // Once acknowledged, it isn't sent again.
+    link.advance(RETRANSMIT_MS);
+    link.step();
+    assert_eq!(read_all(&mut link.controller), []);
+    assert_eq!(link.host.stats().retransmissions, 1);
+}
+
+#[test]
+fn out_of_order_events_are_discarded_and_recovered() {
+    let mut link = Loopback::new().establish();
+
+    link.controller.write_all(&RESET_COMPLETE).unwrap();
+    link.controller.write_all(&HARDWARE_ERROR).unwrap();
+    let frames = link.wire.borrow_mut().take_frames(TO_HOST);
+    assert_eq!(frames.len(), 2);
+
+    link.wire.borrow_mut().deliver(TO_HOST, &frames[1]);
+    link.wire.borrow_mut().deliver(TO_HOST, &frames[0]);
+    link.host.poll().unwrap();
+    assert_eq!(link.host.stats().out_of_order, 1);
+    assert_eq!(read_all(&mut link.host), RESET_COMPLETE);
+
+    // The host's acknowledgement only covers the first event, so the second is sent again.
+    link.step();
+    link.advance(RETRANSMIT_MS);
+    link.step();
+    assert_eq!(read_all(&mut link.host), HARDWARE_ERROR);
+    assert!(link.controller.stats().retransmissions >= 1);
+}
+
+#[test]
+fn corrupted_frame_is_discarded() {
+    let mut link = Loopback::new().establish();
+
+    link.host.write_all(&RESET).unwrap();
+    let mut frames = link.wire.borrow_mut().take_frames(TO_CONTROLLER);
+    let frame = frames.iter_mut().find(|f| is_reliable(f)).unwrap();
+    let last = frame.len() - 2;
+    frame[last] ^= 0x01;
+    link.wire.borrow_mut().deliver(TO_CONTROLLER, frame);
+
+    link.controller.poll().unwrap();
+    assert_eq!(read_all(&mut link.controller), []);
+    assert_eq!(link.controller.stats().bad_frames, 1);
+
+    link.advance(RETRANSMIT_MS);
+    link.step();
+    assert_eq!(read_all(&mut link.controller), RESET);
+}
+
+#[test]
+fn write_blocks_until_the_link_has_room() {
+    let wire = Rc::new(RefCell::new(Wire::default()));
+    let now = Rc::new(Cell::new(0));
+    let controller = Rc::new(RefCell::new(endpoint(&wire, &now, TO_HOST, TO_CONTROLLER)));
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    // The controller answers LE Read Buffer Size with 4-byte buffers, and plenty of them. It is
+    // slow, and only runs every fiftieth time the host looks for input, so the link fills up.
+    let peer: Box<dyn FnMut()> = {
+        let wire = wire.clone();
+        let controller = controller.clone();
+        let received = received.clone();
+        let mut turns = 0;
+        Box::new(move || {
+            turns += 1;
+            if turns % 50 != 0 {
+                return;
+            }
+            wire.borrow_mut().deliver_all();
+            let mut controller = controller.borrow_mut();
+            controller.poll().unwrap();
+            let packets = read_all(&mut controller);
+            if packets == [0x01, 0x02, 0x20, 0x00] {
+                let complete = [0x04, 0x0E, 0x07, 0x01, 0x02, 0x20, 0x00, 0x04, 0x00, 0x10];
+                controller.write_all(&complete).unwrap();
+            } else {
+                received.borrow_mut().extend(packets);
+            }
+            wire.borrow_mut().deliver_all();
+        })
+    };
+    let clock_now = now.clone();
+    let clock: Box<dyn FnMut() -> u64> = Box::new(move || clock_now.get());
+    let mut host = H5::new(
+        Serial {
+            wire: wire.clone(),
+            tx: TO_CONTROLLER,
+            rx: TO_HOST,
+            peer: Some(peer),
+        },
+        clock,
+    );
+    while !(host.is_active() && controller.borrow().is_active()) {
+        host.poll().unwrap();
+        now.set(now.get() + RETRANSMIT_MS);
+    }
+
+    let (mut ble, qslot) = Ble::new(host, NoDelay);
+    let (buffer_size, _qslot) = ble
+        .read_acl_buffer_size(qslot, PollBehavior::Strict)
+        .unwrap();
+    assert_eq!(
+        buffer_size,
+        AclBufferSize {
+            data_len: 4,
+            num_packets: 16
+        }
+    );
+
+    // Nine fragments, more than the window and the queue of the link hold together.
+    let payload: Vec<u8> = (0..30).collect();
+    let frame = L2capFrame::new(0x0040, 0x0004, &payload).unwrap();
+    ble.send_acl(&frame).unwrap();
+    for _ in 0..100 {
+        assert!(matches!(ble.try_poll_raw(), Err(BleError::WouldBlock)));
+    }
+
+    let mut expected = Vec::new();
+    let data: Vec<u8> = [&[30, 0x00, 0x04, 0x00][..], &payload].concat();
+    for (i, fragment) in data.chunks(4).enumerate() {
+        let flags = if i == 0 { 0x00 } else { 0x10 };
+        expected.extend([0x02, 0x40, flags, fragment.len() as u8, 0x00]);
+        expected.extend(fragment);
+    }
+    assert_eq!(*received.borrow(), expected);
+    assert_eq!(ble.hci().stats().retransmissions, 0);
+}
+
+#[test]
+fn write_rejects_packets_too_long_for_the_link() {
+    let mut link = Loopback::new().establish();
+
+    // An ACL data packet with 300 bytes of data, more than an H5 payload can hold.
+    let mut packet = vec![0x02, 0x40, 0x00, 0x2C, 0x01];
+    packet.resize(packet.len() + 300, 0xA5);
+    assert!(matches!(
+        link.host.write_all(&packet),
+        Err(H5Error::PacketTooLong)
+    ));
+
+    // The link carries on with the next packet.
+    link.host.write_all(&RESET).unwrap();
+    link.step();
+    assert_eq!(read_all(&mut link.controller), RESET);
+}
+
+#[test]
+fn peer_reset_reports_dropped_commands() {
+    let mut link = Loopback::new().establish();
+
+    link.host.write_all(&RESET).unwrap();
+    link.wire.borrow_mut().take_frames(TO_CONTROLLER);
+    link.restart_controller();
+    link.step();
+    assert_eq!(link.host.stats().peer_resets, 1);
+
+    let mut buf = [0; 8];
+    assert!(matches!(
+        link.host.read(&mut buf),
+        Err(H5Error::PeerReset {
+            dropped_commands: 1
+        })
+    ));
+    // It is only reported once, and the link comes back.
+    assert_eq!(link.host.read(&mut buf).unwrap(), 0);
+    let link = link.establish();
+    assert!(link.host.is_active());
+}
@@ -0,0 +1,614 @@
+//! Property tests for the codec: every message decodes back to what was encoded, for many
+//! pseudo-random values of its fields.
+
+use std::fmt::Debug;
+
+use wable::devices::ble::{
+    command::{
+        le_clear_advertising_sets::LeClearAdvertisingSets,
+        le_create_connection::LeCreateConnection,
+        le_read_buffer_size::{LeBufferSize, LeReadBufferSize},
+        le_read_buffer_size_v2::{LeBufferSizeV2, LeReadBufferSizeV2},
+        le_read_local_supported_features::LeReadLocalSupportedFeatures,
+        le_read_maximum_advertising_data_length::LeReadMaximumAdvertisingDataLength,
+        le_read_number_of_supported_advertising_sets::LeReadNumberOfSupportedAdvertisingSets,
+        le_remove_advertising_set::LeRemoveAdvertisingSet,
+        le_set_advertising_data::LeSetAdvertisingData,
+        le_set_advertising_enable::LeSetAdvertisingEnable,
+        le_set_advertising_parameters::LeSetAdvertisingParameters,
+        le_set_event_mask::LeSetEventMask,
+        le_set_extended_advertising_data::LeSetExtendedAdvertisingData,
+        le_set_extended_advertising_enable::{
+            AdvertisingSetEnable, AdvertisingSetEnables, LeSetExtendedAdvertisingEnable,
+        },
+        le_set_extended_advertising_parameters::LeSetExtendedAdvertisingParameters,
+        le_set_extended_scan_enable::LeSetExtendedScanEnable,
+        le_set_extended_scan_parameters::{
+            LeSetExtendedScanParameters, PhyScanParameters, ScanningPhys,
+        },
+        le_set_extended_scan_response_data::LeSetExtendedScanResponseData,
+        le_set_scan_enable::LeSetScanEnable,
+        le_set_scan_parameters::LeSetScanParameters,
+        le_set_scan_response_data::LeSetScanResponseData,
+        read_bd_addr::ReadBdAddr,
+        read_local_supported_commands::ReadLocalSupportedCommands,
+        read_local_supported_features::ReadLocalSupportedFeatures,
+        read_local_version_information::{LocalVersionInformation, ReadLocalVersionInformation},
+        reset::Reset,
+        set_event_mask::SetEventMask,
+        AnyCommand, CommandParameters, EncodedCommand,
+    },
+    data::{
+        address::Address,
+        advertising_data::{self, AdvertisingData, AdvertisingDataOperation},
+        event_mask::{EventMask, LeEventMask},
+        features::{LeFeatures, LmpFeatures},
+        opcode::Opcode,
+        status_code::{StatusCode, StatusError, WithStatus},
+        supported_commands::SupportedCommands,
+        Buffer, Decode, Decoder, Encode, Encoder, U24,
+    },
+    event::{
+        command_complete::CommandComplete,
+        command_status::CommandStatus,
+        disconnection_complete::DisconnectionComplete,
+        hardware_error::HardwareError,
+        le_advertising_report::{
+            LeAdvertisingReport, LeAdvertisingReportItem, OwnedLeAdvertisingReport,
+        },
+        le_advertising_set_terminated::LeAdvertisingSetTerminated,
+        le_connection_complete::LeConnectionComplete,
+        le_extended_advertising_report::{
+            LeExtendedAdvertisingReport, LeExtendedAdvertisingReportItem,
+            OwnedLeExtendedAdvertisingReport,
+        },
+        number_of_completed_packets::NumberOfCompletedPackets,
+        EncodedEvent, EventParameters,
+    },
+};
+
+const CASES: usize = 256;
+
+/// A small xorshift generator, so that failures can be reproduced.
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Rng {
+        Rng(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.u64() as u8
+    }
+
+    fn u16(&mut self) -> u16 {
+        self.u64() as u16
+    }
+
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        [(); N].map(|()| self.u8())
+    }
+
+    /// Return parameters that succeed most of the time, like [Rng::status].
+    fn with_status<T>(&mut self, rest: T) -> WithStatus<T> {
+        match self.status().error() {
+            Some(error) => WithStatus::Failure(error),
+            None => WithStatus::Success(rest),
+        }
+    }
+
+    fn status(&mut self) -> StatusCode {
+        // Mostly successes, since those are followed by more parameters.
+        if self.u8() < 0x40 {
+            StatusCode(self.u8())
+        } else {
+            StatusCode::SUCCESS
+        }
+    }
+}
+
+fn command_round_trips<C>(command: C)
+where
+    C: CommandParameters + Decode + PartialEq + Debug,
+{
+    let encoded = EncodedCommand::encode_ref(&command).unwrap();
+    assert_eq!(encoded.opcode, C::OPCODE);
+    assert_eq!(encoded.decode::<C>().unwrap(), Some(command));
+}
+
+fn event_round_trips<E>(event: E)
+where
+    E: for<'a> EventParameters<'a> + Encode + PartialEq + Debug,
+{
+    let encoded = EncodedEvent::encode(&event).unwrap();
+    assert_eq!(encoded.code, E::EVENT_CODE);
+    assert_eq!(encoded.decode::<E>().unwrap(), Some(event));
+}
+
+fn value_round_trips<T>(value: T)
+where
+    T: Encode + Decode + PartialEq + Debug,
+{
+    let mut buf = Buffer::<255>::new();
+    buf.encode(&value).unwrap();
+    let mut encoded: &[u8] = &buf;
+    assert_eq!(encoded.decode::<T>().unwrap(), value);
+    assert!(encoded.is_empty());
+}
+
+#[test]
+fn commands_round_trip() {
+    let mut rng = Rng::new();
+    command_round_trips(Reset {});
+
+    for _ in 0..CASES {
+        command_round_trips(SetEventMask {
+            mask: EventMask(rng.u64()),
+        });
+        command_round_trips(LeSetEventMask {
+            le_event_mask: LeEventMask(rng.u64()),
+        });
+        command_round_trips(LeSetScanParameters {
+            le_scan_type: rng.u8(),
+            le_scan_interval: rng.u16(),
+            le_scan_window: rng.u16(),
+            own_address_type: rng.u8(),
+            scanning_filter_policy: rng.u8(),
+        });
+        command_round_trips(LeSetScanEnable {
+            le_scan_enable: rng.u8(),
+            filter_duplicates: rng.u8(),
+        });
+        command_round_trips(LeCreateConnection {
+            le_scan_interval: rng.u16(),
+            le_scan_window: rng.u16(),
+            initiator_filter_policy: rng.u8(),
+            peer_address_type: rng.u8(),
+            peer_address: Address(rng.bytes()),
+            own_address_type: rng.u8(),
+            connection_interval_min: rng.u16(),
+            connection_interval_max: rng.u16(),
+            max_latency: rng.u16(),
+            supervision_timeout: rng.u16(),
+            min_ce_length: rng.u16(),
+            max_ce_length: rng.u16(),
+        });
+        command_round_trips(LeSetAdvertisingParameters {
+            advertising_interval_min: rng.u16(),
+            advertising_interval_max: rng.u16(),
+            advertising_type: rng.u8(),
+            own_address_type: rng.u8(),
+            peer_address_type: rng.u8(),
+            peer_address: Address(rng.bytes()),
+            advertising_channel_map: rng.u8(),
+            advertising_filter_policy: rng.u8(),
+        });
+        let data: [u8; 31] = rng.bytes();
+        let len = rng.u8() as usize % (data.len() + 1);
+        command_round_trips(LeSetAdvertisingData {
+            advertising_data: AdvertisingData::try_from(&data[..len]).unwrap(),
+        });
+        command_round_trips(LeSetScanResponseData {
+            scan_response_data: AdvertisingData::try_from(&data[len..]).unwrap(),
+        });
+        command_round_trips(LeSetAdvertisingEnable {
+            advertising_enable: rng.u8(),
+        });
+        command_round_trips(LeSetExtendedAdvertisingParameters {
+            advertising_handle: rng.u8(),
+            advertising_event_properties: rng.u16(),
+            primary_advertising_interval_min: U24::new(rng.u64() as u32 & 0xFF_FFFF).unwrap(),
+            primary_advertising_interval_max: U24::new(rng.u64() as u32 & 0xFF_FFFF).unwrap(),
+            primary_advertising_channel_map: rng.u8(),
+            own_address_type: rng.u8(),
+            peer_address_type: rng.u8(),
+            peer_address: Address(rng.bytes()),
+            advertising_filter_policy: rng.u8(),
+            advertising_tx_power: rng.u8() as i8,
+            primary_advertising_phy: rng.u8(),
+            secondary_advertising_max_skip: rng.u8(),
+            secondary_advertising_phy: rng.u8(),
+            advertising_sid: rng.u8(),
+            scan_request_notification_enable: rng.u8(),
+        });
+        let data: [u8; 251] = rng.bytes();
+        let len = rng.u8() as usize % (data.len() + 1);
+        let operations = [
+            AdvertisingDataOperation::Intermediate,
+            AdvertisingDataOperation::First,
+            AdvertisingDataOperation::Last,
+            AdvertisingDataOperation::Complete,
+            AdvertisingDataOperation::Unchanged,
+        ];
+        command_round_trips(LeSetExtendedAdvertisingData {
+            advertising_handle: rng.u8(),
+            operation: operations[rng.u8() as usize % operations.len()],
+            fragment_preference: rng.u8(),
+            advertising_data: data[..len].try_into().unwrap(),
+        });
+        command_round_trips(LeSetExtendedScanResponseData {
+            advertising_handle: rng.u8(),
+            operation: operations[rng.u8() as usize % operations.len()],
+            fragment_preference: rng.u8(),
+            scan_response_data: data[len..].try_into().unwrap(),
+        });
+        let mut sets = AdvertisingSetEnables::default();
+        for _ in 0..rng.u8() % 64 {
+            let set = AdvertisingSetEnable {
+                advertising_handle: rng.u8(),
+                duration: rng.u16(),
+                max_extended_advertising_events: rng.u8(),
+            };
+            sets.0.push(set).unwrap();
+        }
+        command_round_trips(LeSetExtendedAdvertisingEnable {
+            enable: rng.u8(),
+            sets,
+        });
+        command_round_trips(LeRemoveAdvertisingSet {
+            advertising_handle: rng.u8(),
+        });
+        let mut phy = || PhyScanParameters {
+            scan_type: rng.u8(),
+            scan_interval: rng.u16(),
+            scan_window: rng.u16(),
+        };
+        let (le_1m, le_coded) = (phy(), phy());
+        let phys = rng.u8();
+        command_round_trips(LeSetExtendedScanParameters {
+            own_address_type: rng.u8(),
+            scanning_filter_policy: rng.u8(),
+            phys: ScanningPhys {
+                le_1m: (phys & 1 != 0).then_some(le_1m),
+                le_coded: (phys & 2 != 0).then_some(le_coded),
+            },
+        });
+        command_round_trips(LeSetExtendedScanEnable {
+            enable: rng.u8(),
+            filter_duplicates: rng.u8(),
+            duration: rng.u16(),
+            period: rng.u16(),
+        });
+    }
+    command_round_trips(LeClearAdvertisingSets {});
+    command_round_trips(LeReadMaximumAdvertisingDataLength {});
+    command_round_trips(LeReadNumberOfSupportedAdvertisingSets {});
+}
+
+#[test]
+fn extended_advertising_data_is_split_into_fragments() {
+    let data: Vec<u8> = (0..600).map(|i| i as u8).collect();
+    let fragments: Vec<_> = advertising_data::fragments(&data).collect();
+    let operations: Vec<_> = fragments.iter().map(|(operation, _)| *operation).collect();
+    assert_eq!(
+        operations,
+        [
+            AdvertisingDataOperation::First,
+            AdvertisingDataOperation::Intermediate,
+            AdvertisingDataOperation::Last,
+        ]
+    );
+    let joined: Vec<u8> = fragments
+        .iter()
+        .flat_map(|(_, fragment)| fragment.as_bytes().iter().copied())
+        .collect();
+    assert_eq!(joined, data);
+
+    let (operation, fragment) = advertising_data::fragments(&[]).next().unwrap();
+    assert_eq!(operation, AdvertisingDataOperation::Complete);
+    assert!(fragment.as_bytes().is_empty());
+}
+
+#[test]
+fn oversized_advertising_data_is_rejected() {
+    let mut parameters = [0; 32];
+    parameters[0] = 32;
+    assert!(parameters.as_slice().decode::<AdvertisingData>().is_err());
+    assert!(AdvertisingData::try_from(&[0; 32][..]).is_err());
+}
+
+#[test]
+fn commands_are_told_apart_by_opcode() {
+    let encoded = EncodedCommand::encode(Reset {}).unwrap();
+    assert_eq!(encoded.decode::<SetEventMask>().unwrap(), None);
+}
+
+#[test]
+fn status_codes_round_trip() {
+    for code in 0..=u8::MAX {
+        value_round_trips(StatusCode(code));
+        match StatusCode(code).error() {
+            Some(error) => value_round_trips(WithStatus::<u16>::Failure(error)),
+            None => value_round_trips(WithStatus::Success(0x1234u16)),
+        }
+    }
+    assert_eq!(
+        StatusCode::from(StatusError::CommandDisallowed),
+        StatusCode(0x0C)
+    );
+}
+
+#[test]
+fn primitives_round_trip() {
+    let mut rng = Rng::new();
+
+    for _ in 0..CASES {
+        value_round_trips(rng.u8() as i8);
+        value_round_trips(rng.u16() as i16);
+        value_round_trips(rng.u64() as u32);
+        value_round_trips(rng.u64());
+        value_round_trips(U24::new(rng.u64() as u32 & 0xFF_FFFF).unwrap());
+        value_round_trips(rng.u8() & 1 == 1);
+    }
+}
+
+#[test]
+fn command_flow_events_round_trip() {
+    let mut rng = Rng::new();
+
+    for _ in 0..CASES {
+        let num_hci_command_packets = rng.u8();
+        event_round_trips(CommandComplete::<Reset> {
+            num_hci_command_packets,
+            command_opcode: Reset::OPCODE,
+            return_parameters: rng.status(),
+        });
+        event_round_trips(CommandComplete::<SetEventMask> {
+            num_hci_command_packets,
+            command_opcode: SetEventMask::OPCODE,
+            return_parameters: rng.status(),
+        });
+        event_round_trips(CommandComplete::<LeSetScanParameters> {
+            num_hci_command_packets,
+            command_opcode: LeSetScanParameters::OPCODE,
+            return_parameters: rng.status(),
+        });
+        event_round_trips(CommandComplete::<LeSetScanEnable> {
+            num_hci_command_packets,
+            command_opcode: LeSetScanEnable::OPCODE,
+            return_parameters: rng.status(),
+        });
+        event_round_trips(CommandComplete::<LeSetAdvertisingParameters> {
+            num_hci_command_packets,
+            command_opcode: LeSetAdvertisingParameters::OPCODE,
+            return_parameters: rng.status(),
+        });
+        event_round_trips(CommandComplete::<LeSetAdvertisingEnable> {
+            num_hci_command_packets,
+            command_opcode: LeSetAdvertisingEnable::OPCODE,
+            return_parameters: rng.status(),
+        });
+        let version = LocalVersionInformation {
+            hci_version: rng.u8(),
+            hci_subversion: rng.u16(),
+            lmp_version: rng.u8(),
+            company_identifier: rng.u16(),
+            lmp_subversion: rng.u16(),
+        };
+        event_round_trips(CommandComplete::<ReadLocalVersionInformation> {
+            num_hci_command_packets,
+            command_opcode: ReadLocalVersionInformation::OPCODE,
+            return_parameters: rng.with_status(version),
+        });
+        let address = Address(rng.bytes());
+        event_round_trips(CommandComplete::<ReadBdAddr> {
+            num_hci_command_packets,
+            command_opcode: ReadBdAddr::OPCODE,
+            return_parameters: rng.with_status(address),
+        });
+        let commands = SupportedCommands(rng.bytes());
+        event_round_trips(CommandComplete::<ReadLocalSupportedCommands> {
+            num_hci_command_packets,
+            command_opcode: ReadLocalSupportedCommands::OPCODE,
+            return_parameters: rng.with_status(commands),
+        });
+        let features = LmpFeatures(rng.u64());
+        event_round_trips(CommandComplete::<ReadLocalSupportedFeatures> {
+            num_hci_command_packets,
+            command_opcode: ReadLocalSupportedFeatures::OPCODE,
+            return_parameters: rng.with_status(features),
+        });
+        let le_features = LeFeatures(rng.u64());
+        event_round_trips(CommandComplete::<LeReadLocalSupportedFeatures> {
+            num_hci_command_packets,
+            command_opcode: LeReadLocalSupportedFeatures::OPCODE,
+            return_parameters: rng.with_status(le_features),
+        });
+        let buffer_size = LeBufferSize {
+            le_acl_data_packet_length: rng.u16(),
+            total_num_le_acl_data_packets: rng.u8(),
+        };
+        event_round_trips(CommandComplete::<LeReadBufferSize> {
+            num_hci_command_packets,
+            command_opcode: LeReadBufferSize::OPCODE,
+            return_parameters: rng.with_status(buffer_size),
+        });
+        let buffer_size = LeBufferSizeV2 {
+            le_acl_data_packet_length: rng.u16(),
+            total_num_le_acl_data_packets: rng.u8(),
+            iso_data_packet_length: rng.u16(),
+            total_num_iso_data_packets: rng.u8(),
+        };
+        event_round_trips(CommandComplete::<LeReadBufferSizeV2> {
+            num_hci_command_packets,
+            command_opcode: LeReadBufferSizeV2::OPCODE,
+            return_parameters: rng.with_status(buffer_size),
+        });
+        let tx_power = rng.u8() as i8;
+        event_round_trips(CommandComplete::<LeSetExtendedAdvertisingParameters> {
+            num_hci_command_packets,
+            command_opcode: LeSetExtendedAdvertisingParameters::OPCODE,
+            return_parameters: rng.with_status(tx_power),
+        });
+        event_round_trips(CommandComplete::<LeSetExtendedAdvertisingEnable> {
+            num_hci_command_packets,
+            command_opcode: LeSetExtendedAdvertisingEnable::OPCODE,
+            return_parameters: rng.status(),
+        });
+        let max_len = rng.u16();
+        event_round_trips(CommandComplete::<LeReadMaximumAdvertisingDataLength> {
+            num_hci_command_packets,
+            command_opcode: LeReadMaximumAdvertisingDataLength::OPCODE,
+            return_parameters: rng.with_status(max_len),
+        });
+        let num_sets = rng.u8();
+        event_round_trips(CommandComplete::<LeReadNumberOfSupportedAdvertisingSets> {
+            num_hci_command_packets,
+            command_opcode: LeReadNumberOfSupportedAdvertisingSets::OPCODE,
+            return_parameters: rng.with_status(num_sets),
+        });
+        event_round_trips(CommandComplete::<AnyCommand> {
+            num_hci_command_packets,
+            command_opcode: Opcode(rng.u16()),
+            return_parameters: (),
+        });
+        event_round_trips(CommandStatus::<LeCreateConnection>::new(
+            rng.status(),
+            num_hci_command_packets,
+            LeCreateConnection::OPCODE,
+        ));
+        event_round_trips(CommandStatus::<AnyCommand>::new(
+            rng.status(),
+            num_hci_command_packets,
+            Opcode(rng.u16()),
+        ));
+    }
+}
+
+#[test]
+fn events_round_trip() {
+    let mut rng = Rng::new();
+
+    for _ in 0..CASES {
+        event_round_trips(DisconnectionComplete {
+            status: rng.status(),
+            connection_handle: rng.u16(),
+            reason: StatusCode(rng.u8()),
+        });
+        event_round_trips(HardwareError {
+            hardware_code: rng.u8(),
+        });
+        let entries: Vec<(u16, u16)> = (0..rng.u8() % 64).map(|_| (rng.u16(), rng.u16())).collect();
+        let completed = NumberOfCompletedPackets::from_entries(entries.iter().copied()).unwrap();
+        assert!(completed.entries().eq(entries));
+        event_round_trips(completed);
+        event_round_trips(LeConnectionComplete {
+            status: rng.status(),
+            connection_handle: rng.u16(),
+            role: rng.u8(),
+            peer_address_type: rng.u8(),
+            peer_address: Address(rng.bytes()),
+            connection_interval: rng.u16(),
+            peripheral_latency: rng.u16(),
+            supervision_timeout: rng.u16(),
+            central_clock_accuracy: rng.u8(),
+        });
+        event_round_trips(LeAdvertisingSetTerminated {
+            status: rng.status(),
+            advertising_handle: rng.u8(),
+            connection_handle: rng.u16(),
+            num_completed_extended_advertising_events: rng.u8(),
+        });
+    }
+}
+
+#[test]
+fn advertising_reports_round_trip() {
+    let mut rng = Rng::new();
+
+    for _ in 0..CASES {
+        let data: [u8; 0x1F] = rng.bytes();
+        // Up to six reports of the longest data fit into an event.
+        let count = 1 + rng.u8() % 6;
+        let mut item = || LeAdvertisingReportItem {
+            event_type: rng.u8(),
+            address_type: rng.u8(),
+            address: rng.bytes(),
+            data: &data[..rng.u8() as usize % (data.len() + 1)],
+            rssi: rng.u8() as i8,
+        };
+        let items: Vec<_> = (0..count).map(|_| item()).collect();
+        let report = OwnedLeAdvertisingReport::from_items(items.iter().copied()).unwrap();
+        event_round_trips(report.clone());
+
+        // The borrowed form decodes to the same report, and its items to the same items.
+        let encoded = EncodedEvent::encode(&report).unwrap();
+        let decoded = encoded.decode::<LeAdvertisingReport>().unwrap().unwrap();
+        assert_eq!(decoded, report.report());
+        let decoded_items: Vec<_> = decoded.items().map(Result::unwrap).collect();
+        assert_eq!(decoded_items, items);
+        assert_eq!(
+            EncodedEvent::encode(&decoded).unwrap().parameters,
+            encoded.parameters
+        );
+    }
+}
+
+#[test]
+fn extended_advertising_reports_round_trip() {
+    let mut rng = Rng::new();
+
+    for _ in 0..CASES {
+        let data: [u8; 229] = rng.bytes();
+        let len = rng.u8() as usize % (data.len() + 1);
+        let mut item = || LeExtendedAdvertisingReportItem {
+            event_type: rng.u16(),
+            address_type: rng.u8(),
+            address: Address(rng.bytes()),
+            primary_phy: rng.u8(),
+            secondary_phy: rng.u8(),
+            advertising_sid: rng.u8(),
+            tx_power: rng.u8() as i8,
+            rssi: rng.u8() as i8,
+            periodic_advertising_interval: rng.u16(),
+            direct_address_type: rng.u8(),
+            direct_address: Address(rng.bytes()),
+            data: &[],
+        };
+        // Two reports share the room in an event.
+        let items = [
+            LeExtendedAdvertisingReportItem {
+                data: &data[..len / 2],
+                ..item()
+            },
+            LeExtendedAdvertisingReportItem {
+                data: &data[len / 2..len],
+                ..item()
+            },
+        ];
+        let count = if len + 2 * 24 <= 253 { 2 } else { 1 };
+        let report =
+            OwnedLeExtendedAdvertisingReport::from_items(items[..count].iter().copied()).unwrap();
+        event_round_trips(report.clone());
+
+        let encoded = EncodedEvent::encode(&report).unwrap();
+        let decoded = encoded
+            .decode::<LeExtendedAdvertisingReport>()
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded, report.report());
+        let decoded_items: Vec<_> = decoded.items().map(Result::unwrap).collect();
+        assert_eq!(decoded_items, items[..count]);
+        assert_eq!(decoded.into_owned(), report);
+    }
+}
+
+#[test]
+fn oversized_advertising_data_is_not_encoded() {
+    let data = [0; 0x20];
+    let item = LeAdvertisingReportItem {
+        event_type: 0x00,
+        address_type: 0x00,
+        address: [0; 6],
+        data: &data,
+        rssi: 0x00,
+    };
+    assert!(OwnedLeAdvertisingReport::from_items([item]).is_err());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{MockController, NoDelay};
+use wable::devices::ble::{
+    command::{le_set_scan_enable::LeSetScanEnable, reset::Reset, CommandParameters},
+    data::{
+        event_mask::{EventMask, EventMasks, LeEventMask},
+        status_code::StatusError,
+    },
+    event::{
+        command_complete::CommandComplete, disconnection_complete::DisconnectionComplete,
+        hardware_error::HardwareError, le_advertising_report::OwnedLeAdvertisingReport,
+        le_connection_complete::LeConnectionComplete,
+        number_of_completed_packets::NumberOfCompletedPackets,
+    },
+    router::{EventRouter, Handler, Subscription},
+    Ble, PollBehavior,
+};
+
+const SCAN_ENABLE: LeSetScanEnable = LeSetScanEnable {
+    le_scan_enable: 0x01,
+    filter_duplicates: 0x00,
+};
+
+#[test]
+fn events_during_a_command_reach_subscribers() {
+    let hci = MockController::new()
+        .expect_command(LeSetScanEnable::OPCODE, &[0x01, 0x00])
+        .reply_le_meta(0x02, &[0x00])
+        .reply_event(0x05, &[0x00, 0x40, 0x00, 0x13])
+        .reply_le_meta(0x02, &[0x00])
+        .reply_command_complete(1, LeSetScanEnable::OPCODE, &[0x00]);
+    let router = (
+        Subscription::<OwnedLeAdvertisingReport, 1>::new(),
+        Subscription::<DisconnectionComplete, 4>::new(),
+    );
+    let (mut ble, [qslot]) = Ble::with_router(hci, NoDelay, router);
+
+    ble.run_until_complete(qslot, PollBehavior::Filter, SCAN_ENABLE)
+        .unwrap();
+
+    let (adverts, disconnections) = ble.router_mut();
+    assert!(adverts.pop().is_some());
+    assert!(adverts.pop().is_none());
+    assert_eq!(adverts.missed(), 1);
+    let disconnection = disconnections.pop().unwrap();
+    assert_eq!(disconnection.connection_handle, 0x0040);
+    assert_eq!(
+        disconnection.reason.error(),
+        Some(StatusError::RemoteUserTerminatedConnection)
+    );
+    assert_eq!(ble.unrouted_events(), 0);
+
+    ble.hci().finish();
+}
+
+#[test]
+fn handlers_are_called_and_unsubscribed_events_counted() {
+    let hci = MockController::new()
+        .reply_event(0x10, &[0x2A])
+        .reply_event(0x05, &[0x00, 0x40, 0x00, 0x13]);
+    let mut hardware_codes = Vec::new();
+    let router = Handler::new(|event: HardwareError| hardware_codes.push(event.hardware_code));
+    let (mut ble, _qslot) = Ble::with_router::<1>(hci, NoDelay, router);
+
+    ble.dispatch().unwrap();
+    ble.dispatch().unwrap();
+    assert_eq!(ble.unrouted_events(), 1);
+
+    ble.hci().finish();
+    drop(ble);
+    assert_eq!(hardware_codes, [0x2A]);
+}
+
+#[test]
+fn event_masks_enable_exactly_the_subscribed_events() {
+    let router = (
+        Subscription::<OwnedLeAdvertisingReport, 1>::new(),
+        Subscription::<DisconnectionComplete, 1>::new(),
+        Handler::new(|_: HardwareError| ()),
+    );
+
+    let masks = router.event_masks().with::<LeConnectionComplete>();
+    assert_eq!(
+        masks.events,
+        EventMask::DISCONNECTION_COMPLETE | EventMask::HARDWARE_ERROR | EventMask::LE_META
+    );
+    assert_eq!(
+        masks.le_events,
+        LeEventMask::ADVERTISING_REPORT | LeEventMask::CONNECTION_COMPLETE
+    );
+
+    // Command Complete can't be masked, since it's always sent.
+    assert_eq!(
+        ().event_masks().with::<CommandComplete<Reset>>(),
+        EventMasks::HOST
+    );
+}
+
+#[test]
+fn event_masks_always_enable_the_events_the_host_needs() {
+    let masks = ().event_masks();
+    assert!(masks.events.contains(EventMask::DISCONNECTION_COMPLETE));
+    assert_eq!(masks.le_events, LeEventMask::NONE);
+
+    let masks = Subscription::<OwnedLeAdvertisingReport, 1>::new().event_masks();
+    assert!(masks.events.contains(EventMask::DISCONNECTION_COMPLETE));
+    // Number Of Completed Packets is always sent.
+    assert_eq!(
+        EventMasks::NONE.with::<NumberOfCompletedPackets>(),
+        EventMasks::NONE
+    );
+}
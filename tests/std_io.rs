@@ -0,0 +1,80 @@
+#![cfg(feature = "std")]
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+    time::Duration,
+};
+
+use wable::devices::ble::{
+    command::{reset::Reset, CommandParameters},
+    std_io::{StdDelay, StdTransport},
+    Ble, BleError, PollBehavior,
+};
+
+/// Starts a controller on a localhost socket that runs `script` on the accepted connection.
+fn controller(script: impl FnOnce(std::net::TcpStream) + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        script(stream);
+    });
+    addr
+}
+
+#[test]
+fn runs_a_command_over_tcp() {
+    let addr = controller(|mut stream| {
+        let mut command = [0; 4];
+        stream.read_exact(&mut command).unwrap();
+        let opcode = Reset::OPCODE.0.to_le_bytes();
+        assert_eq!(command, [0x01, opcode[0], opcode[1], 0x00]);
+
+        // Split the event across writes, so the host sees it arrive in pieces.
+        stream.write_all(&[0x04, 0x0E, 0x04]).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        stream
+            .write_all(&[0x01, opcode[0], opcode[1], 0x00])
+            .unwrap();
+    });
+
+    let transport = StdTransport::connect_tcp(addr).unwrap();
+    let (mut ble, qslot) = Ble::new(transport, StdDelay);
+
+    let (status, _qslot) = ble
+        .run_until_complete(qslot, PollBehavior::Strict, Reset {})
+        .unwrap();
+    assert!(status.is_successful());
+}
+
+#[test]
+fn nothing_to_read_would_block() {
+    let addr = controller(|stream| {
+        thread::sleep(Duration::from_millis(200));
+        drop(stream);
+    });
+
+    let transport = StdTransport::connect_tcp(addr).unwrap();
+    let (mut ble, _qslot) = Ble::new(transport, StdDelay);
+
+    assert!(matches!(ble.try_poll_packet(), Err(BleError::WouldBlock)));
+}
+
+#[test]
+fn closed_connection_is_an_error() {
+    let addr = controller(drop);
+
+    let transport = StdTransport::connect_tcp(addr).unwrap();
+    let (mut ble, _qslot) = Ble::new(transport, StdDelay);
+
+    let err = loop {
+        match ble.try_poll_packet() {
+            Err(BleError::WouldBlock) => continue,
+            Err(err) => break err,
+            Ok(_) => panic!("unexpected packet"),
+        }
+    };
+    assert!(matches!(err, BleError::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+}
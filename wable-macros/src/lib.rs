@@ -0,0 +1,361 @@
+//! Derive macros for the HCI codec in `wable::devices::ble::data`.
+//!
+//! Fields are encoded and decoded in declaration order with their own `Encode`/`Decode` impls,
+//! which are little-endian for the integer types, as the HCI requires. Attributes turn a plain
+//! parameter struct into a command or an event:
+//!
+//! - `#[opcode(ogf = LE_CONTROLLER, ocf = 0x000D)]` on a `#[derive(Encode)]` struct implements
+//!   `CommandParameters`. `ogf` is either the name of one of the `Ogf` constants or a number.
+//! - `#[event(code = 0x05)]` on a `#[derive(Decode)]` struct implements `EventParameters`.
+//! - `#[event(subevent = 0x01)]` on a `#[derive(Decode)]` struct makes it an LE Meta event:
+//!   instead of `Decode`, it implements `MaybeDecode`, matching the subevent code first. With
+//!   `#[derive(Encode)]`, the subevent code is written before the fields.
+//!
+//! Fieldless enums are encoded as their discriminant, which is a `u8` unless the enum has a
+//! `#[repr(u16)]`. Decoding a value that isn't one of the variants is an error.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DataEnum, DeriveInput, Error, Expr,
+    Fields, Ident, Index, Lit, Result,
+};
+
+/// The LE Meta event, which carries all LE subevents.
+const LE_META_EVENT_CODE: u8 = 0x3E;
+
+#[proc_macro_derive(Encode, attributes(opcode, event))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_encode(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Decode, attributes(event))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_decode(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_encode(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    if let Data::Enum(data) = &input.data {
+        return expand_enum_encode(input, data);
+    }
+    let fields = struct_fields(input)?;
+
+    let accessors: Vec<TokenStream2> = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|field| {
+                let ident = &field.ident;
+                quote!(#ident)
+            })
+            .collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                quote!(#index)
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    let subevent = match event_kind(&input.attrs)? {
+        Some(EventKind::Subevent(subevent)) => {
+            check_u8_literal(&subevent)?;
+            quote! {
+                let subevent_code: u8 = #subevent;
+                e.encode(&subevent_code)?;
+            }
+        }
+        _ => TokenStream2::new(),
+    };
+
+    let data = quote!(::wable::devices::ble::data);
+    let mut expanded = quote! {
+        impl #impl_generics #data::Encode for #name #ty_generics #where_clause {
+            fn encode<E>(&self, e: &mut E) -> ::core::result::Result<(), #data::EncoderFull>
+            where
+                E: #data::Encoder + ?::core::marker::Sized,
+            {
+                #subevent
+                #(e.encode(&self.#accessors)?;)*
+
+                ::core::result::Result::Ok(())
+            }
+        }
+    };
+
+    if let Some(attr) = find_attr(&input.attrs, "opcode")? {
+        let mut ogf = None;
+        let mut ocf = None;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("ogf") {
+                ogf = Some(meta.value()?.parse::<Expr>()?);
+            } else if meta.path.is_ident("ocf") {
+                ocf = Some(meta.value()?.parse::<Expr>()?);
+            } else {
+                return Err(meta.error("expected `ogf` or `ocf`"));
+            }
+            Ok(())
+        })?;
+        let ogf = ogf.ok_or_else(|| Error::new(attr.span(), "missing `ogf`"))?;
+        let ocf = ocf.ok_or_else(|| Error::new(attr.span(), "missing `ocf`"))?;
+
+        let opcode = quote!(#data::opcode);
+        let ogf = match &ogf {
+            Expr::Path(path) if path.path.get_ident().is_some() => {
+                quote!(#opcode::Ogf::#path)
+            }
+            other => quote!(#opcode::Ogf(#other)),
+        };
+
+        expanded.extend(quote! {
+            impl #impl_generics ::wable::devices::ble::command::CommandParameters
+                for #name #ty_generics #where_clause
+            {
+                const OPCODE: #opcode::Opcode = #opcode::Opcode::new(#ogf, #ocf);
+            }
+        });
+    }
+
+    Ok(expanded)
+}
+
+enum EventKind {
+    Code(Expr),
+    Subevent(Expr),
+}
+
+fn expand_decode(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    if let Data::Enum(data) = &input.data {
+        return expand_enum_decode(input, data);
+    }
+    let fields = struct_fields(input)?;
+
+    let event = event_kind(&input.attrs)?;
+
+    let construct = match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|field| &field.ident);
+            quote!(Self { #(#idents: d.decode()?,)* })
+        }
+        Fields::Unnamed(unnamed) => {
+            let decodes = unnamed.unnamed.iter().map(|_| quote!(d.decode()?));
+            quote!(Self(#(#decodes,)*))
+        }
+        Fields::Unit => quote!(Self),
+    };
+
+    let data = quote!(::wable::devices::ble::data);
+    let event_mod = quote!(::wable::devices::ble::event);
+    // Fieldless messages don't touch the decoder.
+    let d = if fields.is_empty() {
+        Ident::new("_d", Span::call_site())
+    } else {
+        format_ident!("d")
+    };
+
+    let expanded = match event {
+        None | Some(EventKind::Code(_)) => {
+            let mut expanded = quote! {
+                impl #impl_generics #data::Decode for #name #ty_generics #where_clause {
+                    fn decode<D>(#d: &mut D) -> ::core::result::Result<Self, #data::DecodeError>
+                    where
+                        D: #data::Decoder + ?::core::marker::Sized,
+                    {
+                        ::core::result::Result::Ok(#construct)
+                    }
+                }
+            };
+            if let Some(EventKind::Code(code)) = event {
+                expanded.extend(quote! {
+                    impl #impl_generics #event_mod::EventParameters<'_> for #name #ty_generics #where_clause {
+                        const EVENT_CODE: #event_mod::EventCode = #event_mod::EventCode(#code);
+                    }
+                });
+            }
+            expanded
+        }
+        Some(EventKind::Subevent(subevent)) => {
+            check_u8_literal(&subevent)?;
+            quote! {
+                impl #impl_generics #data::MaybeDecode for #name #ty_generics #where_clause {
+                    fn maybe_decode<D>(
+                        d: &mut D,
+                    ) -> ::core::result::Result<::core::option::Option<Self>, #data::DecodeError>
+                    where
+                        D: #data::MaybeDecoder + ?::core::marker::Sized,
+                    {
+                        let subevent_code: u8 = d.decode()?;
+                        if subevent_code != #subevent {
+                            return ::core::result::Result::Ok(::core::option::Option::None);
+                        }
+
+                        ::core::result::Result::Ok(::core::option::Option::Some(#construct))
+                    }
+                }
+
+                impl #impl_generics #event_mod::EventParameters<'_> for #name #ty_generics #where_clause {
+                    const EVENT_CODE: #event_mod::EventCode =
+                        #event_mod::EventCode(#LE_META_EVENT_CODE);
+                    const SUBEVENT_CODE: ::core::option::Option<u8> =
+                        ::core::option::Option::Some(#subevent);
+                }
+            }
+        }
+    };
+
+    Ok(expanded)
+}
+
+fn expand_enum_encode(input: &DeriveInput, data: &DataEnum) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let repr = enum_repr(input)?;
+    let variants = fieldless_variants(data)?;
+
+    let data = quote!(::wable::devices::ble::data);
+    Ok(quote! {
+        impl #impl_generics #data::Encode for #name #ty_generics #where_clause {
+            fn encode<E>(&self, e: &mut E) -> ::core::result::Result<(), #data::EncoderFull>
+            where
+                E: #data::Encoder + ?::core::marker::Sized,
+            {
+                let value: #repr = match self {
+                    #(Self::#variants => Self::#variants as #repr,)*
+                };
+                e.encode(&value)
+            }
+        }
+    })
+}
+
+fn expand_enum_decode(input: &DeriveInput, data: &DataEnum) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let repr = enum_repr(input)?;
+    let variants = fieldless_variants(data)?;
+    let error = format!("not a valid {name}");
+
+    let data = quote!(::wable::devices::ble::data);
+    Ok(quote! {
+        impl #impl_generics #data::Decode for #name #ty_generics #where_clause {
+            fn decode<D>(d: &mut D) -> ::core::result::Result<Self, #data::DecodeError>
+            where
+                D: #data::Decoder + ?::core::marker::Sized,
+            {
+                let value: #repr = d.decode()?;
+                #(
+                    if value == Self::#variants as #repr {
+                        return ::core::result::Result::Ok(Self::#variants);
+                    }
+                )*
+
+                ::core::result::Result::Err(#data::DecodeError::Malformed(#error))
+            }
+        }
+    })
+}
+
+/// The integer type an enum is encoded as: `u8`, or `u16` with `#[repr(u16)]`.
+fn enum_repr(input: &DeriveInput) -> Result<Ident> {
+    let mut repr = format_ident!("u8");
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("u8") {
+                repr = format_ident!("u8");
+            } else if meta.path.is_ident("u16") {
+                repr = format_ident!("u16");
+            } else {
+                return Err(meta.error("only `u8` and `u16` enums can be derived"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(repr)
+}
+
+fn fieldless_variants(data: &DataEnum) -> Result<Vec<&Ident>> {
+    data.variants
+        .iter()
+        .map(|variant| match variant.fields {
+            Fields::Unit => Ok(&variant.ident),
+            _ => Err(Error::new(
+                variant.span(),
+                "only enums without fields can be derived",
+            )),
+        })
+        .collect()
+}
+
+fn event_kind(attrs: &[Attribute]) -> Result<Option<EventKind>> {
+    let Some(attr) = find_attr(attrs, "event")? else {
+        return Ok(None);
+    };
+
+    let mut kind = None;
+    attr.parse_nested_meta(|meta| {
+        let value = meta.value()?.parse::<Expr>()?;
+        if kind.is_some() {
+            return Err(meta.error("expected only one of `code` and `subevent`"));
+        }
+        if meta.path.is_ident("code") {
+            kind = Some(EventKind::Code(value));
+        } else if meta.path.is_ident("subevent") {
+            kind = Some(EventKind::Subevent(value));
+        } else {
+            return Err(meta.error("expected `code` or `subevent`"));
+        }
+        Ok(())
+    })?;
+    let kind = kind.ok_or_else(|| Error::new(attr.span(), "expected `code` or `subevent`"))?;
+
+    Ok(Some(kind))
+}
+
+fn struct_fields(input: &DeriveInput) -> Result<&Fields> {
+    match &input.data {
+        Data::Struct(data) => Ok(&data.fields),
+        _ => Err(Error::new(
+            input.ident.span(),
+            "only structs and enums can be derived",
+        )),
+    }
+}
+
+fn find_attr<'a>(attrs: &'a [Attribute], name: &str) -> Result<Option<&'a Attribute>> {
+    let mut found = attrs.iter().filter(|attr| attr.path().is_ident(name));
+    let first = found.next();
+    if let Some(second) = found.next() {
+        return Err(Error::new(
+            second.span(),
+            format!("duplicate `{name}` attribute"),
+        ));
+    }
+    Ok(first)
+}
+
+/// Catches subevent codes that can't be a byte where they are written, rather than in the
+/// generated comparison.
+fn check_u8_literal(expr: &Expr) -> Result<()> {
+    if let Expr::Lit(lit) = expr {
+        if let Lit::Int(int) = &lit.lit {
+            int.base10_parse::<u8>()?;
+        }
+    }
+    Ok(())
+}